@@ -0,0 +1,68 @@
+use crate::SimulatedDevice;
+use rand::{Rng, RngCore};
+use s_home_proto::{DeviceRequest, Response};
+use std::time::Duration;
+
+static SUPPORTED: [&str; 1] = ["GetContact"];
+// chance the door is opened or closed during a second
+static TOGGLE_PER_SEC: f64 = 0.05;
+
+// a contact sensor on a door somebody walks through now and then
+#[derive(Default)]
+pub struct DoorContact {
+    is_open: bool,
+}
+
+impl DoorContact {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SimulatedDevice for DoorContact {
+    fn device_type(&self) -> &'static str {
+        "DOOR"
+    }
+
+    fn supported(&self) -> &'static [&'static str] {
+        &SUPPORTED
+    }
+
+    fn handle(&mut self, req: DeviceRequest) -> Response {
+        match req {
+            DeviceRequest::GetContact => Response::Contact(self.is_open),
+            _ => Response::Err(format!("bad request: {:?}", req)),
+        }
+    }
+
+    fn tick(&mut self, elapsed: Duration, rng: &mut dyn RngCore) {
+        let chance = (TOGGLE_PER_SEC * elapsed.as_secs_f64()).min(1.0);
+        if rng.gen_bool(chance) {
+            self.is_open = !self.is_open;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::door::DoorContact;
+    use crate::SimulatedDevice;
+    use s_home_proto::{DeviceRequest, Response};
+    use std::time::Duration;
+
+    #[test]
+    fn test_toggles() {
+        let mut door = DoorContact::new();
+        let mut rng = rand::thread_rng();
+        assert_eq!(
+            door.handle(DeviceRequest::GetContact),
+            Response::Contact(false)
+        );
+        // long enough for a toggle to be certain
+        door.tick(Duration::from_secs(60), &mut rng);
+        assert_eq!(
+            door.handle(DeviceRequest::GetContact),
+            Response::Contact(true)
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use crate::SimulatedDevice;
+use rand::{Rng, RngCore};
+use s_home_proto::{DeviceRequest, Measurement, Metric, Response};
+use std::time::Duration;
+
+static SUPPORTED: [&str; 2] = ["GetHumidity", "GetMeasurements"];
+
+// relative humidity in percent
+pub struct HumiditySensor {
+    humidity: f32,
+}
+
+impl Default for HumiditySensor {
+    fn default() -> Self {
+        Self { humidity: 45.0 }
+    }
+}
+
+impl HumiditySensor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SimulatedDevice for HumiditySensor {
+    fn device_type(&self) -> &'static str {
+        "HUMI"
+    }
+
+    fn supported(&self) -> &'static [&'static str] {
+        &SUPPORTED
+    }
+
+    fn handle(&mut self, req: DeviceRequest) -> Response {
+        match req {
+            DeviceRequest::GetHumidity => Response::Humidity(self.humidity),
+            DeviceRequest::GetMeasurements => {
+                Response::Measurements(vec![Measurement::new(Metric::Humidity, self.humidity)])
+            }
+            _ => Response::Err(format!("bad request: {:?}", req)),
+        }
+    }
+
+    fn tick(&mut self, _elapsed: Duration, rng: &mut dyn RngCore) {
+        self.humidity = (self.humidity + rng.gen_range(-0.5..0.5)).clamp(0.0, 100.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::humidity::HumiditySensor;
+    use crate::SimulatedDevice;
+    use s_home_proto::{DeviceRequest, Response};
+    use std::time::Duration;
+
+    #[test]
+    fn test_stays_in_range() {
+        let mut sensor = HumiditySensor { humidity: 99.9 };
+        let mut rng = rand::thread_rng();
+        for _ in 0..1000 {
+            sensor.tick(Duration::from_secs(1), &mut rng);
+        }
+        let Response::Humidity(humidity) = sensor.handle(DeviceRequest::GetHumidity) else {
+            panic!("no humidity");
+        };
+        assert!((0.0..=100.0).contains(&humidity));
+    }
+}
@@ -0,0 +1,81 @@
+use crate::devices::switch;
+use crate::SimulatedDevice;
+use s_home_proto::{DeviceRequest, Response};
+
+static SUPPORTED: [&str; 4] = ["Status", "DeviceAction", "SetBrightness", "GetBrightness"];
+static MAX_BRIGHTNESS: u8 = 100;
+
+// a dimmable light; the brightness is kept while it is off
+pub struct Light {
+    is_on: bool,
+    brightness: u8,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            is_on: false,
+            brightness: MAX_BRIGHTNESS,
+        }
+    }
+}
+
+impl Light {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SimulatedDevice for Light {
+    fn device_type(&self) -> &'static str {
+        "LGHT"
+    }
+
+    fn supported(&self) -> &'static [&'static str] {
+        &SUPPORTED
+    }
+
+    fn handle(&mut self, req: DeviceRequest) -> Response {
+        match req {
+            DeviceRequest::Status => Response::Status(self.is_on),
+            DeviceRequest::DeviceAction { method } => switch(&mut self.is_on, method),
+            DeviceRequest::SetBrightness { level } if level > MAX_BRIGHTNESS => {
+                Response::Err(format!("brightness {} is over {}", level, MAX_BRIGHTNESS))
+            }
+            DeviceRequest::SetBrightness { level } => {
+                self.brightness = level;
+                Response::Ok
+            }
+            DeviceRequest::GetBrightness => Response::Brightness(self.brightness),
+            _ => Response::Err(format!("bad request: {:?}", req)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::light::Light;
+    use crate::SimulatedDevice;
+    use s_home_proto::{DeviceRequest, Response};
+
+    #[test]
+    fn test_brightness() {
+        let mut light = Light::new();
+        assert_eq!(
+            light.handle(DeviceRequest::SetBrightness { level: 30 }),
+            Response::Ok
+        );
+        assert_eq!(
+            light.handle(DeviceRequest::GetBrightness),
+            Response::Brightness(30)
+        );
+        assert!(matches!(
+            light.handle(DeviceRequest::SetBrightness { level: 101 }),
+            Response::Err(_)
+        ));
+        assert_eq!(
+            light.handle(DeviceRequest::GetBrightness),
+            Response::Brightness(30)
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{SimError, SimulatedDevice};
+use s_home_proto::{DeviceAction, Response};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+pub mod door;
+pub mod humidity;
+pub mod light;
+pub mod plug;
+pub mod socket;
+pub mod thermometer;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceKind {
+    Socket,
+    Thermometer,
+    Humidity,
+    Light,
+    Door,
+    Plug,
+}
+
+impl DeviceKind {
+    pub const ALL: [DeviceKind; 6] = [
+        Self::Socket,
+        Self::Thermometer,
+        Self::Humidity,
+        Self::Light,
+        Self::Door,
+        Self::Plug,
+    ];
+
+    pub fn new_device(&self) -> Box<dyn SimulatedDevice> {
+        match self {
+            Self::Socket => Box::new(socket::Socket::new()),
+            Self::Thermometer => Box::new(thermometer::Thermometer::new()),
+            Self::Humidity => Box::new(humidity::HumiditySensor::new()),
+            Self::Light => Box::new(light::Light::new()),
+            Self::Door => Box::new(door::DoorContact::new()),
+            Self::Plug => Box::new(plug::SmartPlug::new()),
+        }
+    }
+}
+
+impl Display for DeviceKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Socket => write!(f, "socket"),
+            Self::Thermometer => write!(f, "thermometer"),
+            Self::Humidity => write!(f, "humidity"),
+            Self::Light => write!(f, "light"),
+            Self::Door => write!(f, "door"),
+            Self::Plug => write!(f, "plug"),
+        }
+    }
+}
+
+impl FromStr for DeviceKind {
+    type Err = SimError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.to_string() == s.to_lowercase())
+            .ok_or_else(|| SimError::UnknownKind(s.to_string()))
+    }
+}
+
+// `DeviceAction` for devices with a power switch
+fn switch(is_on: &mut bool, method: DeviceAction) -> Response {
+    match method {
+        DeviceAction::TurnOn => *is_on = true,
+        DeviceAction::TurnOff => *is_on = false,
+        // simulated devices never trip
+        DeviceAction::Reset => {}
+    }
+    Response::Ok
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::DeviceKind;
+
+    #[test]
+    fn test_kind_from_str() {
+        for kind in DeviceKind::ALL {
+            assert_eq!(kind.to_string().parse::<DeviceKind>().unwrap(), kind);
+        }
+        assert_eq!("Light".parse::<DeviceKind>().unwrap(), DeviceKind::Light);
+        assert!("toaster".parse::<DeviceKind>().is_err());
+    }
+}
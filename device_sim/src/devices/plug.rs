@@ -0,0 +1,91 @@
+use crate::devices::switch;
+use crate::SimulatedDevice;
+use rand::{Rng, RngCore};
+use s_home_proto::{DeviceRequest, Response};
+use std::time::Duration;
+
+static SUPPORTED: [&str; 4] = ["Status", "DeviceAction", "GetPower", "GetEnergy"];
+// what the appliance behind the plug draws, W
+static LOAD: f32 = 60.0;
+
+// a switchable plug metering what the appliance behind it draws
+pub struct SmartPlug {
+    is_on: bool,
+    load: f32,
+    // kWh
+    energy: f32,
+}
+
+impl Default for SmartPlug {
+    fn default() -> Self {
+        Self {
+            is_on: false,
+            load: LOAD,
+            energy: 0.0,
+        }
+    }
+}
+
+impl SmartPlug {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn power(&self) -> f32 {
+        if self.is_on {
+            self.load
+        } else {
+            0.0
+        }
+    }
+}
+
+impl SimulatedDevice for SmartPlug {
+    fn device_type(&self) -> &'static str {
+        "PLUG"
+    }
+
+    fn supported(&self) -> &'static [&'static str] {
+        &SUPPORTED
+    }
+
+    fn handle(&mut self, req: DeviceRequest) -> Response {
+        match req {
+            DeviceRequest::Status => Response::Status(self.is_on),
+            DeviceRequest::DeviceAction { method } => switch(&mut self.is_on, method),
+            DeviceRequest::GetPower => Response::Power(self.power()),
+            DeviceRequest::GetEnergy => Response::Energy(self.energy),
+            _ => Response::Err(format!("bad request: {:?}", req)),
+        }
+    }
+
+    fn tick(&mut self, elapsed: Duration, rng: &mut dyn RngCore) {
+        // metered at the draw of the tick that ends, before it moves
+        self.energy += self.power() * elapsed.as_secs_f32() / 3_600_000.0;
+        self.load = (self.load + rng.gen_range(-2.0..2.0)).max(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::plug::SmartPlug;
+    use crate::SimulatedDevice;
+    use s_home_proto::{DeviceAction, DeviceRequest, Response};
+    use std::time::Duration;
+
+    #[test]
+    fn test_meters_only_while_on() {
+        let mut plug = SmartPlug::new();
+        let mut rng = rand::thread_rng();
+        plug.tick(Duration::from_secs(3600), &mut rng);
+        assert_eq!(plug.handle(DeviceRequest::GetPower), Response::Power(0.0));
+        assert_eq!(plug.handle(DeviceRequest::GetEnergy), Response::Energy(0.0));
+
+        plug.handle(DeviceRequest::DeviceAction {
+            method: DeviceAction::TurnOn,
+        });
+        plug.load = 1000.0;
+        plug.tick(Duration::from_secs(3600), &mut rng);
+        assert_eq!(plug.handle(DeviceRequest::GetEnergy), Response::Energy(1.0));
+    }
+}
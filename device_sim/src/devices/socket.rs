@@ -0,0 +1,51 @@
+use crate::devices::switch;
+use crate::SimulatedDevice;
+use rand::{Rng, RngCore};
+use s_home_proto::{DeviceRequest, Response};
+use std::time::Duration;
+
+static SUPPORTED: [&str; 3] = ["Status", "DeviceAction", "GetPower"];
+
+// the same device `power_socket_server` serves
+pub struct Socket {
+    is_on: bool,
+    power: f32,
+}
+
+impl Default for Socket {
+    fn default() -> Self {
+        Self {
+            is_on: false,
+            power: 20.0,
+        }
+    }
+}
+
+impl Socket {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SimulatedDevice for Socket {
+    fn device_type(&self) -> &'static str {
+        "PSOC"
+    }
+
+    fn supported(&self) -> &'static [&'static str] {
+        &SUPPORTED
+    }
+
+    fn handle(&mut self, req: DeviceRequest) -> Response {
+        match req {
+            DeviceRequest::Status => Response::Status(self.is_on),
+            DeviceRequest::DeviceAction { method } => switch(&mut self.is_on, method),
+            DeviceRequest::GetPower => Response::Power(self.power),
+            _ => Response::Err(format!("bad request: {:?}", req)),
+        }
+    }
+
+    fn tick(&mut self, _elapsed: Duration, rng: &mut dyn RngCore) {
+        self.power += rng.gen_range(-2.0f32..2.0f32);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::devices::switch;
+use crate::SimulatedDevice;
+use rand::{Rng, RngCore};
+use s_home_proto::{DeviceRequest, Measurement, Metric, Response};
+use std::time::Duration;
+
+static SUPPORTED: [&str; 4] = [
+    "Status",
+    "DeviceAction",
+    "GetTemperature",
+    "GetMeasurements",
+];
+
+// the same device `thermometer_server` serves, wandering around instead of only warming up
+pub struct Thermometer {
+    is_on: bool,
+    temp: f32,
+}
+
+impl Default for Thermometer {
+    fn default() -> Self {
+        Self {
+            is_on: true,
+            temp: 20.0,
+        }
+    }
+}
+
+impl Thermometer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SimulatedDevice for Thermometer {
+    fn device_type(&self) -> &'static str {
+        "THRM"
+    }
+
+    fn supported(&self) -> &'static [&'static str] {
+        &SUPPORTED
+    }
+
+    fn handle(&mut self, req: DeviceRequest) -> Response {
+        match req {
+            DeviceRequest::Status => Response::Status(self.is_on),
+            DeviceRequest::DeviceAction { method } => switch(&mut self.is_on, method),
+            DeviceRequest::GetTemperature => Response::Temperature(self.temp),
+            DeviceRequest::GetMeasurements => {
+                Response::Measurements(vec![Measurement::new(Metric::Temperature, self.temp)])
+            }
+            _ => Response::Err(format!("bad request: {:?}", req)),
+        }
+    }
+
+    fn tick(&mut self, _elapsed: Duration, rng: &mut dyn RngCore) {
+        self.temp = (self.temp + rng.gen_range(-0.05..0.05)).clamp(-40.0, 60.0);
+    }
+}
@@ -0,0 +1,257 @@
+use rand::RngCore;
+use s_home_proto::auth::Keyring;
+use s_home_proto::fleet::{FleetConfig, Route};
+use s_home_proto::metrics::ServerMetrics;
+use s_home_proto::server::{serve_tcp, serve_udp, Endpoint, PlainTcp, Shutdown};
+use s_home_proto::{DeviceDescription, DeviceRequest, Response};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::info;
+
+pub mod devices;
+
+use devices::DeviceKind;
+
+// how its metrics are labelled
+pub static SERVER_NAME: &str = "device_sim";
+static FIRMWARE: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+// how often the simulated readings move
+static TICK: Duration = Duration::from_secs(1);
+
+// one simulated device; the serve loops take care of decoding, auth, Ping and Describe
+pub trait SimulatedDevice: Send {
+    // what `Describe` reports, e.g. "PSOC"
+    fn device_type(&self) -> &'static str;
+
+    // `DeviceRequest::kind` of every request `handle` answers
+    fn supported(&self) -> &'static [&'static str];
+
+    fn handle(&mut self, req: DeviceRequest) -> Response;
+
+    // moves the simulated readings along, `elapsed` after the last tick
+    fn tick(&mut self, _elapsed: Duration, _rng: &mut dyn RngCore) {}
+}
+
+// simulated devices hosted by one process, of any kind, addressed by device id
+pub type Fleet = s_home_proto::fleet::Fleet<Box<dyn SimulatedDevice>>;
+pub type DeviceRoute = Route<Box<dyn SimulatedDevice>>;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SimError {
+    #[error("unknown device kind '{0}'")]
+    UnknownKind(String),
+    #[error("unknown transport '{0}'")]
+    UnknownTransport(String),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Transport {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+impl Display for Transport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp => write!(f, "tcp"),
+            Self::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+impl FromStr for Transport {
+    type Err = SimError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "tcp" => Ok(Self::Tcp),
+            "udp" => Ok(Self::Udp),
+            _ => Err(SimError::UnknownTransport(s.to_string())),
+        }
+    }
+}
+
+pub fn describe(device: &dyn SimulatedDevice) -> DeviceDescription {
+    let mut supported = vec!["Ping", "Describe"];
+    supported.extend(device.supported());
+    DeviceDescription::new(device.device_type(), FIRMWARE, &supported)
+}
+
+pub fn respond(device: &mut dyn SimulatedDevice, req: DeviceRequest) -> Response {
+    match req {
+        DeviceRequest::Ping => Response::Pong,
+        DeviceRequest::Describe => Response::Description(describe(device)),
+        req if device.supported().contains(&req.kind()) => device.handle(req),
+        _ => Response::Err(format!("bad request: {:?}", req)),
+    }
+}
+
+// what a listener answers requests for the devices of `route` with
+pub fn endpoint(
+    route: DeviceRoute,
+    metrics: Arc<ServerMetrics>,
+    keyring: Arc<Keyring>,
+) -> Endpoint<Box<dyn SimulatedDevice>> {
+    Endpoint::new(
+        route,
+        |req, state| respond(state.lock().unwrap().as_mut(), req),
+        metrics,
+        keyring,
+    )
+}
+
+// every device of the config, of its own kind or of `default` when it names none
+pub fn build_fleet(config: &FleetConfig, default: DeviceKind) -> Result<Fleet, SimError> {
+    let mut fleet = Fleet::new();
+    for device in config.devices.iter() {
+        let kind = match &device.kind {
+            Some(kind) => kind.parse()?,
+            None => default,
+        };
+        fleet.insert(&device.id, Arc::new(Mutex::new(kind.new_device())));
+    }
+    Ok(fleet)
+}
+
+// serves a single device on `addr` until the listener fails
+pub async fn serve(
+    addr: &str,
+    device: Box<dyn SimulatedDevice>,
+    transport: Transport,
+    metrics: Arc<ServerMetrics>,
+    keyring: Keyring,
+) -> std::io::Result<()> {
+    let route = Route::single(Arc::new(Mutex::new(device)));
+    let drift = spawn_drift(Arc::clone(route.fleet()));
+    let result = listen(addr, route, transport, metrics, Arc::new(keyring)).await;
+    drift.abort();
+    result
+}
+
+// serves every device of the fleet on the ports the config lists
+pub async fn serve_fleet(
+    fleet: Fleet,
+    config: &FleetConfig,
+    transport: Transport,
+    metrics: Arc<ServerMetrics>,
+    keyring: Keyring,
+) -> std::io::Result<()> {
+    let fleet = Arc::new(fleet);
+    let keyring = Arc::new(keyring);
+    let routes = config.routes(&fleet);
+    info!(devices = fleet.len(), %transport, "fleet up");
+
+    let drift = spawn_drift(Arc::clone(&fleet));
+    let mut servers = tokio::task::JoinSet::new();
+    for (route, addr) in routes {
+        let metrics = Arc::clone(&metrics);
+        let keyring = Arc::clone(&keyring);
+        servers.spawn(async move { listen(&addr, route, transport, metrics, keyring).await });
+    }
+    let mut result = Ok(());
+    // one listener failing takes the others down with it
+    if let Some(joined) = servers.join_next().await {
+        result = joined.map_err(std::io::Error::other).and_then(|r| r);
+    }
+    servers.abort_all();
+    drift.abort();
+    result
+}
+
+async fn listen(
+    addr: &str,
+    route: DeviceRoute,
+    transport: Transport,
+    metrics: Arc<ServerMetrics>,
+    keyring: Arc<Keyring>,
+) -> std::io::Result<()> {
+    let endpoint = endpoint(route, metrics, keyring);
+    match transport {
+        Transport::Tcp => {
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            serve_tcp(listener, endpoint, PlainTcp, Shutdown::new()).await
+        }
+        Transport::Udp => {
+            let socket = tokio::net::UdpSocket::bind(addr).await?;
+            serve_udp(socket, endpoint, Shutdown::new()).await
+        }
+    }
+}
+
+// ticks every hosted device
+pub fn spawn_drift(fleet: Arc<Fleet>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut rng = <rand::rngs::StdRng as rand::SeedableRng>::from_entropy();
+        let mut interval = tokio::time::interval(TICK);
+        // the first tick completes immediately
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            for device in fleet.states() {
+                device.lock().unwrap().tick(TICK, &mut rng);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::DeviceKind;
+    use crate::{build_fleet, respond, SimError, Transport};
+    use s_home_proto::fleet::FleetConfig;
+    use s_home_proto::{DeviceRequest, Response};
+
+    #[test]
+    fn test_respond() {
+        for kind in DeviceKind::ALL {
+            let mut device = kind.new_device();
+            assert_eq!(
+                respond(device.as_mut(), DeviceRequest::Ping),
+                Response::Pong
+            );
+            let Response::Description(description) =
+                respond(device.as_mut(), DeviceRequest::Describe)
+            else {
+                panic!("{} did not describe itself", kind);
+            };
+            assert_eq!(description.device_type, device.device_type());
+            assert!(description.supports("Ping"));
+            assert!(matches!(
+                respond(device.as_mut(), DeviceRequest::Exit),
+                Response::Err(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_build_fleet() {
+        let config = FleetConfig::parse(
+            "listen = 1\n[[device]]\nid = \"a\"\n[[device]]\nid = \"b\"\nkind = \"door\"",
+        )
+        .unwrap();
+        let fleet = build_fleet(&config, DeviceKind::Light).unwrap();
+        let types: Vec<_> = fleet
+            .states()
+            .map(|device| device.lock().unwrap().device_type())
+            .collect();
+        assert_eq!(types, vec!["LGHT", "DOOR"]);
+
+        let config =
+            FleetConfig::parse("listen = 1\n[[device]]\nid = \"a\"\nkind = \"toaster\"").unwrap();
+        assert_eq!(
+            build_fleet(&config, DeviceKind::Light).err(),
+            Some(SimError::UnknownKind("toaster".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_transport_from_str() {
+        assert_eq!("UDP".parse::<Transport>().unwrap(), Transport::Udp);
+        assert_eq!(Transport::Tcp.to_string().parse(), Ok(Transport::Tcp));
+        assert!("quic".parse::<Transport>().is_err());
+    }
+}
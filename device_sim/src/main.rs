@@ -0,0 +1,56 @@
+use device_sim::devices::DeviceKind;
+use device_sim::{build_fleet, serve, serve_fleet, Transport};
+use s_home_proto::auth::Keyring;
+use s_home_proto::discovery::{spawn_responder, DeviceAnnouncement};
+use s_home_proto::fleet::FleetConfig;
+use s_home_proto::metrics::{serve_server_metrics, ServerMetrics};
+use std::net::SocketAddr;
+
+#[tokio::main]
+async fn main() {
+    s_home_proto::logging::init();
+
+    // e.g. AUTH_KEYS=home:s3cret:control,ops:t0p:admin; without keys nothing is checked
+    let keyring = Keyring::from_spec(&std::env::var("AUTH_KEYS").unwrap_or_default()).unwrap();
+
+    let metrics = ServerMetrics::shared(device_sim::SERVER_NAME);
+    // the /metrics endpoint is off unless an address is given
+    if let Ok(addr) = std::env::var("METRICS_ADDR") {
+        serve_server_metrics(&addr, metrics.clone()).unwrap();
+    }
+
+    // e.g. SIM_KIND=light SIM_TRANSPORT=udp; a socket over TCP unless given
+    let kind: DeviceKind = std::env::var("SIM_KIND")
+        .map(|kind| kind.parse().unwrap())
+        .unwrap_or(DeviceKind::Socket);
+    let transport: Transport = std::env::var("SIM_TRANSPORT")
+        .map(|transport| transport.parse().unwrap())
+        .unwrap_or_default();
+
+    // a whole fleet in this one process, devices without a kind are of SIM_KIND
+    if let Ok(path) = std::env::var("FLEET_CONFIG") {
+        let config = FleetConfig::load(&path).unwrap();
+        let fleet = build_fleet(&config, kind).unwrap();
+        serve_fleet(fleet, &config, transport, metrics, keyring)
+            .await
+            .unwrap();
+        return;
+    }
+
+    let addr = std::env::var("SIM_ADDR").unwrap_or_else(|_| "127.0.0.1:1300".to_string());
+    let device = kind.new_device();
+
+    // discovery is off unless an address is given, e.g. 0.0.0.0:41234
+    if let Ok(discovery_addr) = std::env::var("DISCOVERY_ADDR") {
+        let name = std::env::var("DEVICE_NAME").unwrap_or_else(|_| kind.to_string());
+        let port = addr.parse::<SocketAddr>().unwrap().port();
+        let announcement = DeviceAnnouncement::new(device.device_type(), &name, port);
+        if let Err(err) = spawn_responder(&discovery_addr, announcement) {
+            tracing::warn!(%err, address = discovery_addr.as_str(), "discovery responder not started");
+        }
+    }
+
+    serve(&addr, device, transport, metrics, keyring)
+        .await
+        .unwrap();
+}
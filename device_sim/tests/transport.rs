@@ -0,0 +1,124 @@
+use device_sim::devices::DeviceKind;
+use device_sim::{build_fleet, endpoint, spawn_drift};
+use s_home_proto::auth::{Credentials, Keyring, Role};
+use s_home_proto::fleet::{FleetConfig, Route};
+use s_home_proto::server::{serve_tcp, serve_udp, PlainTcp, Shutdown};
+use s_home_proto::{AddressedRequest, DeviceRequest, Marshal, Response};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+
+static CONFIG: &str = r#"
+    listen = 1
+    [[device]]
+    id = "light"
+    kind = "light"
+    [[device]]
+    id = "plug"
+    kind = "plug"
+    [[device]]
+    id = "door"
+    kind = "door"
+"#;
+
+fn metrics() -> Arc<s_home_proto::metrics::ServerMetrics> {
+    s_home_proto::metrics::ServerMetrics::shared(device_sim::SERVER_NAME)
+}
+
+fn addressed(device: &str, request: DeviceRequest) -> Vec<u8> {
+    AddressedRequest {
+        device: device.to_string(),
+        request,
+    }
+    .marshal()
+    .unwrap()
+    .into_bytes()
+}
+
+async fn tcp_request(addr: &str, msg: &[u8]) -> Response {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(msg).await.unwrap();
+    let mut buf = vec![];
+    stream.read_to_end(&mut buf).await.unwrap();
+    Response::unmarshal(std::str::from_utf8(&buf).unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn test_tcp_fleet() {
+    let config = FleetConfig::parse(CONFIG).unwrap();
+    let fleet = Arc::new(build_fleet(&config, DeviceKind::Socket).unwrap());
+    spawn_drift(Arc::clone(&fleet));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let endpoint = endpoint(Route::shared(fleet), metrics(), Arc::new(Keyring::open()));
+    tokio::spawn(serve_tcp(listener, endpoint, PlainTcp, Shutdown::new()));
+
+    let set = addressed("light", DeviceRequest::SetBrightness { level: 25 });
+    assert_eq!(tcp_request(&addr, &set).await, Response::Ok);
+    let get = addressed("light", DeviceRequest::GetBrightness);
+    assert_eq!(tcp_request(&addr, &get).await, Response::Brightness(25));
+
+    let energy = addressed("plug", DeviceRequest::GetEnergy);
+    assert_eq!(tcp_request(&addr, &energy).await, Response::Energy(0.0));
+    let contact = addressed("door", DeviceRequest::GetContact);
+    assert!(matches!(
+        tcp_request(&addr, &contact).await,
+        Response::Contact(_)
+    ));
+
+    // each device answers only what its kind supports
+    let wrong_kind = addressed("door", DeviceRequest::GetBrightness);
+    assert!(matches!(
+        tcp_request(&addr, &wrong_kind).await,
+        Response::Err(_)
+    ));
+    let Response::Description(description) =
+        tcp_request(&addr, &addressed("plug", DeviceRequest::Describe)).await
+    else {
+        panic!("plug did not describe itself");
+    };
+    assert_eq!(description.device_type, "PLUG");
+    assert!(description.supports("GetEnergy"));
+}
+
+#[tokio::test]
+async fn test_udp_device() {
+    let keyring = Keyring::open().with_key("viewer", "v1ew", Role::ReadOnly);
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    let device = DeviceKind::Humidity.new_device();
+    let route = Route::single(Arc::new(std::sync::Mutex::new(device)));
+    let endpoint = endpoint(route, metrics(), Arc::new(keyring));
+    tokio::spawn(serve_udp(socket, endpoint, Shutdown::new()));
+
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    client.connect(addr).await.unwrap();
+    let request = |req: DeviceRequest| {
+        Credentials::new("viewer", "v1ew")
+            .sign(req)
+            .marshal()
+            .unwrap()
+    };
+    let mut buf = [0u8; 512];
+
+    let signed = request(DeviceRequest::GetHumidity);
+    client.send(signed.as_bytes()).await.unwrap();
+    let len = client.recv(&mut buf).await.unwrap();
+    let resp = Response::unmarshal(std::str::from_utf8(&buf[..len]).unwrap()).unwrap();
+    assert_eq!(resp, Response::Humidity(45.0));
+
+    // the very same datagram sent again is a replay
+    client.send(signed.as_bytes()).await.unwrap();
+    let len = client.recv(&mut buf).await.unwrap();
+    let resp = Response::unmarshal(std::str::from_utf8(&buf[..len]).unwrap()).unwrap();
+    assert!(matches!(resp, Response::Err(err) if err.contains("replayed")));
+
+    // unsigned requests are refused
+    client
+        .send(DeviceRequest::GetHumidity.marshal().unwrap().as_bytes())
+        .await
+        .unwrap();
+    let len = client.recv(&mut buf).await.unwrap();
+    let resp = Response::unmarshal(std::str::from_utf8(&buf[..len]).unwrap()).unwrap();
+    assert!(matches!(resp, Response::Err(err) if err.starts_with("denied")));
+}
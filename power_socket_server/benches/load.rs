@@ -0,0 +1,171 @@
+// load test comparing the async server with the old sequential one:
+// `cargo bench -p power_socket_server --bench load`, sized by BENCH_CLIENTS and BENCH_REQUESTS;
+// BENCH_SLOW clients pause between connecting and sending, like a flaky network would
+use power_socket_server::sequential::serve_sequential;
+use power_socket_server::{serve_async, State};
+use s_home_proto::auth::Keyring;
+use s_home_proto::metrics::ServerMetrics;
+use s_home_proto::{DeviceRequest, Marshal, Response};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+fn env_or(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+static SLOW_PAUSE: Duration = Duration::from_millis(20);
+
+async fn request(addr: SocketAddr, req: DeviceRequest) -> Result<Response, String> {
+    request_after(addr, req, Duration::ZERO).await
+}
+
+async fn request_after(
+    addr: SocketAddr,
+    req: DeviceRequest,
+    pause: Duration,
+) -> Result<Response, String> {
+    let mut stream = TcpStream::connect(addr).await.map_err(|e| e.to_string())?;
+    if !pause.is_zero() {
+        tokio::time::sleep(pause).await;
+    }
+    stream
+        .write_all(req.marshal().unwrap().as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut buf = String::new();
+    stream
+        .read_to_string(&mut buf)
+        .await
+        .map_err(|e| e.to_string())?;
+    Response::unmarshal(&buf).map_err(|e| e.to_string())
+}
+
+struct Report {
+    elapsed: Duration,
+    latencies: Vec<Duration>,
+    errors: usize,
+}
+
+impl Report {
+    fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let idx = ((self.latencies.len() - 1) as f64 * p).round() as usize;
+        self.latencies[idx]
+    }
+
+    fn print(&self, name: &str) {
+        let rps = self.latencies.len() as f64 / self.elapsed.as_secs_f64();
+        println!(
+            "{:<12} {:>10.0} req/s  p50 {:>9.2?}  p99 {:>9.2?}  p99.9 {:>9.2?}  max {:>9.2?}  errors {}",
+            name,
+            rps,
+            self.percentile(0.5),
+            self.percentile(0.99),
+            self.percentile(0.999),
+            self.latencies.last().copied().unwrap_or_default(),
+            self.errors
+        );
+    }
+}
+
+// every client sends its requests one after another, all clients at once;
+// only the fast clients are measured
+async fn load(addr: SocketAddr, clients: usize, slow: usize, requests: usize) -> Report {
+    let started = Instant::now();
+    let mut slow_tasks = tokio::task::JoinSet::new();
+    for _ in 0..slow {
+        slow_tasks.spawn(async move {
+            for _ in 0..requests {
+                let _ = request_after(addr, DeviceRequest::GetPower, SLOW_PAUSE).await;
+            }
+        });
+    }
+    let mut tasks = tokio::task::JoinSet::new();
+    for _ in 0..clients {
+        tasks.spawn(async move {
+            let mut latencies = Vec::with_capacity(requests);
+            let mut errors = 0;
+            for _ in 0..requests {
+                let sent = Instant::now();
+                match request(addr, DeviceRequest::GetPower).await {
+                    Ok(Response::Power(_)) => latencies.push(sent.elapsed()),
+                    _ => errors += 1,
+                }
+            }
+            (latencies, errors)
+        });
+    }
+
+    let mut report = Report {
+        elapsed: Duration::ZERO,
+        latencies: Vec::with_capacity(clients * requests),
+        errors: 0,
+    };
+    while let Some(result) = tasks.join_next().await {
+        let (latencies, errors) = result.unwrap();
+        report.latencies.extend(latencies);
+        report.errors += errors;
+    }
+    report.elapsed = started.elapsed();
+    report.latencies.sort();
+    slow_tasks.join_all().await;
+    report
+}
+
+fn main() {
+    let clients = env_or("BENCH_CLIENTS", 256);
+    let requests = env_or("BENCH_REQUESTS", 20);
+    let slow = env_or("BENCH_SLOW", 4);
+    println!(
+        "{} clients (+{} slow) x {} requests",
+        clients, slow, requests
+    );
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        serve_sequential(
+            State::new(),
+            listener,
+            ServerMetrics::shared(power_socket_server::SERVER_NAME),
+            Keyring::open(),
+        )
+        .unwrap()
+    });
+    runtime
+        .block_on(load(addr, clients, slow, requests))
+        .print("sequential");
+    runtime
+        .block_on(request(addr, DeviceRequest::Exit))
+        .unwrap();
+    server.join().unwrap();
+
+    // the server gets a runtime of its own so the clients do not steal its threads
+    let server_runtime = tokio::runtime::Runtime::new().unwrap();
+    let listener = server_runtime
+        .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = server_runtime.spawn(serve_async(
+        State::new(),
+        listener,
+        ServerMetrics::shared(power_socket_server::SERVER_NAME),
+        Keyring::open(),
+    ));
+    runtime
+        .block_on(load(addr, clients, slow, requests))
+        .print("async");
+    runtime
+        .block_on(request(addr, DeviceRequest::Exit))
+        .unwrap();
+    server_runtime.block_on(server).unwrap().unwrap();
+}
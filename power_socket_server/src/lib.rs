@@ -1,117 +1,277 @@
 use rand::Rng;
-use s_home_proto::{DeviceAction, DeviceRequest, Marshal, Response};
-use std::error::Error;
-use std::io::{Read, Write};
-use std::net::{TcpListener, TcpStream};
+use s_home_proto::auth::Keyring;
+use s_home_proto::discovery::{spawn_responder, DeviceAnnouncement};
+use s_home_proto::fleet::{FleetDevice, Route};
+use s_home_proto::metrics::ServerMetrics;
+use s_home_proto::server::{Endpoint, PlainTcp, Upgrade};
+use s_home_proto::{
+    DeviceAction, DeviceDescription, DeviceRequest, Overload, PowerFault, Response,
+};
 use std::sync::{Arc, Mutex};
-use std::thread::{sleep, spawn};
-use std::time::Duration;
+use std::thread::JoinHandle;
+use tracing::{debug, info, warn};
 
-static SERVER_PREFIX: &str = "[SERVER]";
+pub mod sequential;
+mod server;
+#[cfg(feature = "tls")]
+mod tls;
+
+pub use server::{serve_async, serve_fleet};
+#[cfg(feature = "tls")]
+pub use server::{serve_fleet_tls, serve_tls_async};
+
+static DEVICE_TYPE: &str = "PSOC";
+// how its metrics are labelled
+pub static SERVER_NAME: &str = "power_socket";
+static FIRMWARE: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+static SUPPORTED: [&str; 6] = [
+    "Ping",
+    "Status",
+    "DeviceAction",
+    "GetPower",
+    "Exit",
+    "Describe",
+];
+
+// answers discovery probes on `discovery_addr` on behalf of the device served on `port`
+pub fn announce(name: &str, port: u16, discovery_addr: &str) -> std::io::Result<JoinHandle<()>> {
+    spawn_responder(
+        discovery_addr,
+        DeviceAnnouncement::new(DEVICE_TYPE, name, port),
+    )
+}
+
+// mains voltage, for telling the current from the power drawn
+static VOLTAGE: f32 = 230.0;
+
+// what a socket may draw before it trips off, nothing by default
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PowerLimits {
+    // watts
+    pub max_power: Option<f32>,
+    // amperes
+    pub max_current: Option<f32>,
+}
+
+impl PowerLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_power(mut self, watts: f32) -> Self {
+        self.max_power = Some(watts);
+        self
+    }
+
+    pub fn with_max_current(mut self, amperes: f32) -> Self {
+        self.max_current = Some(amperes);
+        self
+    }
+
+    // MAX_POWER and MAX_CURRENT, unset ones are no limit; a bad one is an error rather than
+    // quietly no protection
+    pub fn from_env() -> Result<Self, String> {
+        let limit = |var: &str| match std::env::var(var) {
+            Ok(value) => parse_limit(var, &value).map(Some),
+            Err(_) => Ok(None),
+        };
+        Ok(Self {
+            max_power: limit("MAX_POWER")?,
+            max_current: limit("MAX_CURRENT")?,
+        })
+    }
+
+    // the limits of a fleet device, those it does not set taken from `self`
+    pub fn for_device(&self, device: &FleetDevice) -> Self {
+        Self {
+            max_power: device.max_power.or(self.max_power),
+            max_current: device.max_current.or(self.max_current),
+        }
+    }
+
+    fn check(&self, watts: f32) -> Option<PowerFault> {
+        if let Some(limit) = self.max_power.filter(|limit| watts > *limit) {
+            return Some(PowerFault::new(Overload::Power, watts, limit));
+        }
+        let amperes = watts / VOLTAGE;
+        self.max_current
+            .filter(|limit| amperes > *limit)
+            .map(|limit| PowerFault::new(Overload::Current, amperes, limit))
+    }
+}
+
+// a limit named `var`, which has to be a positive number
+fn parse_limit(var: &str, value: &str) -> Result<f32, String> {
+    match value.trim().parse::<f32>() {
+        Ok(limit) if limit.is_finite() && limit > 0.0 => Ok(limit),
+        _ => Err(format!(
+            "bad {} '{}': expected a positive number",
+            var, value
+        )),
+    }
+}
 
 pub struct State {
     is_on: bool,
     power: f32,
+    limits: PowerLimits,
+    // latched until reset
+    fault: Option<PowerFault>,
 }
 
+// sockets hosted by one process, addressed by device id
+pub type Fleet = s_home_proto::fleet::Fleet<State>;
+
 impl State {
     pub fn new() -> Arc<Mutex<Self>> {
+        Self::with_limits(PowerLimits::default())
+    }
+
+    pub fn with_limits(limits: PowerLimits) -> Arc<Mutex<Self>> {
         Arc::new(Mutex::new(Self {
             is_on: false,
             power: 20.0,
+            limits,
+            fault: None,
         }))
     }
-}
 
-pub fn serve(state: Arc<Mutex<State>>, port: u32) -> Result<(), Box<dyn std::error::Error>> {
-    let address = format!("0.0.0.0:{}", port);
-    let listener = TcpListener::bind(address.as_str()).unwrap();
-    println!(
-        "{} listening on {} with log level {}",
-        SERVER_PREFIX,
-        address,
-        log::max_level()
-    );
-
-    let state_clone = Arc::clone(&state);
-    spawn(move || {
-        let mut rng = rand::thread_rng();
-        let mut heartbeat = 0u32;
-        loop {
-            if heartbeat == 0 || heartbeat % 10 == 0 {
-                println!("{} heartbeat #{}", SERVER_PREFIX, heartbeat)
-            }
-            heartbeat += 1;
+    pub fn fault(&self) -> Option<PowerFault> {
+        self.fault
+    }
 
-            state_clone.lock().unwrap().power += rng.gen_range(-2.0f32..2.0f32);
-            sleep(Duration::from_secs(1));
+    // switches off for good when the load is over a limit, only a reset switches it on again
+    fn trip_if_overloaded(&mut self) {
+        if !self.is_on {
+            return;
         }
-    });
-
-    for stream in listener.incoming() {
-        let stream = stream.unwrap();
-        let state = Arc::clone(&state);
+        if let Some(fault) = self.limits.check(self.power) {
+            warn!(%fault, "tripped");
+            self.is_on = false;
+            self.fault = Some(fault);
+        }
+    }
 
-        let result = handle_request(stream, state);
-        match result {
-            Ok(true) => break,
-            Ok(false) => {
-                println!("{} request handled", SERVER_PREFIX)
+    // switching off always works, switching on only once a fault is reset
+    fn switch(&mut self, method: DeviceAction) -> Response {
+        match method {
+            DeviceAction::TurnOn => {
+                if self.fault.is_none() {
+                    self.is_on = true;
+                    self.trip_if_overloaded();
+                }
+                if let Some(fault) = self.fault {
+                    return Response::Fault(fault);
+                }
+            }
+            DeviceAction::TurnOff => self.is_on = false,
+            DeviceAction::Reset => {
+                if let Some(fault) = self.fault.take() {
+                    info!(%fault, "fault reset");
+                }
             }
-            Err(err) => return Err(err),
         }
+        Response::Ok
     }
-    Ok(())
 }
 
-type HandleResult = Result<bool, Box<dyn Error>>;
+// the blocking entry points run the async server on a runtime of their own
+pub fn serve(state: Arc<Mutex<State>>, port: u32) -> Result<(), Box<dyn std::error::Error>> {
+    serve_with_metrics(state, port, ServerMetrics::shared(SERVER_NAME))
+}
 
-fn handle_request(mut stream: TcpStream, state: Arc<Mutex<State>>) -> HandleResult {
-    let mut buf = [0u8; 200];
-    let message_len = stream.read(&mut buf).unwrap();
-    println!("{} buf: {}", SERVER_PREFIX, message_len);
+pub fn serve_with_metrics(
+    state: Arc<Mutex<State>>,
+    port: u32,
+    metrics: Arc<ServerMetrics>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    serve_with_auth(state, port, metrics, Keyring::open())
+}
 
-    let mut exit_flag = false;
+// like `serve_with_metrics`, but requests must be signed by a key from the keyring
+pub fn serve_with_auth(
+    state: Arc<Mutex<State>>,
+    port: u32,
+    metrics: Arc<ServerMetrics>,
+    keyring: Keyring,
+) -> Result<(), Box<dyn std::error::Error>> {
+    block_on(state, port, metrics, keyring, PlainTcp)
+}
 
-    let bs = String::from_utf8_lossy(&buf[..message_len]).to_string();
-    println!("{} message: {}", SERVER_PREFIX, bs.as_str());
+// like `serve_with_auth`, but clients must connect over TLS
+#[cfg(feature = "tls")]
+pub fn serve_with_tls(
+    state: Arc<Mutex<State>>,
+    port: u32,
+    metrics: Arc<ServerMetrics>,
+    keyring: Keyring,
+    tls: &s_home_proto::tls::TlsConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tls = tls::Tls(tls.server_config()?);
+    block_on(state, port, metrics, keyring, tls)
+}
 
-    let req: DeviceRequest;
-    req = match DeviceRequest::unmarshal(bs.as_str()) {
-        Ok(r) => r,
-        Err(err) => return Err(format!("err unmarshalling device request {}: {}", bs, err).into()),
-    };
-    println!("{} request: {:?}", SERVER_PREFIX, &req);
+fn block_on(
+    state: Arc<Mutex<State>>,
+    port: u32,
+    metrics: Arc<ServerMetrics>,
+    keyring: Keyring,
+    upgrade: impl Upgrade,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
+        server::run_single(state, listener, metrics, Arc::new(keyring), upgrade).await
+    })?;
+    Ok(())
+}
 
+// the simulated load drifts a little every second
+fn fluctuate(state: &Mutex<State>, rng: &mut impl Rng, heartbeat: u32) {
+    if heartbeat.is_multiple_of(10) {
+        debug!(heartbeat, "heartbeat")
+    }
     let mut state = state.lock().unwrap();
+    state.power += rng.gen_range(-2.0f32..2.0f32);
+    state.trip_if_overloaded();
+}
+
+// what a listener answers requests for the sockets of `route` with; the state is locked only
+// while it is read or changed
+fn endpoint(
+    route: Route<State>,
+    metrics: Arc<ServerMetrics>,
+    keyring: Arc<Keyring>,
+) -> Endpoint<State> {
+    Endpoint::new(route, respond, metrics, keyring)
+}
 
-    let resp = match req {
+fn respond(req: DeviceRequest, state: &Mutex<State>) -> Response {
+    match req {
         DeviceRequest::Ping => Response::Pong,
-        DeviceRequest::Status => Response::Status(state.is_on),
-        DeviceRequest::GetPower => Response::Power(state.power),
-        DeviceRequest::DeviceAction { method } => match method {
-            DeviceAction::TurnOn => {
-                state.is_on = true;
-                Response::Ok
+        // a tripped socket answers with its fault until it is reset
+        DeviceRequest::Status => {
+            let state = state.lock().unwrap();
+            match state.fault {
+                Some(fault) => Response::Fault(fault),
+                None => Response::Status(state.is_on),
             }
-            DeviceAction::TurnOff => {
-                state.is_on = false;
-                Response::Ok
+        }
+        DeviceRequest::GetPower => {
+            let state = state.lock().unwrap();
+            match state.fault {
+                Some(fault) => Response::Fault(fault),
+                None => Response::Power(state.power),
             }
-        },
-        // exiting totally
-        DeviceRequest::Exit => {
-            exit_flag = true;
-            Response::Ok
         }
+        DeviceRequest::DeviceAction { method } => state.lock().unwrap().switch(method),
+        DeviceRequest::Describe => {
+            Response::Description(DeviceDescription::new(DEVICE_TYPE, FIRMWARE, &SUPPORTED))
+        }
+        // the caller stops serving once the answer is sent
+        DeviceRequest::Exit => Response::Ok,
         _ => Response::Err(format!("bad request: {:?}", req)),
-    };
-    stream
-        .write_all(resp.marshal().unwrap().as_bytes())
-        .unwrap();
-    // exit totally
-    if exit_flag {
-        return Ok(true);
     }
-    Ok(false)
 }
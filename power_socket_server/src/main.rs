@@ -1,8 +1,67 @@
-use power_socket_server::{serve, State};
+use power_socket_server::{announce, serve_async, serve_fleet, Fleet, PowerLimits, State};
+use s_home_proto::auth::Keyring;
+use s_home_proto::fleet::FleetConfig;
+use s_home_proto::metrics::{serve_server_metrics, ServerMetrics};
+use std::process::exit;
 
-fn main() {
-    simple_logger::init_with_level(log::Level::Debug).unwrap();
+static USAGE: &str = "usage: [MAX_POWER=<watts>] [MAX_CURRENT=<amperes>] [FLEET_CONFIG=<path>] \
+[AUTH_KEYS=<keys>] [METRICS_ADDR=<addr>] [DISCOVERY_ADDR=<addr>] power_socket_server";
 
-    let state = State::new();
-    serve(state, 1234).unwrap();
+#[tokio::main]
+async fn main() {
+    s_home_proto::logging::init();
+
+    // e.g. AUTH_KEYS=home:s3cret:control,ops:t0p:admin; without keys nothing is checked
+    let keyring = Keyring::from_spec(&std::env::var("AUTH_KEYS").unwrap_or_default()).unwrap();
+
+    let metrics = ServerMetrics::shared(power_socket_server::SERVER_NAME);
+    // the /metrics endpoint is off unless an address is given
+    if let Ok(addr) = std::env::var("METRICS_ADDR") {
+        serve_server_metrics(&addr, metrics.clone()).unwrap();
+    }
+
+    // e.g. MAX_POWER=2000 or MAX_CURRENT=10; sockets trip off above them until reset
+    let limits = PowerLimits::from_env().unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        exit(2)
+    });
+
+    // a whole fleet of sockets in this one process, see `FleetConfig` for the format
+    if let Ok(path) = std::env::var("FLEET_CONFIG") {
+        let config = FleetConfig::load(&path).unwrap();
+        let fleet = Fleet::from_config_with(&config, |device| {
+            State::with_limits(limits.for_device(device))
+        });
+        #[cfg(feature = "tls")]
+        if let Some(tls) = s_home_proto::tls::TlsConfig::from_env("TLS") {
+            power_socket_server::serve_fleet_tls(fleet, &config, metrics, keyring, &tls)
+                .await
+                .unwrap();
+            return;
+        }
+        serve_fleet(fleet, &config, metrics, keyring).await.unwrap();
+        return;
+    }
+
+    // discovery is off unless an address is given, e.g. 0.0.0.0:41234
+    if let Ok(addr) = std::env::var("DISCOVERY_ADDR") {
+        let name = std::env::var("DEVICE_NAME").unwrap_or_else(|_| "power socket".to_string());
+        if let Err(err) = announce(&name, 1234, &addr) {
+            tracing::warn!(%err, address = addr.as_str(), "discovery responder not started");
+        }
+    }
+
+    let state = State::with_limits(limits);
+    let listener = tokio::net::TcpListener::bind("0.0.0.0:1234").await.unwrap();
+    // TLS_CERT and TLS_KEY switch to TLS, TLS_CA additionally requires client certificates
+    #[cfg(feature = "tls")]
+    if let Some(tls) = s_home_proto::tls::TlsConfig::from_env("TLS") {
+        power_socket_server::serve_tls_async(state, listener, metrics, keyring, &tls)
+            .await
+            .unwrap();
+        return;
+    }
+    serve_async(state, listener, metrics, keyring)
+        .await
+        .unwrap();
 }
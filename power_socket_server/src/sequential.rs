@@ -0,0 +1,64 @@
+// the original one-connection-at-a-time server, kept to compare against in `benches/load.rs`
+use crate::{endpoint, fluctuate, State};
+use s_home_proto::auth::Keyring;
+use s_home_proto::fleet::Route;
+use s_home_proto::metrics::ServerMetrics;
+use s_home_proto::server::Endpoint;
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+use tracing::{info, info_span, trace, warn};
+
+pub fn serve_sequential(
+    state: Arc<Mutex<State>>,
+    listener: TcpListener,
+    metrics: Arc<ServerMetrics>,
+    keyring: Keyring,
+) -> Result<(), Box<dyn Error>> {
+    info!(address = %listener.local_addr()?, "listening");
+
+    let state_clone = Arc::clone(&state);
+    spawn(move || {
+        let mut rng = rand::thread_rng();
+        for heartbeat in 0u32.. {
+            fluctuate(&state_clone, &mut rng, heartbeat);
+            sleep(Duration::from_secs(1));
+        }
+    });
+
+    let endpoint = endpoint(Route::single(state), metrics, Arc::new(keyring));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let peer = stream.peer_addr()?;
+        let _span = info_span!("connection", %peer).entered();
+        match handle_request(stream, &endpoint) {
+            Ok(true) => {
+                info!("exit requested, shutting down");
+                break;
+            }
+            Ok(false) => trace!("request handled"),
+            Err(err) => {
+                warn!(%err, "request failed");
+                return Err(err);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn handle_request(
+    mut stream: TcpStream,
+    endpoint: &Endpoint<State>,
+) -> Result<bool, Box<dyn Error>> {
+    let mut buf = [0u8; 512];
+    let message_len = stream.read(&mut buf)?;
+    let handled = endpoint
+        .handle_message(&buf[..message_len])
+        .map_err(|err| err as Box<dyn Error>)?;
+    stream.write_all(&handled.response)?;
+    stream.shutdown(Shutdown::Write)?;
+    Ok(handled.exit)
+}
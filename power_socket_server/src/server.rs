@@ -0,0 +1,122 @@
+use crate::{endpoint, fluctuate, Fleet, State};
+use s_home_proto::auth::Keyring;
+use s_home_proto::fleet::{FleetConfig, Route};
+use s_home_proto::metrics::ServerMetrics;
+use s_home_proto::server::{serve_tcp, PlainTcp, Shutdown, Upgrade};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tracing::info;
+
+// serves every connection on a task of its own until an `Exit` request comes in
+pub async fn serve_async(
+    state: Arc<Mutex<State>>,
+    listener: TcpListener,
+    metrics: Arc<ServerMetrics>,
+    keyring: Keyring,
+) -> std::io::Result<()> {
+    run_single(state, listener, metrics, Arc::new(keyring), PlainTcp).await
+}
+
+#[cfg(feature = "tls")]
+pub async fn serve_tls_async(
+    state: Arc<Mutex<State>>,
+    listener: TcpListener,
+    metrics: Arc<ServerMetrics>,
+    keyring: Keyring,
+    tls: &s_home_proto::tls::TlsConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tls = crate::tls::Tls(tls.server_config()?);
+    run_single(state, listener, metrics, Arc::new(keyring), tls).await?;
+    Ok(())
+}
+
+// serves every device of the fleet on the ports the config lists, until any of them gets `Exit`
+pub async fn serve_fleet(
+    fleet: Fleet,
+    config: &FleetConfig,
+    metrics: Arc<ServerMetrics>,
+    keyring: Keyring,
+) -> std::io::Result<()> {
+    run_fleet(fleet, config, metrics, Arc::new(keyring), PlainTcp).await
+}
+
+#[cfg(feature = "tls")]
+pub async fn serve_fleet_tls(
+    fleet: Fleet,
+    config: &FleetConfig,
+    metrics: Arc<ServerMetrics>,
+    keyring: Keyring,
+    tls: &s_home_proto::tls::TlsConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tls = crate::tls::Tls(tls.server_config()?);
+    run_fleet(fleet, config, metrics, Arc::new(keyring), tls).await?;
+    Ok(())
+}
+
+pub(crate) async fn run_single(
+    state: Arc<Mutex<State>>,
+    listener: TcpListener,
+    metrics: Arc<ServerMetrics>,
+    keyring: Arc<Keyring>,
+    upgrade: impl Upgrade,
+) -> std::io::Result<()> {
+    let route = Route::single(state);
+    let drift = spawn_drift(Arc::clone(route.fleet()));
+    let endpoint = endpoint(route, metrics, keyring);
+    let result = serve_tcp(listener, endpoint, upgrade, Shutdown::new()).await;
+    drift.abort();
+    result
+}
+
+async fn run_fleet(
+    fleet: Fleet,
+    config: &FleetConfig,
+    metrics: Arc<ServerMetrics>,
+    keyring: Arc<Keyring>,
+    upgrade: impl Upgrade,
+) -> std::io::Result<()> {
+    let fleet = Arc::new(fleet);
+    let shutdown = Shutdown::new();
+    let mut servers = tokio::task::JoinSet::new();
+    for (route, addr) in config.routes(&fleet) {
+        let listener = TcpListener::bind(&addr).await?;
+        servers.spawn(serve_tcp(
+            listener,
+            endpoint(route, Arc::clone(&metrics), Arc::clone(&keyring)),
+            upgrade.clone(),
+            shutdown.clone(),
+        ));
+    }
+    info!(
+        devices = fleet.ids().count(),
+        listeners = servers.len(),
+        "fleet up"
+    );
+
+    let drift = spawn_drift(fleet);
+    let mut result = Ok(());
+    while let Some(joined) = servers.join_next().await {
+        if let Err(err) = joined.map_err(std::io::Error::other).and_then(|r| r) {
+            // one listener failing takes the others down with it
+            shutdown.trigger();
+            result = Err(err);
+        }
+    }
+    drift.abort();
+    result
+}
+
+// the simulated load of every hosted socket drifts a little every second
+fn spawn_drift(fleet: Arc<Fleet>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut rng = <rand::rngs::StdRng as rand::SeedableRng>::from_entropy();
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        for heartbeat in 0u32.. {
+            interval.tick().await;
+            for state in fleet.states() {
+                fluctuate(state, &mut rng, heartbeat);
+            }
+        }
+    })
+}
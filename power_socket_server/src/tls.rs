@@ -0,0 +1,27 @@
+use s_home_proto::server::Upgrade;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::server::TlsStream;
+use tracing::debug;
+
+// connections that must complete a TLS handshake before they are read from
+#[derive(Clone)]
+pub(crate) struct Tls(pub(crate) Arc<ServerConfig>);
+
+impl Upgrade for Tls {
+    type Stream = TlsStream<TcpStream>;
+
+    async fn upgrade(&self, stream: TcpStream) -> std::io::Result<Self::Stream> {
+        let stream = tokio_rustls::TlsAcceptor::from(Arc::clone(&self.0))
+            .accept(stream)
+            .await?;
+        let (_, conn) = stream.get_ref();
+        debug!(
+            client_cert = conn.peer_certificates().is_some(),
+            version = ?conn.protocol_version(),
+            "tls established"
+        );
+        Ok(stream)
+    }
+}
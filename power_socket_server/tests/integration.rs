@@ -1,5 +1,8 @@
-use power_socket_server::{serve, State};
-use s_home_proto::{DeviceRequest, Marshal, Response};
+use power_socket_server::sequential::serve_sequential;
+use power_socket_server::{serve, serve_async, serve_with_metrics, PowerLimits, State};
+use s_home_proto::auth::Keyring;
+use s_home_proto::metrics::{serve_server_metrics, ServerMetrics};
+use s_home_proto::{DeviceAction, DeviceRequest, Marshal, Overload, Response};
 use std::io::{Read, Write};
 use std::thread;
 use std::time::Duration;
@@ -30,3 +33,172 @@ fn test_request() {
     let resp = Response::unmarshal(buf.as_str()).unwrap();
     assert_eq!(resp, Response::Pong)
 }
+
+#[test]
+fn test_metrics() {
+    let state = State::new();
+    let metrics = ServerMetrics::shared(power_socket_server::SERVER_NAME);
+    let server_metrics = metrics.clone();
+    thread::spawn(move || serve_with_metrics(state, 1241, server_metrics).unwrap());
+    serve_server_metrics("127.0.0.1:19241", metrics.clone()).unwrap();
+    thread::sleep(Duration::from_secs(1));
+
+    for req in [DeviceRequest::Ping, DeviceRequest::GetTemperature] {
+        let mut stream = std::net::TcpStream::connect("127.0.0.1:1241").unwrap();
+        stream.write_all(req.marshal().unwrap().as_bytes()).unwrap();
+        let mut buf = String::new();
+        stream.read_to_string(&mut buf).unwrap();
+    }
+    assert_eq!(metrics.requests("Ping"), 1);
+    assert_eq!(metrics.requests("GetTemperature"), 1);
+    assert_eq!(metrics.errors(), 1);
+
+    let mut stream = std::net::TcpStream::connect("127.0.0.1:19241").unwrap();
+    stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+    let mut buf = String::new();
+    stream.read_to_string(&mut buf).unwrap();
+    assert!(buf.starts_with("HTTP/1.1 200 OK"));
+    assert!(buf.contains("device_requests_total{server=\"power_socket\",kind=\"Ping\"} 1"));
+}
+
+async fn request(addr: std::net::SocketAddr, req: DeviceRequest) -> Response {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(req.marshal().unwrap().as_bytes())
+        .await
+        .unwrap();
+    let mut buf = String::new();
+    stream.read_to_string(&mut buf).await.unwrap();
+    Response::unmarshal(&buf).unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_concurrent_connections() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(serve_async(
+        State::new(),
+        listener,
+        ServerMetrics::shared(power_socket_server::SERVER_NAME),
+        Keyring::open(),
+    ));
+
+    // a client that connects and says nothing does not hold up the others
+    let _silent = tokio::net::TcpStream::connect(addr).await.unwrap();
+
+    let mut clients = tokio::task::JoinSet::new();
+    for _ in 0..500 {
+        clients.spawn(request(addr, DeviceRequest::Ping));
+    }
+    while let Some(resp) = clients.join_next().await {
+        assert_eq!(resp.unwrap(), Response::Pong);
+    }
+
+    assert_eq!(request(addr, DeviceRequest::Exit).await, Response::Ok);
+    tokio::time::timeout(Duration::from_secs(1), server)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+#[test]
+fn test_sequential() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        serve_sequential(
+            State::new(),
+            listener,
+            ServerMetrics::shared(power_socket_server::SERVER_NAME),
+            Keyring::open(),
+        )
+        .unwrap()
+    });
+
+    for (req, expected) in [
+        (DeviceRequest::Ping, Response::Pong),
+        (DeviceRequest::Exit, Response::Ok),
+    ] {
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        stream.write_all(req.marshal().unwrap().as_bytes()).unwrap();
+        let mut buf = String::new();
+        stream.read_to_string(&mut buf).unwrap();
+        assert_eq!(Response::unmarshal(&buf).unwrap(), expected);
+    }
+    server.join().unwrap();
+}
+
+#[test]
+fn test_limits_from_env() {
+    std::env::set_var("MAX_POWER", "2000");
+    std::env::remove_var("MAX_CURRENT");
+    assert_eq!(
+        PowerLimits::from_env(),
+        Ok(PowerLimits::new().with_max_power(2000.0))
+    );
+    // a typo must not turn the protection off
+    for bad in ["2kW", "0", "-5", "inf", "NaN", ""] {
+        std::env::set_var("MAX_POWER", bad);
+        assert_eq!(
+            PowerLimits::from_env(),
+            Err(format!(
+                "bad MAX_POWER '{}': expected a positive number",
+                bad
+            ))
+        );
+    }
+    std::env::remove_var("MAX_POWER");
+    std::env::set_var("MAX_CURRENT", "ten");
+    assert!(PowerLimits::from_env().unwrap_err().contains("MAX_CURRENT"));
+    std::env::remove_var("MAX_CURRENT");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_overload_trips() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    // the simulated load starts at 20 W, way over this
+    let state = State::with_limits(PowerLimits::new().with_max_current(0.01));
+    let server = tokio::spawn(serve_async(
+        state.clone(),
+        listener,
+        ServerMetrics::shared(power_socket_server::SERVER_NAME),
+        Keyring::open(),
+    ));
+    let action = |method| DeviceRequest::DeviceAction { method };
+
+    let Response::Fault(fault) = request(addr, action(DeviceAction::TurnOn)).await else {
+        panic!("fault expected")
+    };
+    assert_eq!(fault.overload, Overload::Current);
+    assert_eq!(fault.limit, 0.01);
+    // latched: it stays tripped and will not switch on
+    assert_eq!(
+        request(addr, DeviceRequest::Status).await,
+        Response::Fault(fault)
+    );
+    assert_eq!(
+        request(addr, action(DeviceAction::TurnOn)).await,
+        Response::Fault(fault)
+    );
+    assert_eq!(
+        request(addr, action(DeviceAction::TurnOff)).await,
+        Response::Ok
+    );
+
+    assert_eq!(
+        request(addr, action(DeviceAction::Reset)).await,
+        Response::Ok
+    );
+    assert!(state.lock().unwrap().fault().is_none());
+    assert_eq!(
+        request(addr, DeviceRequest::Status).await,
+        Response::Status(false)
+    );
+
+    assert_eq!(request(addr, DeviceRequest::Exit).await, Response::Ok);
+    server.await.unwrap().unwrap();
+}
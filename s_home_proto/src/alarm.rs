@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum AlarmState {
+    Raised,
+    // still active, but somebody is on it
+    Acknowledged,
+    Cleared,
+}
+
+impl Display for AlarmState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Raised => write!(f, "RAISED"),
+            Self::Acknowledged => write!(f, "ACKNOWLEDGED"),
+            Self::Cleared => write!(f, "CLEARED"),
+        }
+    }
+}
+
+// an alarm as reported to home clients, e.g. "kitchen/thermometer/temperature/min"
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AlarmStatus {
+    pub id: String,
+    pub room: String,
+    pub device: String,
+    pub metric: String,
+    pub state: AlarmState,
+    pub message: String,
+    // the reading which raised it, none for stale data
+    pub value: Option<f32>,
+    // unix milliseconds
+    pub raised_at: i64,
+    pub changed_at: i64,
+}
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+// what an area of a home is, from the top down: home → floor → zone → room
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum AreaKind {
+    // the whole home, the root every path starts from
+    Home,
+    Floor,
+    // e.g. a wing of a floor
+    Zone,
+}
+
+impl Display for AreaKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Home => write!(f, "home"),
+            Self::Floor => write!(f, "floor"),
+            Self::Zone => write!(f, "zone"),
+        }
+    }
+}
+
+impl FromStr for AreaKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "home" => Ok(Self::Home),
+            "floor" => Ok(Self::Floor),
+            "zone" => Ok(Self::Zone),
+            _ => Err(format!("unknown area kind '{}'", s)),
+        }
+    }
+}
+
+// the rooms and devices below an area, e.g. "floor1/east", and what they draw together
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AreaSummary {
+    // empty for the home itself
+    pub path: String,
+    pub kind: AreaKind,
+    // every room below, however deep
+    pub rooms: u32,
+    pub devices: u32,
+    // devices in the ERROR condition, tripped ones included
+    pub faulty: u32,
+    // watts
+    pub power: f32,
+    // kWh, from the devices metering it
+    pub energy: f32,
+    // the areas right below
+    pub areas: Vec<AreaSummary>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::area::AreaKind;
+
+    #[test]
+    fn test_area_kind() {
+        for kind in [AreaKind::Home, AreaKind::Floor, AreaKind::Zone] {
+            assert_eq!(kind.to_string().parse::<AreaKind>(), Ok(kind));
+        }
+        assert_eq!("FLOOR".parse::<AreaKind>(), Ok(AreaKind::Floor));
+        assert!("wing".parse::<AreaKind>().is_err());
+        assert!(AreaKind::Floor < AreaKind::Zone);
+    }
+}
@@ -0,0 +1,539 @@
+use crate::{AddressedRequest, Codec, CodecError, DeviceRequest, Marshal};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// how far a signed request's timestamp may be from the server clock; within it the nonces
+// seen are remembered, so no request is served twice
+pub const MAX_CLOCK_SKEW_SECS: u64 = 30;
+
+// ordered, a key with a higher role may do everything a lower one may
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Role {
+    ReadOnly,
+    Control,
+    Admin,
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ReadOnly => write!(f, "read-only"),
+            Self::Control => write!(f, "control"),
+            Self::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = AuthError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "read-only" | "readonly" | "read" => Ok(Self::ReadOnly),
+            "control" => Ok(Self::Control),
+            "admin" => Ok(Self::Admin),
+            _ => Err(AuthError::BadConfig(format!("unknown role '{}'", s))),
+        }
+    }
+}
+
+impl DeviceRequest {
+    pub fn required_role(&self) -> Role {
+        match self {
+            Self::DeviceAction { .. } | Self::SetBrightness { .. } => Role::Control,
+            Self::Exit => Role::Admin,
+            _ => Role::ReadOnly,
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum AuthError {
+    #[error("authentication required")]
+    Unauthenticated,
+    #[error("unknown key '{0}'")]
+    UnknownKey(String),
+    #[error("bad signature")]
+    BadSignature,
+    #[error("request expired or from the future: timestamp {0}")]
+    Expired(u64),
+    #[error("request replayed: nonce {0}")]
+    Replayed(String),
+    #[error("'{request}' needs the {required} role, key has {role}")]
+    Forbidden {
+        request: String,
+        required: Role,
+        role: Role,
+    },
+    #[error("bad auth config: {0}")]
+    BadConfig(String),
+    #[error("{0}")]
+    Decode(String),
+}
+
+impl From<CodecError> for AuthError {
+    fn from(err: CodecError) -> Self {
+        Self::Decode(err.to_string())
+    }
+}
+
+// a device request with the key it was signed with, sent instead of the bare request
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct SignedRequest {
+    pub key_id: String,
+    // unix seconds
+    pub timestamp: u64,
+    // random, a server serves a nonce only once
+    pub nonce: String,
+    // hex encoded HMAC-SHA256 over key id, timestamp, nonce and the request
+    pub mac: String,
+    // for servers hosting several devices, covered by the mac when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    pub request: DeviceRequest,
+}
+
+impl Marshal for SignedRequest {}
+
+fn new_mac(
+    secret: &str,
+    key_id: &str,
+    timestamp: u64,
+    nonce: &str,
+    device: Option<&str>,
+    request: &DeviceRequest,
+) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("hmac takes any key size");
+    mac.update(key_id.as_bytes());
+    mac.update(b"\n");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b"\n");
+    mac.update(nonce.as_bytes());
+    mac.update(b"\n");
+    if let Some(device) = device {
+        mac.update(device.as_bytes());
+        mac.update(b"\n");
+    }
+    // the JSON form, so the signature does not depend on the codec used on the wire
+    mac.update(request.marshal().unwrap().as_bytes());
+    mac
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+// what a client signs its requests with
+#[derive(Clone, PartialEq)]
+pub struct Credentials {
+    key_id: String,
+    secret: String,
+}
+
+impl Credentials {
+    pub fn new(key_id: &str, secret: &str) -> Self {
+        Self {
+            key_id: key_id.to_string(),
+            secret: secret.to_string(),
+        }
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    pub fn sign(&self, request: DeviceRequest) -> SignedRequest {
+        self.sign_at(request, unix_now())
+    }
+
+    pub fn sign_at(&self, request: DeviceRequest, timestamp: u64) -> SignedRequest {
+        self.sign_for_at(None, request, timestamp)
+    }
+
+    // signs a request addressed to one of the devices a server hosts
+    pub fn sign_for(&self, device: Option<&str>, request: DeviceRequest) -> SignedRequest {
+        self.sign_for_at(device, request, unix_now())
+    }
+
+    pub fn sign_for_at(
+        &self,
+        device: Option<&str>,
+        request: DeviceRequest,
+        timestamp: u64,
+    ) -> SignedRequest {
+        let nonce = hex::encode(rand::random::<[u8; 16]>());
+        let mac = new_mac(
+            &self.secret,
+            &self.key_id,
+            timestamp,
+            &nonce,
+            device,
+            &request,
+        );
+        SignedRequest {
+            key_id: self.key_id.clone(),
+            timestamp,
+            nonce,
+            mac: hex::encode(mac.finalize().into_bytes()),
+            device: device.map(str::to_string),
+            request,
+        }
+    }
+}
+
+// keeps secrets out of logs
+impl Debug for Credentials {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("key_id", &self.key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+struct Key {
+    secret: String,
+    role: Role,
+}
+
+// server side list of accepted keys; without any keys every request is let through as before
+#[derive(Default)]
+pub struct Keyring {
+    keys: HashMap<String, Key>,
+    // (key id, nonce) -> timestamp of the requests served within the clock skew
+    seen: Mutex<HashMap<(String, String), u64>>,
+}
+
+impl Keyring {
+    pub fn open() -> Self {
+        Self::default()
+    }
+
+    pub fn with_key(mut self, key_id: &str, secret: &str, role: Role) -> Self {
+        self.keys.insert(
+            key_id.to_string(),
+            Key {
+                secret: secret.to_string(),
+                role,
+            },
+        );
+        self
+    }
+
+    // comma separated `key_id:secret:role`, e.g. `home:s3cret:control,ops:t0p:admin`
+    pub fn from_spec(spec: &str) -> Result<Self, AuthError> {
+        let mut keyring = Self::open();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let parts: Vec<&str> = entry.split(':').collect();
+            match parts.as_slice() {
+                [key_id, secret, role] if !key_id.is_empty() && !secret.is_empty() => {
+                    keyring = keyring.with_key(key_id, secret, role.parse()?)
+                }
+                _ => {
+                    return Err(AuthError::BadConfig(format!(
+                        "expected key_id:secret:role, got '{}'",
+                        entry
+                    )))
+                }
+            }
+        }
+        Ok(keyring)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    // decodes a signed or bare request and checks it may be served
+    pub fn accept(&self, codec: Codec, bytes: &[u8]) -> Result<DeviceRequest, AuthError> {
+        self.accept_at(codec, bytes, unix_now())
+    }
+
+    pub fn accept_at(
+        &self,
+        codec: Codec,
+        bytes: &[u8],
+        now: u64,
+    ) -> Result<DeviceRequest, AuthError> {
+        self.accept_addressed_at(codec, bytes, now)
+            .map(|(_, request)| request)
+    }
+
+    // like `accept`, also returning the device id the request is addressed to, if any
+    pub fn accept_addressed(
+        &self,
+        codec: Codec,
+        bytes: &[u8],
+    ) -> Result<(Option<String>, DeviceRequest), AuthError> {
+        self.accept_addressed_at(codec, bytes, unix_now())
+    }
+
+    pub fn accept_addressed_at(
+        &self,
+        codec: Codec,
+        bytes: &[u8],
+        now: u64,
+    ) -> Result<(Option<String>, DeviceRequest), AuthError> {
+        if let Ok(signed) = SignedRequest::decode(codec, bytes) {
+            self.check(&signed, now)?;
+            return Ok((signed.device, signed.request));
+        }
+        let (device, request) = match AddressedRequest::decode(codec, bytes) {
+            Ok(addressed) => (Some(addressed.device), addressed.request),
+            Err(_) => (None, DeviceRequest::decode(codec, bytes)?),
+        };
+        if self.is_open() {
+            Ok((device, request))
+        } else {
+            Err(AuthError::Unauthenticated)
+        }
+    }
+
+    pub fn verify(&self, signed: SignedRequest, now: u64) -> Result<DeviceRequest, AuthError> {
+        self.check(&signed, now)?;
+        Ok(signed.request)
+    }
+
+    fn check(&self, signed: &SignedRequest, now: u64) -> Result<(), AuthError> {
+        if self.is_open() {
+            return Ok(());
+        }
+        let key = self
+            .keys
+            .get(&signed.key_id)
+            .ok_or_else(|| AuthError::UnknownKey(signed.key_id.clone()))?;
+        let tag = hex::decode(&signed.mac).map_err(|_| AuthError::BadSignature)?;
+        new_mac(
+            &key.secret,
+            &signed.key_id,
+            signed.timestamp,
+            &signed.nonce,
+            signed.device.as_deref(),
+            &signed.request,
+        )
+        .verify_slice(&tag)
+        .map_err(|_| AuthError::BadSignature)?;
+        if now.abs_diff(signed.timestamp) > MAX_CLOCK_SKEW_SECS {
+            return Err(AuthError::Expired(signed.timestamp));
+        }
+        self.check_nonce(signed, now)?;
+
+        let required = signed.request.required_role();
+        if key.role < required {
+            return Err(AuthError::Forbidden {
+                request: signed.request.kind().to_string(),
+                required,
+                role: key.role,
+            });
+        }
+        Ok(())
+    }
+
+    // past the clock skew a request is refused as expired, its nonce need not be kept
+    fn check_nonce(&self, signed: &SignedRequest, now: u64) -> Result<(), AuthError> {
+        let mut seen = self.seen.lock().unwrap();
+        seen.retain(|_, timestamp| now.abs_diff(*timestamp) <= MAX_CLOCK_SKEW_SECS);
+        let key = (signed.key_id.to_string(), signed.nonce.to_string());
+        if seen.contains_key(&key) {
+            return Err(AuthError::Replayed(signed.nonce.to_string()));
+        }
+        seen.insert(key, signed.timestamp);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::{AuthError, Credentials, Keyring, Role, MAX_CLOCK_SKEW_SECS};
+    use crate::{AddressedRequest, Codec, DeviceAction, DeviceRequest, Marshal};
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn keyring() -> Keyring {
+        Keyring::from_spec("viewer:v1ew:read-only, home:h0me:control,ops:0ps:admin").unwrap()
+    }
+
+    fn turn_on() -> DeviceRequest {
+        DeviceRequest::DeviceAction {
+            method: DeviceAction::TurnOn,
+        }
+    }
+
+    #[test]
+    fn test_roles() {
+        let keyring = keyring();
+        let home = Credentials::new("home", "h0me");
+        let viewer = Credentials::new("viewer", "v1ew");
+
+        assert_eq!(
+            keyring.verify(home.sign_at(turn_on(), NOW), NOW),
+            Ok(turn_on())
+        );
+        assert_eq!(
+            keyring.verify(viewer.sign_at(DeviceRequest::GetPower, NOW), NOW),
+            Ok(DeviceRequest::GetPower)
+        );
+        assert!(matches!(
+            keyring.verify(viewer.sign_at(turn_on(), NOW), NOW),
+            Err(AuthError::Forbidden {
+                required: Role::Control,
+                role: Role::ReadOnly,
+                ..
+            })
+        ));
+        assert!(matches!(
+            keyring.verify(home.sign_at(DeviceRequest::Exit, NOW), NOW),
+            Err(AuthError::Forbidden { .. })
+        ));
+        let ops = Credentials::new("ops", "0ps");
+        assert_eq!(
+            keyring.verify(ops.sign_at(DeviceRequest::Exit, NOW), NOW),
+            Ok(DeviceRequest::Exit)
+        );
+    }
+
+    #[test]
+    fn test_bad_signatures() {
+        let keyring = keyring();
+        let wrong_secret = Credentials::new("home", "guess");
+        assert_eq!(
+            keyring.verify(wrong_secret.sign_at(turn_on(), NOW), NOW),
+            Err(AuthError::BadSignature)
+        );
+
+        let home = Credentials::new("home", "h0me");
+        let mut tampered = home.sign_at(DeviceRequest::GetPower, NOW);
+        tampered.request = DeviceRequest::Exit;
+        assert_eq!(keyring.verify(tampered, NOW), Err(AuthError::BadSignature));
+
+        let stranger = Credentials::new("stranger", "h0me");
+        assert_eq!(
+            keyring.verify(stranger.sign_at(turn_on(), NOW), NOW),
+            Err(AuthError::UnknownKey("stranger".to_string()))
+        );
+
+        let stale = home.sign_at(turn_on(), NOW - 60);
+        assert_eq!(
+            keyring.verify(stale, NOW),
+            Err(AuthError::Expired(NOW - 60))
+        );
+    }
+
+    #[test]
+    fn test_accept() {
+        let home = Credentials::new("home", "h0me");
+        for codec in Codec::ALL {
+            let signed = home.sign_at(turn_on(), NOW).encode(codec).unwrap();
+            assert_eq!(keyring().accept_at(codec, &signed, NOW), Ok(turn_on()));
+
+            let bare = turn_on().encode(codec).unwrap();
+            assert_eq!(
+                keyring().accept_at(codec, &bare, NOW),
+                Err(AuthError::Unauthenticated)
+            );
+            // servers without keys keep accepting everything
+            assert_eq!(Keyring::open().accept_at(codec, &bare, NOW), Ok(turn_on()));
+            assert_eq!(
+                Keyring::open().accept_at(codec, &signed, NOW),
+                Ok(turn_on())
+            );
+        }
+        assert!(matches!(
+            keyring().accept_at(Codec::Json, b"{}", NOW),
+            Err(AuthError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn test_addressed() {
+        let home = Credentials::new("home", "h0me");
+        let signed = home
+            .sign_for_at(Some("socket-2"), turn_on(), NOW)
+            .encode(Codec::Cbor)
+            .unwrap();
+        assert_eq!(
+            keyring().accept_addressed_at(Codec::Cbor, &signed, NOW),
+            Ok((Some("socket-2".to_string()), turn_on()))
+        );
+
+        // the device id is signed too
+        let mut redirected = home.sign_for_at(Some("socket-2"), turn_on(), NOW);
+        redirected.device = Some("socket-3".to_string());
+        assert_eq!(
+            keyring().verify(redirected, NOW),
+            Err(AuthError::BadSignature)
+        );
+
+        let bare = AddressedRequest {
+            device: "socket-2".to_string(),
+            request: turn_on(),
+        }
+        .encode(Codec::Json)
+        .unwrap();
+        assert_eq!(
+            Keyring::open().accept_addressed_at(Codec::Json, &bare, NOW),
+            Ok((Some("socket-2".to_string()), turn_on()))
+        );
+        assert_eq!(
+            keyring().accept_addressed_at(Codec::Json, &bare, NOW),
+            Err(AuthError::Unauthenticated)
+        );
+    }
+
+    #[test]
+    fn test_replay() {
+        let keyring = keyring();
+        let ops = Credentials::new("ops", "0ps");
+        let exit = ops
+            .sign_at(DeviceRequest::Exit, NOW)
+            .encode(Codec::Json)
+            .unwrap();
+        assert_eq!(
+            keyring.accept_at(Codec::Json, &exit, NOW),
+            Ok(DeviceRequest::Exit)
+        );
+        assert!(matches!(
+            keyring.accept_at(Codec::Json, &exit, NOW + 1),
+            Err(AuthError::Replayed(_))
+        ));
+        // and once the nonce is forgotten the request has expired anyway
+        assert!(matches!(
+            keyring.accept_at(Codec::Json, &exit, NOW + MAX_CLOCK_SKEW_SECS + 1),
+            Err(AuthError::Expired(_))
+        ));
+
+        // the same request signed again is a new one
+        let again = ops
+            .sign_at(DeviceRequest::Exit, NOW)
+            .encode(Codec::Json)
+            .unwrap();
+        assert_ne!(exit, again);
+        assert!(keyring.accept_at(Codec::Json, &again, NOW).is_ok());
+    }
+
+    #[test]
+    fn test_from_spec() {
+        assert!(Keyring::from_spec("").unwrap().is_open());
+        assert!(Keyring::from_spec("home:secret").is_err());
+        assert!(Keyring::from_spec("home:secret:root").is_err());
+        assert!(Keyring::from_spec(":secret:admin").is_err());
+    }
+
+    #[test]
+    fn test_credentials_debug_hides_secret() {
+        let debug = format!("{:?}", Credentials::new("home", "h0me"));
+        assert!(debug.contains("home"));
+        assert!(!debug.contains("h0me"));
+    }
+}
@@ -0,0 +1,126 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use thiserror::Error;
+
+// wire encodings every message type can be sent with; all of them are self-describing maps,
+// so a peer can tell which one it got from the first byte, see `Codec::detect`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Codec {
+    #[default]
+    Json,
+    Cbor,
+    MsgPack,
+}
+
+#[derive(Error, Debug)]
+pub enum CodecError {
+    #[error("err encoding {0}: {1}")]
+    Encode(Codec, String),
+    #[error("err decoding {0}: {1}")]
+    Decode(Codec, String),
+    #[error("unknown codec '{0}'")]
+    Unknown(String),
+}
+
+impl Codec {
+    pub const ALL: [Codec; 3] = [Codec::Json, Codec::Cbor, Codec::MsgPack];
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        let result = match self {
+            Self::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Self::Cbor => {
+                let mut buf = vec![];
+                ciborium::into_writer(value, &mut buf)
+                    .map(|_| buf)
+                    .map_err(|e| e.to_string())
+            }
+            // named fields keep the tagged enums decodable
+            Self::MsgPack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+        };
+        result.map_err(|e| CodecError::Encode(*self, e))
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, CodecError> {
+        let result = match self {
+            Self::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            Self::Cbor => ciborium::from_reader(bytes).map_err(|e| e.to_string()),
+            Self::MsgPack => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+        };
+        result.map_err(|e| CodecError::Decode(*self, e))
+    }
+
+    // every message is a map: `{` for JSON, major type 5 for CBOR, fixmap/map16/map32 for MessagePack
+    pub fn detect(bytes: &[u8]) -> Option<Codec> {
+        let first = bytes.iter().find(|b| !b.is_ascii_whitespace())?;
+        match first {
+            b'{' => Some(Self::Json),
+            0xa0..=0xbf => Some(Self::Cbor),
+            0x80..=0x8f | 0xde | 0xdf => Some(Self::MsgPack),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Codec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Json => write!(f, "json"),
+            Self::Cbor => write!(f, "cbor"),
+            Self::MsgPack => write!(f, "msgpack"),
+        }
+    }
+}
+
+impl FromStr for Codec {
+    type Err = CodecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "cbor" => Ok(Self::Cbor),
+            "msgpack" | "messagepack" => Ok(Self::MsgPack),
+            _ => Err(CodecError::Unknown(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::Codec;
+
+    #[test]
+    fn test_detect() {
+        for codec in Codec::ALL {
+            let bytes = codec.encode(&crate::Response::Pong).unwrap();
+            assert_eq!(Codec::detect(&bytes), Some(codec));
+        }
+        assert_eq!(Codec::detect(b" \n{}"), Some(Codec::Json));
+        assert_eq!(Codec::detect(b"ping"), None);
+        assert_eq!(Codec::detect(b""), None);
+    }
+
+    #[test]
+    fn test_from_str() {
+        for codec in Codec::ALL {
+            assert_eq!(codec.to_string().parse::<Codec>().unwrap(), codec);
+        }
+        assert!("bincode".parse::<Codec>().is_err());
+    }
+
+    #[test]
+    fn test_compact() {
+        let json = Codec::Json
+            .encode(&crate::DeviceRequest::GetTemperature)
+            .unwrap();
+        let cbor = Codec::Cbor
+            .encode(&crate::DeviceRequest::GetTemperature)
+            .unwrap();
+        let msgpack = Codec::MsgPack
+            .encode(&crate::DeviceRequest::GetTemperature)
+            .unwrap();
+        assert!(cbor.len() < json.len());
+        assert!(msgpack.len() < json.len());
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{Marshal, PROTOCOL_VERSION};
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::thread::{spawn, JoinHandle};
+use tracing::{debug, info, warn};
+
+// where device servers listen for probes unless told otherwise
+pub const DISCOVERY_PORT: u16 = 41234;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct DeviceAnnouncement {
+    pub device_type: String,
+    pub name: String,
+    // the device port; the host is whatever address the announcement came from
+    pub port: u16,
+    pub protocol_version: u32,
+}
+
+impl DeviceAnnouncement {
+    pub fn new(device_type: &str, name: &str, port: u16) -> Self {
+        Self {
+            device_type: device_type.to_string(),
+            name: name.to_string(),
+            port,
+            protocol_version: PROTOCOL_VERSION,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(tag = "discovery", content = "value")]
+pub enum DiscoveryMessage {
+    Probe,
+    Announce(DeviceAnnouncement),
+}
+
+impl Marshal for DiscoveryMessage {}
+
+// every server on a host listens on the same port, so the port is shared; each of them gets
+// the broadcast probes, a probe sent to the host directly reaches only one
+fn bind_shared(addr: &str) -> std::io::Result<UdpSocket> {
+    let addr: SocketAddr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "no discovery address")
+    })?;
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&addr.into())?;
+    Ok(socket.into())
+}
+
+// answers every probe received on `addr` with the announcement, e.g. on `0.0.0.0:41234`
+pub fn spawn_responder(
+    addr: &str,
+    announcement: DeviceAnnouncement,
+) -> std::io::Result<JoinHandle<()>> {
+    let socket = bind_shared(addr)?;
+    info!(address = %socket.local_addr()?, name = announcement.name.as_str(), "discovery responder listening");
+    let reply = DiscoveryMessage::Announce(announcement).marshal()?;
+
+    Ok(spawn(move || {
+        let mut buf = [0u8; 512];
+        loop {
+            let (len, peer) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(err) => {
+                    warn!(%err, "err receiving a probe");
+                    continue;
+                }
+            };
+            let msg = String::from_utf8_lossy(&buf[..len]).to_string();
+            match DiscoveryMessage::unmarshal(&msg) {
+                Ok(DiscoveryMessage::Probe) => {
+                    debug!(%peer, "probe received");
+                    if let Err(err) = socket.send_to(reply.as_bytes(), peer) {
+                        warn!(%err, %peer, "err answering a probe")
+                    }
+                }
+                _ => debug!(%peer, "ignoring datagram"),
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::discovery::{spawn_responder, DeviceAnnouncement, DiscoveryMessage};
+    use crate::Marshal;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    #[test]
+    fn test_responder() {
+        let announcement = DeviceAnnouncement::new("PSOC", "kettle", 1234);
+        spawn_responder("127.0.0.1:41300", announcement.clone()).unwrap();
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let probe = DiscoveryMessage::Probe.marshal().unwrap();
+        socket.send_to(probe.as_bytes(), "127.0.0.1:41300").unwrap();
+
+        let mut buf = [0u8; 512];
+        let len = socket.recv(&mut buf).unwrap();
+        let msg = DiscoveryMessage::unmarshal(std::str::from_utf8(&buf[..len]).unwrap()).unwrap();
+        assert_eq!(msg, DiscoveryMessage::Announce(announcement));
+    }
+
+    #[test]
+    fn test_shared_port() {
+        for name in ["kettle", "toaster"] {
+            let announcement = DeviceAnnouncement::new("PSOC", name, 1234);
+            spawn_responder("127.0.0.1:41301", announcement).unwrap();
+        }
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let probe = DiscoveryMessage::Probe.marshal().unwrap();
+        socket.send_to(probe.as_bytes(), "127.0.0.1:41301").unwrap();
+
+        let mut buf = [0u8; 512];
+        let len = socket.recv(&mut buf).unwrap();
+        let msg = DiscoveryMessage::unmarshal(std::str::from_utf8(&buf[..len]).unwrap()).unwrap();
+        assert!(matches!(msg, DiscoveryMessage::Announce(_)));
+    }
+}
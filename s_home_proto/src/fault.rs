@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overload {
+    // watts
+    Power,
+    // amperes
+    Current,
+}
+
+// why a power socket cut its output, latched until the socket is reset
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct PowerFault {
+    pub overload: Overload,
+    // what was measured when it tripped, in the unit of the overload
+    pub value: f32,
+    pub limit: f32,
+}
+
+impl PowerFault {
+    pub fn new(overload: Overload, value: f32, limit: f32) -> Self {
+        Self {
+            overload,
+            value,
+            limit,
+        }
+    }
+}
+
+impl Display for PowerFault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.overload {
+            Overload::Power => write!(
+                f,
+                "overload: {:.1} W over the {} W limit",
+                self.value, self.limit
+            ),
+            Overload::Current => write!(
+                f,
+                "overcurrent: {:.2} A over the {} A limit",
+                self.value, self.limit
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fault::{Overload, PowerFault};
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            PowerFault::new(Overload::Power, 2104.25, 2000.0).to_string(),
+            "overload: 2104.2 W over the 2000 W limit"
+        );
+        assert_eq!(
+            PowerFault::new(Overload::Current, 10.5, 10.0).to_string(),
+            "overcurrent: 10.50 A over the 10 A limit"
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+// the id a lone device answers to
+pub static DEFAULT_DEVICE: &str = "default";
+
+#[derive(Error, Debug)]
+pub enum FleetError {
+    #[error("err reading fleet config: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("err parsing fleet config: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("bad fleet config: {0}")]
+    Invalid(String),
+}
+
+// the devices one server process hosts, e.g.
+//
+//   listen = 1234            # requests carrying a device id
+//   [[device]]
+//   id = "kitchen-socket"
+//   port = 1301              # optional, a port of its own
+//   kind = "socket"          # optional, for servers simulating several kinds of devices
+//   max_power = 2000.0       # optional, watts a socket trips off above
+//   max_current = 10.0       # optional, amperes a socket trips off above
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FleetConfig {
+    #[serde(default = "default_host")]
+    pub host: String,
+    pub listen: Option<u16>,
+    #[serde(default, rename = "device")]
+    pub devices: Vec<FleetDevice>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FleetDevice {
+    pub id: String,
+    pub port: Option<u16>,
+    pub kind: Option<String>,
+    // power sockets only
+    pub max_power: Option<f32>,
+    pub max_current: Option<f32>,
+}
+
+fn default_host() -> String {
+    "0.0.0.0".to_string()
+}
+
+impl FleetConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, FleetError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(s: &str) -> Result<Self, FleetError> {
+        let config: Self = toml::from_str(s)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), FleetError> {
+        if self.devices.is_empty() {
+            return Err(FleetError::Invalid("no devices".to_string()));
+        }
+        let mut ids = HashSet::new();
+        let mut ports: HashSet<u16> = self.listen.into_iter().collect();
+        for device in self.devices.iter() {
+            if device.id.is_empty() {
+                return Err(FleetError::Invalid("empty device id".to_string()));
+            }
+            if !ids.insert(device.id.as_str()) {
+                return Err(FleetError::Invalid(format!(
+                    "device '{}' listed twice",
+                    device.id
+                )));
+            }
+            match device.port {
+                Some(port) if !ports.insert(port) => {
+                    return Err(FleetError::Invalid(format!("port {} used twice", port)))
+                }
+                // nothing would reach it
+                None if self.listen.is_none() => {
+                    return Err(FleetError::Invalid(format!(
+                        "device '{}' has no port and there is no shared listen port",
+                        device.id
+                    )))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn listen_addr(&self) -> Option<String> {
+        self.listen.map(|port| self.addr(port))
+    }
+
+    pub fn addr(&self, port: u16) -> String {
+        format!("{}:{}", self.host, port)
+    }
+
+    // every address to listen on and what it serves: the shared port, then the devices' own
+    pub fn routes<S>(&self, fleet: &Arc<Fleet<S>>) -> Vec<(Route<S>, String)> {
+        let mut routes = vec![];
+        if let Some(addr) = self.listen_addr() {
+            routes.push((Route::shared(Arc::clone(fleet)), addr));
+        }
+        for device in self.devices.iter() {
+            if let Some(port) = device.port {
+                routes.push((
+                    Route::device(Arc::clone(fleet), &device.id),
+                    self.addr(port),
+                ));
+            }
+        }
+        routes
+    }
+}
+
+// independent devices hosted by one process, each with a state of its own
+pub struct Fleet<S> {
+    devices: BTreeMap<String, Arc<Mutex<S>>>,
+}
+
+impl<S> Default for Fleet<S> {
+    fn default() -> Self {
+        Self {
+            devices: BTreeMap::new(),
+        }
+    }
+}
+
+impl<S> Fleet<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_config(config: &FleetConfig, new_state: impl Fn() -> Arc<Mutex<S>>) -> Self {
+        Self::from_config_with(config, |_| new_state())
+    }
+
+    // like `from_config`, for states set up from what the config says about their device
+    pub fn from_config_with(
+        config: &FleetConfig,
+        new_state: impl Fn(&FleetDevice) -> Arc<Mutex<S>>,
+    ) -> Self {
+        let mut fleet = Self::new();
+        for device in config.devices.iter() {
+            fleet.insert(&device.id, new_state(device));
+        }
+        fleet
+    }
+
+    pub fn single(state: Arc<Mutex<S>>) -> Self {
+        let mut fleet = Self::new();
+        fleet.insert(DEFAULT_DEVICE, state);
+        fleet
+    }
+
+    pub fn insert(&mut self, id: &str, state: Arc<Mutex<S>>) {
+        self.devices.insert(id.to_string(), state);
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Mutex<S>>> {
+        self.devices.get(id).cloned()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.devices.keys().map(String::as_str)
+    }
+
+    pub fn states(&self) -> impl Iterator<Item = &Mutex<S>> {
+        self.devices.values().map(|state| state.as_ref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+}
+
+// which devices a listener serves: one on its own port, or any by id on the shared one
+pub struct Route<S> {
+    fleet: Arc<Fleet<S>>,
+    device: Option<String>,
+}
+
+// not derived, that would require `S: Clone`
+impl<S> Clone for Route<S> {
+    fn clone(&self) -> Self {
+        Self {
+            fleet: Arc::clone(&self.fleet),
+            device: self.device.clone(),
+        }
+    }
+}
+
+impl<S> Route<S> {
+    pub fn shared(fleet: Arc<Fleet<S>>) -> Self {
+        Self {
+            fleet,
+            device: None,
+        }
+    }
+
+    pub fn device(fleet: Arc<Fleet<S>>, id: &str) -> Self {
+        Self {
+            fleet,
+            device: Some(id.to_string()),
+        }
+    }
+
+    pub fn single(state: Arc<Mutex<S>>) -> Self {
+        Self::device(Arc::new(Fleet::single(state)), DEFAULT_DEVICE)
+    }
+
+    pub fn fleet(&self) -> &Arc<Fleet<S>> {
+        &self.fleet
+    }
+
+    // the state a request addressed to `requested`, or to nobody in particular, is for
+    pub fn resolve(&self, requested: Option<&str>) -> Result<&Mutex<S>, String> {
+        let id = match (self.device.as_deref(), requested) {
+            (Some(own), Some(requested)) if own != requested => {
+                return Err(format!("this port serves '{}', not '{}'", own, requested))
+            }
+            (Some(own), _) => own,
+            (None, Some(requested)) => requested,
+            (None, None) if self.fleet.len() == 1 => {
+                return Ok(self.fleet.states().next().unwrap())
+            }
+            (None, None) => return Err("device id required".to_string()),
+        };
+        self.fleet
+            .devices
+            .get(id)
+            .map(|state| state.as_ref())
+            .ok_or_else(|| format!("unknown device '{}'", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fleet::{Fleet, FleetConfig, FleetDevice, FleetError, Route, DEFAULT_DEVICE};
+    use std::sync::{Arc, Mutex};
+
+    fn fleet() -> Arc<Fleet<bool>> {
+        let mut fleet = Fleet::new();
+        fleet.insert("kitchen", Arc::new(Mutex::new(true)));
+        fleet.insert("hall", Arc::new(Mutex::new(false)));
+        Arc::new(fleet)
+    }
+
+    #[test]
+    fn test_shared_route() {
+        let route = Route::shared(fleet());
+        assert!(*route.resolve(Some("kitchen")).unwrap().lock().unwrap());
+        assert!(!*route.resolve(Some("hall")).unwrap().lock().unwrap());
+        assert_eq!(
+            route.resolve(Some("attic")).err(),
+            Some("unknown device 'attic'".to_string())
+        );
+        assert!(route.resolve(None).is_err());
+    }
+
+    #[test]
+    fn test_device_route() {
+        let route = Route::device(fleet(), "kitchen");
+        assert!(*route.resolve(None).unwrap().lock().unwrap());
+        assert!(route.resolve(Some("kitchen")).is_ok());
+        assert!(route.resolve(Some("hall")).is_err());
+
+        let single = Route::single(Arc::new(Mutex::new(())));
+        assert!(single.resolve(None).is_ok());
+        assert!(single.resolve(Some(DEFAULT_DEVICE)).is_ok());
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = FleetConfig::parse(
+            r#"
+            listen = 1300
+            [[device]]
+            id = "kitchen"
+            port = 1301
+            max_current = 10.0
+            [[device]]
+            id = "hall"
+            kind = "light"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.listen_addr(), Some("0.0.0.0:1300".to_string()));
+        assert_eq!(
+            config.devices,
+            vec![
+                FleetDevice {
+                    id: "kitchen".to_string(),
+                    port: Some(1301),
+                    kind: None,
+                    max_power: None,
+                    max_current: Some(10.0),
+                },
+                FleetDevice {
+                    id: "hall".to_string(),
+                    port: None,
+                    kind: Some("light".to_string()),
+                    max_power: None,
+                    max_current: None,
+                },
+            ]
+        );
+
+        let fleet = Arc::new(Fleet::from_config(&config, || Arc::new(Mutex::new(()))));
+        let routes = config.routes(&fleet);
+        let addrs: Vec<_> = routes.iter().map(|(_, addr)| addr.as_str()).collect();
+        assert_eq!(addrs, vec!["0.0.0.0:1300", "0.0.0.0:1301"]);
+        assert!(routes[1].0.resolve(Some("hall")).is_err());
+    }
+
+    #[test]
+    fn test_invalid() {
+        for config in [
+            "listen = 1300",
+            "listen = 1300\n[[device]]\nid = \"a\"\n[[device]]\nid = \"a\"",
+            "listen = 1300\n[[device]]\nid = \"a\"\nport = 1300",
+            "[[device]]\nid = \"a\"",
+            "[[device]]\nid = \"\"\nport = 1",
+        ] {
+            assert!(
+                matches!(FleetConfig::parse(config), Err(FleetError::Invalid(_))),
+                "{}",
+                config
+            );
+        }
+        assert!(matches!(
+            FleetConfig::parse("[[device]]\nname = \"a\""),
+            Err(FleetError::Parse(_))
+        ));
+    }
+}
@@ -1,5 +1,34 @@
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+pub mod alarm;
+pub mod area;
+pub mod auth;
+pub mod codec;
+pub mod discovery;
+pub mod fault;
+pub mod fleet;
+#[cfg(feature = "logging")]
+pub mod logging;
+pub mod measurement;
+pub mod metrics;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod units;
+
+pub use alarm::{AlarmState, AlarmStatus};
+pub use area::{AreaKind, AreaSummary};
+pub use codec::{Codec, CodecError};
+pub use fault::{Overload, PowerFault};
+pub use measurement::{Measurement, Metric, Unit};
+pub use units::{Calibration, Power, PowerUnit, Temperature, TemperatureUnit};
+
+// bumped whenever a request or response changes incompatibly
+//   2: a tripped power socket answers `Status` and `GetPower` with `Fault`
+pub const PROTOCOL_VERSION: u32 = 2;
+
 pub trait Marshal {
     fn marshal(&self) -> serde_json::Result<String>
     where
@@ -14,13 +43,29 @@ pub trait Marshal {
     {
         serde_json::from_str(s)
     }
+    fn encode(&self, codec: Codec) -> Result<Vec<u8>, CodecError>
+    where
+        Self: Sized,
+        Self: Serialize,
+    {
+        codec.encode(self)
+    }
+    fn decode(codec: Codec, bytes: &[u8]) -> Result<Self, CodecError>
+    where
+        Self: Sized,
+        Self: DeserializeOwned,
+    {
+        codec.decode(bytes)
+    }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
 #[serde(tag = "type")]
 pub enum DeviceAction {
     TurnOn,
     TurnOff,
+    // clears a latched fault, e.g. a tripped power socket, leaving the device off
+    Reset,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
@@ -35,10 +80,44 @@ pub enum HomeAction {
 pub enum HomeRequest {
     Ping,
     Status,
+    // the room name is its path, e.g. "floor1/kitchen" for a kitchen on the first floor
     HomeAction {
         method: HomeAction,
         room_name: String,
     },
+    // devices, alarms and the power budget follow the room
+    RenameRoom {
+        room_name: String,
+        new_name: String,
+    },
+    RenameDevice {
+        room_name: String,
+        device: String,
+        new_name: String,
+    },
+    // the device keeps its name in the other room
+    MoveDevice {
+        from_room: String,
+        to_room: String,
+        device: String,
+    },
+    AddArea {
+        path: String,
+        kind: AreaKind,
+    },
+    // only once there is nothing in it anymore
+    RemoveArea {
+        path: String,
+    },
+    // an empty path for the whole home
+    GetArea {
+        path: String,
+    },
+    // every alarm which was raised and not cleared since
+    GetAlarms,
+    AcknowledgeAlarm {
+        id: String,
+    },
 }
 
 impl Marshal for HomeRequest {}
@@ -52,10 +131,74 @@ pub enum DeviceRequest {
     GetTemperature,
     GetPower,
     Exit,
+    Describe,
+    GetHumidity,
+    // percent, 0 to 100
+    SetBrightness { level: u8 },
+    GetBrightness,
+    GetContact,
+    GetEnergy,
+    // every metric a sensor measures
+    GetMeasurements,
 }
 
 impl Marshal for DeviceRequest {}
 
+// a request for one of several devices served behind the same address
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct AddressedRequest {
+    pub device: String,
+    pub request: DeviceRequest,
+}
+
+impl Marshal for AddressedRequest {}
+
+impl DeviceRequest {
+    // variant name without payload, used to label metrics and logs
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Ping => "Ping",
+            Self::Status => "Status",
+            Self::DeviceAction { .. } => "DeviceAction",
+            Self::GetTemperature => "GetTemperature",
+            Self::GetPower => "GetPower",
+            Self::Exit => "Exit",
+            Self::Describe => "Describe",
+            Self::GetHumidity => "GetHumidity",
+            Self::SetBrightness { .. } => "SetBrightness",
+            Self::GetBrightness => "GetBrightness",
+            Self::GetContact => "GetContact",
+            Self::GetEnergy => "GetEnergy",
+            Self::GetMeasurements => "GetMeasurements",
+        }
+    }
+}
+
+// what a device server answers to `DeviceRequest::Describe`
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct DeviceDescription {
+    pub protocol_version: u32,
+    pub device_type: String,
+    pub firmware: String,
+    // `DeviceRequest::kind` of every request the device handles
+    pub supported: Vec<String>,
+}
+
+impl DeviceDescription {
+    pub fn new(device_type: &str, firmware: &str, supported: &[&str]) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            device_type: device_type.to_string(),
+            firmware: firmware.to_string(),
+            supported: supported.iter().map(|kind| kind.to_string()).collect(),
+        }
+    }
+
+    pub fn supports(&self, kind: &str) -> bool {
+        self.supported.iter().any(|k| k == kind)
+    }
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 #[serde(tag = "response", content = "value")]
 pub enum Response {
@@ -65,13 +208,185 @@ pub enum Response {
     Status(bool),
     Temperature(f32),
     Power(f32),
+    Description(DeviceDescription),
+    // relative, percent
+    Humidity(f32),
+    Brightness(u8),
+    // true while the contact is open, e.g. the door is
+    Contact(bool),
+    // metered since the device started, kWh
+    Energy(f32),
+    Measurements(Vec<Measurement>),
+    Alarms(Vec<AlarmStatus>),
+    // the device cut its output and stays off until reset
+    Fault(PowerFault),
+    Area(AreaSummary),
 }
 
 impl Marshal for Response {}
 
 #[cfg(test)]
 mod tests {
-    use crate::{DeviceAction, DeviceRequest, HomeAction, HomeRequest, Marshal, Response};
+    use crate::{
+        AlarmState, AlarmStatus, AreaKind, AreaSummary, Codec, DeviceAction, DeviceDescription,
+        DeviceRequest, HomeAction, HomeRequest, Marshal, Measurement, Metric, Overload, PowerFault,
+        Response, PROTOCOL_VERSION,
+    };
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use std::fmt::Debug;
+
+    fn home_requests() -> Vec<HomeRequest> {
+        vec![
+            HomeRequest::Ping,
+            HomeRequest::Status,
+            HomeRequest::HomeAction {
+                method: HomeAction::AddRoom,
+                room_name: "test".to_string(),
+            },
+            HomeRequest::HomeAction {
+                method: HomeAction::RemoveRoom,
+                room_name: "test".to_string(),
+            },
+            HomeRequest::RenameRoom {
+                room_name: "test".to_string(),
+                new_name: "floor1/test".to_string(),
+            },
+            HomeRequest::RenameDevice {
+                room_name: "test".to_string(),
+                device: "socket".to_string(),
+                new_name: "lamp".to_string(),
+            },
+            HomeRequest::MoveDevice {
+                from_room: "test".to_string(),
+                to_room: "hall".to_string(),
+                device: "socket".to_string(),
+            },
+            HomeRequest::GetAlarms,
+            HomeRequest::AcknowledgeAlarm {
+                id: "hall/thermometer/temperature/min".to_string(),
+            },
+            HomeRequest::AddArea {
+                path: "floor1/east".to_string(),
+                kind: AreaKind::Zone,
+            },
+            HomeRequest::RemoveArea {
+                path: "floor1".to_string(),
+            },
+            HomeRequest::GetArea {
+                path: String::new(),
+            },
+        ]
+    }
+
+    fn device_requests() -> Vec<DeviceRequest> {
+        vec![
+            DeviceRequest::Ping,
+            DeviceRequest::Status,
+            DeviceRequest::DeviceAction {
+                method: DeviceAction::TurnOn,
+            },
+            DeviceRequest::DeviceAction {
+                method: DeviceAction::TurnOff,
+            },
+            DeviceRequest::DeviceAction {
+                method: DeviceAction::Reset,
+            },
+            DeviceRequest::GetTemperature,
+            DeviceRequest::GetPower,
+            DeviceRequest::Exit,
+            DeviceRequest::Describe,
+            DeviceRequest::GetHumidity,
+            DeviceRequest::SetBrightness { level: 40 },
+            DeviceRequest::GetBrightness,
+            DeviceRequest::GetContact,
+            DeviceRequest::GetEnergy,
+            DeviceRequest::GetMeasurements,
+        ]
+    }
+
+    fn responses() -> Vec<Response> {
+        vec![
+            Response::Ok,
+            Response::Pong,
+            Response::Status(true),
+            Response::Power(1.2),
+            Response::Temperature(5.0),
+            Response::Err("something".to_string()),
+            Response::Description(DeviceDescription::new(
+                "PSOC",
+                "test 0.1.0",
+                &["Ping", "Describe"],
+            )),
+            Response::Humidity(45.5),
+            Response::Brightness(40),
+            Response::Contact(true),
+            Response::Energy(0.25),
+            Response::Measurements(vec![
+                Measurement::new(Metric::Temperature, 21.5),
+                Measurement::new(Metric::Co2, 600.0),
+            ]),
+            Response::Alarms(vec![AlarmStatus {
+                id: "hall/thermometer/temperature/min".to_string(),
+                room: "hall".to_string(),
+                device: "thermometer".to_string(),
+                metric: "temperature".to_string(),
+                state: AlarmState::Raised,
+                message: "temperature 3.5 below 5".to_string(),
+                value: Some(3.5),
+                raised_at: 1_714_566_600_000,
+                changed_at: 1_714_566_600_000,
+            }]),
+            Response::Fault(PowerFault::new(Overload::Current, 10.5, 10.0)),
+            Response::Area(AreaSummary {
+                path: "floor1".to_string(),
+                kind: AreaKind::Floor,
+                rooms: 2,
+                devices: 3,
+                faulty: 1,
+                power: 120.5,
+                energy: 0.0,
+                areas: vec![AreaSummary {
+                    path: "floor1/east".to_string(),
+                    kind: AreaKind::Zone,
+                    rooms: 1,
+                    devices: 1,
+                    faulty: 0,
+                    power: 60.0,
+                    energy: 0.0,
+                    areas: vec![],
+                }],
+            }),
+        ]
+    }
+
+    fn assert_round_trips<T>(messages: Vec<T>)
+    where
+        T: Marshal + Serialize + DeserializeOwned + PartialEq + Debug,
+    {
+        for codec in Codec::ALL {
+            for msg in messages.iter() {
+                let bytes = msg.encode(codec).unwrap();
+                assert_eq!(Codec::detect(&bytes), Some(codec), "{:?}", msg);
+                assert_eq!(&T::decode(codec, &bytes).unwrap(), msg, "{}", codec);
+            }
+        }
+    }
+
+    #[test]
+    fn test_codecs_round_trip() {
+        assert_round_trips(home_requests());
+        assert_round_trips(device_requests());
+        assert_round_trips(responses());
+    }
+
+    #[test]
+    fn test_json_codec_matches_marshal() {
+        for req in device_requests() {
+            let bytes = req.encode(Codec::Json).unwrap();
+            assert_eq!(bytes, req.marshal().unwrap().into_bytes());
+        }
+    }
 
     #[test]
     fn test_marshal_home_requests() {
@@ -134,4 +449,12 @@ mod tests {
             println!("test marshal success")
         }
     }
+
+    #[test]
+    fn test_description() {
+        let description = DeviceDescription::new("THRM", "test", &["Ping", "GetTemperature"]);
+        assert_eq!(description.protocol_version, PROTOCOL_VERSION);
+        assert!(description.supports("GetTemperature"));
+        assert!(!description.supports(DeviceRequest::GetPower.kind()));
+    }
 }
@@ -0,0 +1,53 @@
+use tracing_subscriber::EnvFilter;
+
+static DEFAULT_FILTER: &str = "info";
+
+#[derive(Debug, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    // anything but "json" falls back to human readable text
+    pub fn from_env() -> Self {
+        match std::env::var("LOG_FORMAT") {
+            Ok(format) if format.eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Text,
+        }
+    }
+}
+
+// installs the global subscriber; levels come from `RUST_LOG`, e.g. `RUST_LOG=power_socket_server=debug`,
+// and `LOG_FORMAT=json` switches to one JSON object per line
+pub fn init() {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    init_with(filter, LogFormat::from_env())
+}
+
+pub fn init_with(filter: EnvFilter, format: LogFormat) {
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    // a subscriber may already be installed, e.g. by another test in the same binary
+    let _ = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .try_init(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logging::{init_with, LogFormat};
+    use tracing_subscriber::EnvFilter;
+
+    #[test]
+    fn test_init_twice() {
+        init_with(EnvFilter::new("debug"), LogFormat::Json);
+        // the second call must not panic
+        init_with(EnvFilter::new("info"), LogFormat::Text);
+    }
+}
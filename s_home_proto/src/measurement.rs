@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+// what a sensor measures
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Metric {
+    Temperature,
+    Humidity,
+    Pressure,
+    Co2,
+    Illuminance,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 5] = [
+        Self::Temperature,
+        Self::Humidity,
+        Self::Pressure,
+        Self::Co2,
+        Self::Illuminance,
+    ];
+
+    // the unit devices report the metric in
+    pub fn unit(&self) -> Unit {
+        match self {
+            Self::Temperature => Unit::Celsius,
+            Self::Humidity => Unit::Percent,
+            Self::Pressure => Unit::Hectopascal,
+            Self::Co2 => Unit::Ppm,
+            Self::Illuminance => Unit::Lux,
+        }
+    }
+}
+
+impl Display for Metric {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Temperature => write!(f, "temperature"),
+            Self::Humidity => write!(f, "humidity"),
+            Self::Pressure => write!(f, "pressure"),
+            Self::Co2 => write!(f, "co2"),
+            Self::Illuminance => write!(f, "illuminance"),
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|metric| metric.to_string() == s.to_lowercase())
+            .ok_or_else(|| format!("unknown metric '{}'", s))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Unit {
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+    // relative humidity
+    Percent,
+    Hectopascal,
+    // parts per million
+    Ppm,
+    Lux,
+    Watt,
+    Kilowatt,
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Celsius => write!(f, "°C"),
+            Self::Fahrenheit => write!(f, "°F"),
+            Self::Kelvin => write!(f, "K"),
+            Self::Percent => write!(f, "%"),
+            Self::Hectopascal => write!(f, "hPa"),
+            Self::Ppm => write!(f, "ppm"),
+            Self::Lux => write!(f, "lx"),
+            Self::Watt => write!(f, "W"),
+            Self::Kilowatt => write!(f, "kW"),
+        }
+    }
+}
+
+// one reading of a sensor, e.g. 21.5 °C
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Measurement {
+    pub metric: Metric,
+    pub value: f32,
+    pub unit: Unit,
+}
+
+impl Measurement {
+    // in the metric's own unit
+    pub fn new(metric: Metric, value: f32) -> Self {
+        Self {
+            metric,
+            value,
+            unit: metric.unit(),
+        }
+    }
+}
+
+impl Display for Measurement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} {}", self.metric, self.value, self.unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::measurement::{Measurement, Metric, Unit};
+
+    #[test]
+    fn test_metric_from_str() {
+        for metric in Metric::ALL {
+            assert_eq!(metric.to_string().parse::<Metric>().unwrap(), metric);
+        }
+        assert_eq!("CO2".parse::<Metric>().unwrap(), Metric::Co2);
+        assert!("radiation".parse::<Metric>().is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        let measurement = Measurement::new(Metric::Temperature, 21.5);
+        assert_eq!(measurement.unit, Unit::Celsius);
+        assert_eq!(measurement.to_string(), "temperature: 21.5 °C");
+        assert_eq!(
+            Measurement::new(Metric::Co2, 600.0).to_string(),
+            "co2: 600 ppm"
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread::{spawn, JoinHandle};
+use std::time::Duration;
+
+static LATENCY_BUCKETS: [f64; 8] = [0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0];
+
+pub static CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+pub fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[derive(Default)]
+struct Counters {
+    requests: BTreeMap<String, u64>,
+    errors: u64,
+    // cumulative counts per bucket of LATENCY_BUCKETS
+    buckets: [u64; 8],
+    latency_sum: f64,
+    latency_count: u64,
+}
+
+// request counters shared by the device servers, rendered in the Prometheus text format
+pub struct ServerMetrics {
+    server: String,
+    counters: Mutex<Counters>,
+}
+
+impl ServerMetrics {
+    pub fn new(server: &str) -> Self {
+        Self {
+            server: server.to_string(),
+            counters: Mutex::new(Counters::default()),
+        }
+    }
+
+    // what the servers hand to every connection they serve
+    pub fn shared(server: &str) -> Arc<Self> {
+        Arc::new(Self::new(server))
+    }
+
+    pub fn observe(&self, kind: &str, latency: Duration, is_err: bool) {
+        let secs = latency.as_secs_f64();
+        let mut counters = self.counters.lock().unwrap();
+        *counters.requests.entry(kind.to_string()).or_insert(0) += 1;
+        if is_err {
+            counters.errors += 1;
+        }
+        for (i, bound) in LATENCY_BUCKETS.iter().enumerate() {
+            if secs <= *bound {
+                counters.buckets[i] += 1;
+            }
+        }
+        counters.latency_sum += secs;
+        counters.latency_count += 1;
+    }
+
+    // for requests which could not even be decoded
+    pub fn observe_error(&self) {
+        self.counters.lock().unwrap().errors += 1;
+    }
+
+    pub fn requests(&self, kind: &str) -> u64 {
+        let counters = self.counters.lock().unwrap();
+        counters.requests.get(kind).copied().unwrap_or(0)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.counters.lock().unwrap().errors
+    }
+
+    pub fn render(&self) -> String {
+        let counters = self.counters.lock().unwrap();
+        let server = escape_label(&self.server);
+        let mut out = String::new();
+
+        out.push_str("# HELP device_requests_total Device requests handled, by request kind.\n");
+        out.push_str("# TYPE device_requests_total counter\n");
+        for (kind, count) in counters.requests.iter() {
+            let _ = writeln!(
+                out,
+                "device_requests_total{{server=\"{}\",kind=\"{}\"}} {}",
+                server,
+                escape_label(kind),
+                count
+            );
+        }
+
+        out.push_str(
+            "# HELP device_request_errors_total Device requests answered with an error.\n",
+        );
+        out.push_str("# TYPE device_request_errors_total counter\n");
+        let _ = writeln!(
+            out,
+            "device_request_errors_total{{server=\"{}\"}} {}",
+            server, counters.errors
+        );
+
+        out.push_str("# HELP device_request_duration_seconds Device request handling latency.\n");
+        out.push_str("# TYPE device_request_duration_seconds histogram\n");
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(counters.buckets.iter()) {
+            let _ = writeln!(
+                out,
+                "device_request_duration_seconds_bucket{{server=\"{}\",le=\"{}\"}} {}",
+                server, bound, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "device_request_duration_seconds_bucket{{server=\"{}\",le=\"+Inf\"}} {}",
+            server, counters.latency_count
+        );
+        let _ = writeln!(
+            out,
+            "device_request_duration_seconds_sum{{server=\"{}\"}} {}",
+            server, counters.latency_sum
+        );
+        let _ = writeln!(
+            out,
+            "device_request_duration_seconds_count{{server=\"{}\"}} {}",
+            server, counters.latency_count
+        );
+        out
+    }
+}
+
+// answers `GET /metrics` with whatever `render` returns, one connection at a time
+pub fn serve_metrics<F>(listener: TcpListener, render: F) -> JoinHandle<()>
+where
+    F: Fn() -> String + Send + 'static,
+{
+    spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let mut request_line = String::new();
+            let mut reader = BufReader::new(&stream);
+            if reader.read_line(&mut request_line).is_err() {
+                continue;
+            }
+            let response = http_response(&request_line, &render);
+            let _ = stream.write_all(response.as_bytes());
+        }
+    })
+}
+
+// exposes `GET /metrics` of a device server on a separate port next to its device port
+pub fn serve_server_metrics(
+    addr: &str,
+    metrics: Arc<ServerMetrics>,
+) -> std::io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr)?;
+    tracing::info!(address = %listener.local_addr()?, "metrics endpoint listening");
+    Ok(serve_metrics(listener, move || metrics.render()))
+}
+
+pub fn http_response<F: Fn() -> String>(request_line: &str, render: F) -> String {
+    let mut parts = request_line.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = render();
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                CONTENT_TYPE,
+                body.len(),
+                body
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::{escape_label, http_response, ServerMetrics};
+    use std::time::Duration;
+
+    #[test]
+    fn test_observe() {
+        let metrics = ServerMetrics::new("test");
+        metrics.observe("Ping", Duration::from_micros(100), false);
+        metrics.observe("GetPower", Duration::from_millis(20), true);
+        metrics.observe_error();
+
+        assert_eq!(metrics.requests("Ping"), 1);
+        assert_eq!(metrics.requests("Exit"), 0);
+        assert_eq!(metrics.errors(), 2);
+
+        let text = metrics.render();
+        assert!(text.contains("device_requests_total{server=\"test\",kind=\"Ping\"} 1\n"));
+        assert!(text
+            .contains("device_request_duration_seconds_bucket{server=\"test\",le=\"0.005\"} 1\n"));
+        assert!(text
+            .contains("device_request_duration_seconds_bucket{server=\"test\",le=\"+Inf\"} 2\n"));
+    }
+
+    #[test]
+    fn test_http_response() {
+        let ok = http_response("GET /metrics HTTP/1.1\r\n", || "up 1\n".to_string());
+        assert!(ok.starts_with("HTTP/1.1 200 OK"));
+        assert!(ok.ends_with("\r\n\r\nup 1\n"));
+
+        let not_found = http_response("GET / HTTP/1.1\r\n", String::new);
+        assert!(not_found.starts_with("HTTP/1.1 404"));
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("a\"b\\c\n"), "a\\\"b\\\\c\\n")
+    }
+}
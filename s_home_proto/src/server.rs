@@ -0,0 +1,372 @@
+// request handling and the TCP and UDP serve loops every device server shares
+use crate::auth::{AuthError, Keyring};
+use crate::fleet::Route;
+use crate::metrics::ServerMetrics;
+use crate::{Codec, DeviceRequest, Marshal, Response};
+use std::error::Error;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::watch;
+use tracing::{debug, info, info_span, trace, warn, Instrument};
+
+pub type BoxError = Box<dyn Error + Send + Sync>;
+
+// how a server answers a decoded and authorized request for one of its devices
+pub type Respond<S> = fn(DeviceRequest, &Mutex<S>) -> Response;
+
+// a client that connects and says nothing must not hold its task forever
+static CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+// what to send back for one request
+pub struct Handled {
+    pub response: Vec<u8>,
+    // an `Exit` was answered, the server should stop
+    pub exit: bool,
+}
+
+// everything a listener needs to answer requests for the devices it serves
+pub struct Endpoint<S> {
+    route: Route<S>,
+    respond: Respond<S>,
+    metrics: Arc<ServerMetrics>,
+    keyring: Arc<Keyring>,
+}
+
+// not derived, that would require `S: Clone`
+impl<S> Clone for Endpoint<S> {
+    fn clone(&self) -> Self {
+        Self {
+            route: self.route.clone(),
+            respond: self.respond,
+            metrics: Arc::clone(&self.metrics),
+            keyring: Arc::clone(&self.keyring),
+        }
+    }
+}
+
+impl<S> Endpoint<S> {
+    pub fn new(
+        route: Route<S>,
+        respond: Respond<S>,
+        metrics: Arc<ServerMetrics>,
+        keyring: Arc<Keyring>,
+    ) -> Self {
+        Self {
+            route,
+            respond,
+            metrics,
+            keyring,
+        }
+    }
+
+    // decodes, authorizes and answers a request, the same for every transport
+    pub fn handle_message(&self, bs: &[u8]) -> Result<Handled, BoxError> {
+        debug!(bytes = bs.len(), message = ?bs, "received");
+
+        let started = Instant::now();
+        // answering in whatever codec the client spoke
+        let codec = Codec::detect(bs).unwrap_or_default();
+        let (device, req) = match self.keyring.accept_addressed(codec, bs) {
+            Ok(accepted) => accepted,
+            Err(AuthError::Decode(err)) => {
+                self.metrics.observe_error();
+                return Err(format!("err unmarshalling device request {:?}: {}", bs, err).into());
+            }
+            Err(err) => {
+                self.metrics.observe_error();
+                warn!(%err, "request denied");
+                let resp = Response::Err(format!("denied: {}", err));
+                return Ok(Handled {
+                    response: resp.encode(codec)?,
+                    exit: false,
+                });
+            }
+        };
+        let kind = req.kind();
+        let exit = req == DeviceRequest::Exit;
+
+        let resp = match self.route.resolve(device.as_deref()) {
+            Ok(state) => (self.respond)(req, state),
+            Err(err) => Response::Err(err),
+        };
+        let response = resp.encode(codec)?;
+        let latency = started.elapsed();
+        let failed = matches!(resp, Response::Err(_));
+        self.metrics.observe(kind, latency, failed);
+        info!(
+            request = kind,
+            device = device.as_deref(),
+            %codec,
+            response = ?resp,
+            latency_us = latency.as_micros() as u64,
+            "request handled"
+        );
+        // servers that do not support `Exit` answer it with an error
+        Ok(Handled {
+            response,
+            exit: exit && !failed,
+        })
+    }
+}
+
+// stops every listener of the process once triggered
+#[derive(Clone)]
+pub struct Shutdown(Arc<watch::Sender<bool>>);
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self(Arc::new(watch::channel(false).0))
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn trigger(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub async fn wait(&self) {
+        let _ = self.0.subscribe().wait_for(|stopped| *stopped).await;
+    }
+}
+
+// turns an accepted connection into the stream a request is read from, e.g. by a TLS handshake
+pub trait Upgrade: Clone + Send + Sync + 'static {
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send;
+
+    fn upgrade(
+        &self,
+        stream: TcpStream,
+    ) -> impl Future<Output = std::io::Result<Self::Stream>> + Send;
+}
+
+// connections used as they are
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PlainTcp;
+
+impl Upgrade for PlainTcp {
+    type Stream = TcpStream;
+
+    async fn upgrade(&self, stream: TcpStream) -> std::io::Result<TcpStream> {
+        Ok(stream)
+    }
+}
+
+// one request per connection, answered and closed, every connection on a task of its own;
+// returns once an `Exit` is answered or the shutdown is triggered
+pub async fn serve_tcp<S, U>(
+    listener: TcpListener,
+    endpoint: Endpoint<S>,
+    upgrade: U,
+    shutdown: Shutdown,
+) -> std::io::Result<()>
+where
+    S: Send + 'static,
+    U: Upgrade,
+{
+    let address = listener.local_addr()?;
+    info!(%address, auth = !endpoint.keyring.is_open(), "listening on tcp");
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                // e.g. out of file descriptors, the next accept may well succeed
+                Err(err) => {
+                    warn!(%err, "err accepting a connection");
+                    continue;
+                }
+            },
+            _ = shutdown.wait() => break,
+        };
+
+        let endpoint = endpoint.clone();
+        let upgrade = upgrade.clone();
+        let shutdown = shutdown.clone();
+        let connection = async move {
+            let handled = match upgrade.upgrade(stream).await {
+                Ok(stream) => handle_connection(stream, &endpoint).await,
+                Err(err) => {
+                    endpoint.metrics.observe_error();
+                    Err(format!("err setting up the connection: {}", err).into())
+                }
+            };
+            match handled {
+                Ok(true) => {
+                    info!("exit requested, shutting down");
+                    shutdown.trigger();
+                }
+                Ok(false) => trace!("request handled"),
+                Err(err) => warn!(%err, "request failed"),
+            }
+        };
+        tokio::spawn(connection.instrument(info_span!("connection", %address, %peer)));
+    }
+    Ok(())
+}
+
+// true when the server should exit
+async fn handle_connection<S, St>(mut stream: St, endpoint: &Endpoint<S>) -> Result<bool, BoxError>
+where
+    St: AsyncRead + AsyncWrite + Unpin,
+{
+    // room for a signed request
+    let mut buf = [0u8; 512];
+    let message_len = tokio::time::timeout(CONNECTION_TIMEOUT, stream.read(&mut buf)).await??;
+    if message_len == 0 {
+        return Ok(false);
+    }
+
+    let handled = endpoint.handle_message(&buf[..message_len])?;
+    stream.write_all(&handled.response).await?;
+    // for TLS this is also the close_notify the client waits for
+    stream.shutdown().await?;
+    Ok(handled.exit)
+}
+
+// a datagram per request and one back; returns once an `Exit` is answered or the shutdown is
+// triggered
+pub async fn serve_udp<S>(
+    socket: UdpSocket,
+    endpoint: Endpoint<S>,
+    shutdown: Shutdown,
+) -> std::io::Result<()> {
+    let address = socket.local_addr()?;
+    info!(%address, auth = !endpoint.keyring.is_open(), "listening on udp");
+    let mut buf = [0; 1024];
+
+    loop {
+        let (len, peer) = tokio::select! {
+            received = socket.recv_from(&mut buf) => match received {
+                Ok(received) => received,
+                Err(err) => {
+                    warn!(%err, "err receiving a datagram");
+                    continue;
+                }
+            },
+            _ = shutdown.wait() => break,
+        };
+        // answered inline, every request is a few field reads or writes
+        let handled = {
+            let _span = info_span!("datagram", %address, %peer).entered();
+            match endpoint.handle_message(&buf[..len]) {
+                Ok(handled) => handled,
+                Err(err) => {
+                    warn!(%err, "request failed");
+                    continue;
+                }
+            }
+        };
+        if let Err(err) = socket.send_to(&handled.response, peer).await {
+            warn!(%err, %peer, "err sending a response");
+        }
+        if handled.exit {
+            info!("exit requested, shutting down");
+            shutdown.trigger();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::Keyring;
+    use crate::fleet::Route;
+    use crate::metrics::ServerMetrics;
+    use crate::server::{serve_tcp, serve_udp, Endpoint, PlainTcp, Shutdown};
+    use crate::{DeviceRequest, Marshal, Response};
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream, UdpSocket};
+
+    // a counter that can be told to exit
+    fn respond(req: DeviceRequest, state: &Mutex<u32>) -> Response {
+        match req {
+            DeviceRequest::Ping => {
+                *state.lock().unwrap() += 1;
+                Response::Pong
+            }
+            DeviceRequest::Exit => Response::Ok,
+            _ => Response::Err(format!("bad request: {:?}", req)),
+        }
+    }
+
+    fn endpoint(state: Arc<Mutex<u32>>) -> Endpoint<u32> {
+        Endpoint::new(
+            Route::single(state),
+            respond,
+            Arc::new(ServerMetrics::new("test")),
+            Arc::new(Keyring::open()),
+        )
+    }
+
+    async fn tcp_request(addr: &str, req: DeviceRequest) -> Response {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(req.marshal().unwrap().as_bytes())
+            .await
+            .unwrap();
+        let mut buf = vec![];
+        stream.read_to_end(&mut buf).await.unwrap();
+        Response::unmarshal(std::str::from_utf8(&buf).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_tcp_exit() {
+        let state = Arc::new(Mutex::new(0));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(serve_tcp(
+            listener,
+            endpoint(Arc::clone(&state)),
+            PlainTcp,
+            Shutdown::new(),
+        ));
+
+        assert_eq!(
+            tcp_request(&addr, DeviceRequest::Ping).await,
+            Response::Pong
+        );
+        assert!(matches!(
+            tcp_request(&addr, DeviceRequest::GetPower).await,
+            Response::Err(_)
+        ));
+        assert_eq!(tcp_request(&addr, DeviceRequest::Exit).await, Response::Ok);
+        server.await.unwrap().unwrap();
+        assert_eq!(*state.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_udp_shutdown() {
+        let state = Arc::new(Mutex::new(0));
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let shutdown = Shutdown::new();
+        let server = tokio::spawn(serve_udp(
+            socket,
+            endpoint(Arc::clone(&state)),
+            shutdown.clone(),
+        ));
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(addr).await.unwrap();
+        let mut buf = [0u8; 512];
+        // undecodable datagrams go unanswered
+        client.send(b"garbage").await.unwrap();
+        let ping = DeviceRequest::Ping.marshal().unwrap();
+        client.send(ping.as_bytes()).await.unwrap();
+        let len = client.recv(&mut buf).await.unwrap();
+        let resp = Response::unmarshal(std::str::from_utf8(&buf[..len]).unwrap()).unwrap();
+        assert_eq!(resp, Response::Pong);
+
+        shutdown.trigger();
+        server.await.unwrap().unwrap();
+        assert_eq!(*state.lock().unwrap(), 1);
+    }
+}
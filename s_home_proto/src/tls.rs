@@ -0,0 +1,217 @@
+use rustls::crypto::ring;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum TlsError {
+    #[error("tls config is missing {0}")]
+    Missing(&'static str),
+    #[error("err reading '{path}': {reason}")]
+    Pem { path: PathBuf, reason: String },
+    #[error("bad client verifier: {0}")]
+    Verifier(String),
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+}
+
+// PEM file paths; a server needs an identity and asks clients for one when a CA is set,
+// a client needs the CA and presents its identity when it has one
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct TlsConfig {
+    ca: Option<PathBuf>,
+    cert: Option<PathBuf>,
+    key: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_ca(mut self, ca: impl AsRef<Path>) -> Self {
+        self.ca = Some(ca.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn with_identity(mut self, cert: impl AsRef<Path>, key: impl AsRef<Path>) -> Self {
+        self.cert = Some(cert.as_ref().to_path_buf());
+        self.key = Some(key.as_ref().to_path_buf());
+        self
+    }
+
+    // `{prefix}_CERT`, `{prefix}_KEY` and `{prefix}_CA`, e.g. `TLS_CERT`; `None` when none is set
+    pub fn from_env(prefix: &str) -> Option<Self> {
+        let var = |name: &str| std::env::var(format!("{}_{}", prefix, name)).ok();
+        let config = Self {
+            ca: var("CA").map(PathBuf::from),
+            cert: var("CERT").map(PathBuf::from),
+            key: var("KEY").map(PathBuf::from),
+        };
+        (config != Self::default()).then_some(config)
+    }
+
+    pub fn is_mutual(&self) -> bool {
+        self.ca.is_some() && self.cert.is_some()
+    }
+
+    pub fn server_config(&self) -> Result<Arc<ServerConfig>, TlsError> {
+        let provider = Arc::new(ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+        let builder = match &self.ca {
+            None => builder.with_no_client_auth(),
+            Some(ca) => {
+                let verifier = WebPkiClientVerifier::builder_with_provider(
+                    Arc::new(load_roots(ca)?),
+                    provider,
+                )
+                .build()
+                .map_err(|e| TlsError::Verifier(e.to_string()))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+        };
+        let (certs, key) = self.identity()?.ok_or(TlsError::Missing("cert and key"))?;
+        Ok(Arc::new(builder.with_single_cert(certs, key)?))
+    }
+
+    pub fn client_config(&self) -> Result<Arc<ClientConfig>, TlsError> {
+        let ca = self.ca.as_ref().ok_or(TlsError::Missing("ca"))?;
+        let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(load_roots(ca)?);
+        let config = match self.identity()? {
+            None => builder.with_no_client_auth(),
+            Some((certs, key)) => builder.with_client_auth_cert(certs, key)?,
+        };
+        Ok(Arc::new(config))
+    }
+
+    fn identity(
+        &self,
+    ) -> Result<Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>, TlsError> {
+        let (Some(cert), Some(key)) = (&self.cert, &self.key) else {
+            return Ok(None);
+        };
+        let key = PrivateKeyDer::from_pem_file(key).map_err(|e| pem_error(key, e))?;
+        Ok(Some((load_certs(cert)?, key)))
+    }
+}
+
+fn pem_error(path: &Path, err: impl ToString) -> TlsError {
+    TlsError::Pem {
+        path: path.to_path_buf(),
+        reason: err.to_string(),
+    }
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| pem_error(path, e))?;
+    if certs.is_empty() {
+        return Err(pem_error(path, "no certificates"));
+    }
+    Ok(certs)
+}
+
+fn load_roots(path: &Path) -> Result<RootCertStore, TlsError> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert).map_err(|e| pem_error(path, e))?;
+    }
+    Ok(roots)
+}
+
+// throwaway certificates, for tests only
+#[cfg(any(test, feature = "test-support"))]
+pub mod testing {
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+    use std::path::PathBuf;
+
+    pub struct Pems {
+        pub ca: PathBuf,
+        // (certificate, key) for 127.0.0.1
+        pub server: (PathBuf, PathBuf),
+        // (certificate, key) of a controller
+        pub client: (PathBuf, PathBuf),
+    }
+
+    // a CA with a certificate for a server and one for a client, written to a temp dir
+    pub fn generate_pems(name: &str) -> Pems {
+        let dir = std::env::temp_dir().join(format!("s_home_tls_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(vec![]).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+
+        let issue = |name: &str, san: &str| {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(vec![san.to_string()])
+                .unwrap()
+                .signed_by(&key, &ca, &ca_key)
+                .unwrap();
+            let paths = (
+                dir.join(format!("{}.pem", name)),
+                dir.join(format!("{}.key", name)),
+            );
+            std::fs::write(&paths.0, cert.pem()).unwrap();
+            std::fs::write(&paths.1, key.serialize_pem()).unwrap();
+            paths
+        };
+        let server = issue("server", "127.0.0.1");
+        let client = issue("client", "controller.local");
+        Pems {
+            ca: dir.join("ca.pem"),
+            server,
+            client,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tls::testing::generate_pems;
+    use crate::tls::{TlsConfig, TlsError};
+
+    #[test]
+    fn test_configs() {
+        let pems = generate_pems("configs");
+        let (ca, (cert, key)) = (pems.ca, pems.server);
+        let server = TlsConfig::new().with_identity(&cert, &key);
+        assert!(server.server_config().is_ok());
+        assert!(!server.is_mutual());
+
+        let mutual = server.clone().with_ca(&ca);
+        assert!(mutual.is_mutual());
+        assert!(mutual.server_config().is_ok());
+        assert!(mutual.client_config().is_ok());
+        assert!(TlsConfig::new().with_ca(&ca).client_config().is_ok());
+    }
+
+    #[test]
+    fn test_missing_files() {
+        assert!(matches!(
+            TlsConfig::new().with_ca("ca.pem").server_config(),
+            Err(TlsError::Pem { .. })
+        ));
+        assert!(matches!(
+            TlsConfig::new().client_config(),
+            Err(TlsError::Missing("ca"))
+        ));
+        let cert = generate_pems("missing").server.0;
+        assert!(matches!(
+            TlsConfig::new()
+                .with_identity(&cert, "nope.pem")
+                .server_config(),
+            Err(TlsError::Pem { .. })
+        ));
+    }
+}
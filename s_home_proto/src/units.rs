@@ -0,0 +1,241 @@
+use crate::measurement::Unit;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+}
+
+impl Display for TemperatureUnit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Unit::from(*self).fmt(f)
+    }
+}
+
+impl FromStr for TemperatureUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "c" | "celsius" => Ok(Self::Celsius),
+            "f" | "fahrenheit" => Ok(Self::Fahrenheit),
+            "k" | "kelvin" => Ok(Self::Kelvin),
+            _ => Err(format!("unknown temperature unit '{}'", s)),
+        }
+    }
+}
+
+impl From<TemperatureUnit> for Unit {
+    fn from(unit: TemperatureUnit) -> Self {
+        match unit {
+            TemperatureUnit::Celsius => Unit::Celsius,
+            TemperatureUnit::Fahrenheit => Unit::Fahrenheit,
+            TemperatureUnit::Kelvin => Unit::Kelvin,
+        }
+    }
+}
+
+// devices report degrees Celsius, the unit only matters when showing one
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Temperature {
+    celsius: f32,
+}
+
+impl Temperature {
+    pub fn new(value: f32, unit: TemperatureUnit) -> Self {
+        let celsius = match unit {
+            TemperatureUnit::Celsius => value,
+            TemperatureUnit::Fahrenheit => (value - 32.0) * 5.0 / 9.0,
+            TemperatureUnit::Kelvin => value - 273.15,
+        };
+        Self { celsius }
+    }
+
+    pub fn from_celsius(celsius: f32) -> Self {
+        Self { celsius }
+    }
+
+    pub fn celsius(&self) -> f32 {
+        self.celsius
+    }
+
+    pub fn value_in(&self, unit: TemperatureUnit) -> f32 {
+        match unit {
+            TemperatureUnit::Celsius => self.celsius,
+            TemperatureUnit::Fahrenheit => self.celsius * 9.0 / 5.0 + 32.0,
+            TemperatureUnit::Kelvin => self.celsius + 273.15,
+        }
+    }
+
+    // e.g. "70.3 °F", a tenth of a degree is as good as thermometers get
+    pub fn format(&self, unit: TemperatureUnit) -> String {
+        format!("{:.1} {}", self.value_in(unit), unit)
+    }
+}
+
+impl Display for Temperature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format(TemperatureUnit::Celsius))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PowerUnit {
+    #[default]
+    Watt,
+    Kilowatt,
+}
+
+impl Display for PowerUnit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Unit::from(*self).fmt(f)
+    }
+}
+
+impl FromStr for PowerUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "w" | "watt" => Ok(Self::Watt),
+            "kw" | "kilowatt" => Ok(Self::Kilowatt),
+            _ => Err(format!("unknown power unit '{}'", s)),
+        }
+    }
+}
+
+impl From<PowerUnit> for Unit {
+    fn from(unit: PowerUnit) -> Self {
+        match unit {
+            PowerUnit::Watt => Unit::Watt,
+            PowerUnit::Kilowatt => Unit::Kilowatt,
+        }
+    }
+}
+
+// devices report watts
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Power {
+    watts: f32,
+}
+
+impl Power {
+    pub fn new(value: f32, unit: PowerUnit) -> Self {
+        let watts = match unit {
+            PowerUnit::Watt => value,
+            PowerUnit::Kilowatt => value * 1000.0,
+        };
+        Self { watts }
+    }
+
+    pub fn from_watts(watts: f32) -> Self {
+        Self { watts }
+    }
+
+    pub fn watts(&self) -> f32 {
+        self.watts
+    }
+
+    pub fn value_in(&self, unit: PowerUnit) -> f32 {
+        match unit {
+            PowerUnit::Watt => self.watts,
+            PowerUnit::Kilowatt => self.watts / 1000.0,
+        }
+    }
+
+    pub fn format(&self, unit: PowerUnit) -> String {
+        match unit {
+            PowerUnit::Watt => format!("{:.1} {}", self.watts, unit),
+            PowerUnit::Kilowatt => format!("{:.3} {}", self.value_in(unit), unit),
+        }
+    }
+}
+
+impl Display for Power {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format(PowerUnit::Watt))
+    }
+}
+
+// corrects a sensor's raw readings: `raw * scale + offset`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct Calibration {
+    pub offset: f32,
+    pub scale: f32,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            offset: 0.0,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Calibration {
+    pub fn offset(offset: f32) -> Self {
+        Self {
+            offset,
+            ..Self::default()
+        }
+    }
+
+    pub fn apply(&self, raw: f32) -> f32 {
+        raw * self.scale + self.offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::units::{Calibration, Power, PowerUnit, Temperature, TemperatureUnit};
+
+    #[test]
+    fn test_temperature() {
+        let boiling = Temperature::from_celsius(100.0);
+        assert_eq!(boiling.value_in(TemperatureUnit::Fahrenheit), 212.0);
+        assert_eq!(boiling.value_in(TemperatureUnit::Kelvin), 373.15);
+        assert_eq!(
+            Temperature::new(32.0, TemperatureUnit::Fahrenheit).celsius(),
+            0.0
+        );
+        assert_eq!(boiling.format(TemperatureUnit::Fahrenheit), "212.0 °F");
+        assert_eq!(Temperature::from_celsius(21.24).to_string(), "21.2 °C");
+        assert!(Temperature::from_celsius(-1.0) < Temperature::from_celsius(1.0));
+    }
+
+    #[test]
+    fn test_power() {
+        let kettle = Power::new(2.2, PowerUnit::Kilowatt);
+        assert_eq!(kettle.watts(), 2200.0);
+        assert_eq!(kettle.to_string(), "2200.0 W");
+        assert_eq!(kettle.format(PowerUnit::Kilowatt), "2.200 kW");
+    }
+
+    #[test]
+    fn test_units_from_str() {
+        for unit in ["c", "Celsius", "F", "kelvin"] {
+            assert!(unit.parse::<TemperatureUnit>().is_ok(), "{}", unit);
+        }
+        assert_eq!("kW".parse::<PowerUnit>(), Ok(PowerUnit::Kilowatt));
+        assert!("rankine".parse::<TemperatureUnit>().is_err());
+    }
+
+    #[test]
+    fn test_calibration() {
+        // reads 1.5 °C high
+        assert_eq!(Calibration::offset(-1.5).apply(22.0), 20.5);
+        let calibration = Calibration {
+            offset: 1.0,
+            scale: 0.5,
+        };
+        assert_eq!(calibration.apply(10.0), 6.0);
+        assert_eq!(Calibration::default().apply(10.0), 10.0);
+    }
+}
@@ -0,0 +1,627 @@
+use crate::devices::DeviceStatus;
+use crate::report::Timestamp;
+use crate::store;
+use s_home_proto::{AlarmState, AlarmStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{info, warn};
+
+#[derive(Error, Debug)]
+pub enum AlarmError {
+    #[error("err accessing alarm store: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("err parsing alarm store: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("no alarm '{0}'")]
+    UnknownAlarm(String),
+    #[error("alarm '{0}' is cleared")]
+    Cleared(String),
+}
+
+// what is alarming about one metric of a device, e.g. a room below 5 °C or a socket above 2000 W
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AlarmDefinition {
+    pub metric: String,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    // the largest change per minute
+    pub max_rate: Option<f32>,
+    // seconds without a fresh reading
+    pub stale_after: Option<u64>,
+}
+
+impl AlarmDefinition {
+    pub fn new(metric: &str) -> Self {
+        Self {
+            metric: metric.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_min(mut self, min: f32) -> Self {
+        self.min = Some(min);
+        self
+    }
+
+    pub fn with_max(mut self, max: f32) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    pub fn with_max_rate(mut self, per_minute: f32) -> Self {
+        self.max_rate = Some(per_minute);
+        self
+    }
+
+    pub fn with_stale_after(mut self, stale_after: Duration) -> Self {
+        self.stale_after = Some(stale_after.as_secs());
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DefinedAlarm {
+    pub room: String,
+    pub device: String,
+    pub definition: AlarmDefinition,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlarmKind {
+    Min,
+    Max,
+    Rate,
+    Stale,
+}
+
+impl Display for AlarmKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Min => write!(f, "min"),
+            Self::Max => write!(f, "max"),
+            Self::Rate => write!(f, "rate"),
+            Self::Stale => write!(f, "stale"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Alarm {
+    // "room/device/metric/kind"
+    pub id: String,
+    pub room: String,
+    pub device: String,
+    pub metric: String,
+    pub kind: AlarmKind,
+    pub state: AlarmState,
+    pub message: String,
+    pub value: Option<f32>,
+    pub raised_at: Timestamp,
+    pub changed_at: Timestamp,
+}
+
+impl Alarm {
+    pub fn is_active(&self) -> bool {
+        self.state != AlarmState::Cleared
+    }
+
+    pub fn status(&self) -> AlarmStatus {
+        AlarmStatus {
+            id: self.id.to_string(),
+            room: self.room.to_string(),
+            device: self.device.to_string(),
+            metric: self.metric.to_string(),
+            state: self.state,
+            message: self.message.to_string(),
+            value: self.value,
+            raised_at: self.raised_at.unix_millis(),
+            changed_at: self.changed_at.unix_millis(),
+        }
+    }
+}
+
+impl Display for Alarm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}: {}", self.state, self.id, self.message)
+    }
+}
+
+// what survives a restart
+#[derive(Serialize, Deserialize, Default)]
+struct Stored {
+    definitions: Vec<DefinedAlarm>,
+    alarms: BTreeMap<String, Alarm>,
+}
+
+// whether an alarm condition holds, `None` when there is nothing to tell it by
+type Verdict = Option<(bool, String, Option<f32>)>;
+
+// alarm definitions and the alarms they raised, optionally kept in a JSON file
+pub struct Alarms {
+    stored: Stored,
+    // (room, device, metric) -> the last reading and when it was taken, for rates
+    last: HashMap<(String, String, String), (f32, Instant)>,
+    // devices never read count as stale from here
+    started: Instant,
+    store: Option<PathBuf>,
+}
+
+impl Default for Alarms {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Alarms {
+    pub fn new() -> Self {
+        Self {
+            stored: Stored::default(),
+            last: HashMap::new(),
+            started: Instant::now(),
+            store: None,
+        }
+    }
+
+    // picks up the definitions and alarms saved by an earlier run, saving every change from now on
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AlarmError> {
+        let path = path.as_ref();
+        Ok(Self {
+            stored: store::read::<_, AlarmError>(path)?,
+            store: Some(path.to_path_buf()),
+            ..Self::new()
+        })
+    }
+
+    fn save(&self) -> Result<(), AlarmError> {
+        match &self.store {
+            Some(path) => store::write_atomically(path, &self.stored),
+            None => Ok(()),
+        }
+    }
+
+    // replaces the device's definition for the same metric
+    pub fn define(
+        &mut self,
+        room: &str,
+        device: &str,
+        definition: AlarmDefinition,
+    ) -> Result<(), AlarmError> {
+        self.stored.definitions.retain(|defined| {
+            defined.room != room
+                || defined.device != device
+                || defined.definition.metric != definition.metric
+        });
+        self.stored.definitions.push(DefinedAlarm {
+            room: room.to_string(),
+            device: device.to_string(),
+            definition,
+        });
+        self.save()
+    }
+
+    pub fn definitions(&self) -> &[DefinedAlarm] {
+        &self.stored.definitions
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Alarm> {
+        self.stored.alarms.get(id)
+    }
+
+    // raised or acknowledged, by id
+    pub fn active(&self) -> impl Iterator<Item = &Alarm> {
+        self.stored
+            .alarms
+            .values()
+            .filter(|alarm| alarm.is_active())
+    }
+
+    pub fn acknowledge(&mut self, id: &str) -> Result<(), AlarmError> {
+        let alarm = self
+            .stored
+            .alarms
+            .get_mut(id)
+            .ok_or_else(|| AlarmError::UnknownAlarm(id.to_string()))?;
+        match alarm.state {
+            AlarmState::Cleared => return Err(AlarmError::Cleared(id.to_string())),
+            AlarmState::Acknowledged => return Ok(()),
+            AlarmState::Raised => {
+                alarm.state = AlarmState::Acknowledged;
+                alarm.changed_at = Timestamp::now();
+                info!(alarm = %alarm, "alarm acknowledged");
+            }
+        }
+        self.save()
+    }
+
+    // definitions and alarms of renamed or moved devices follow them; `moved` tells the new
+    // (room, device) of a (room, device), none when it stayed where it was
+    pub(crate) fn relocate(
+        &mut self,
+        moved: impl Fn(&str, &str) -> Option<(String, String)>,
+    ) -> Result<(), AlarmError> {
+        let mut changed = false;
+        for defined in self.stored.definitions.iter_mut() {
+            if let Some((room, device)) = moved(&defined.room, &defined.device) {
+                (defined.room, defined.device) = (room, device);
+                changed = true;
+            }
+        }
+        let ids: Vec<String> = self.stored.alarms.keys().cloned().collect();
+        for id in ids {
+            let alarm = &self.stored.alarms[&id];
+            let Some((room, device)) = moved(&alarm.room, &alarm.device) else {
+                continue;
+            };
+            let mut alarm = self.stored.alarms.remove(&id).unwrap();
+            alarm.id = format!("{}/{}/{}/{}", room, device, alarm.metric, alarm.kind);
+            (alarm.room, alarm.device) = (room, device);
+            self.stored.alarms.insert(alarm.id.to_string(), alarm);
+            changed = true;
+        }
+        self.last = std::mem::take(&mut self.last)
+            .into_iter()
+            .map(|((room, device, metric), last)| {
+                let (room, device) = moved(&room, &device).unwrap_or((room, device));
+                ((room, device, metric), last)
+            })
+            .collect();
+        match changed {
+            true => self.save(),
+            false => Ok(()),
+        }
+    }
+
+    // definitions and alarms of removed devices go with them; `gone` tells whether a
+    // (room, device) was removed
+    pub(crate) fn forget(&mut self, gone: impl Fn(&str, &str) -> bool) -> Result<(), AlarmError> {
+        let before = (self.stored.definitions.len(), self.stored.alarms.len());
+        self.stored
+            .definitions
+            .retain(|defined| !gone(&defined.room, &defined.device));
+        self.stored
+            .alarms
+            .retain(|_, alarm| !gone(&alarm.room, &alarm.device));
+        self.last.retain(|(room, device, _), _| !gone(room, device));
+        match before != (self.stored.definitions.len(), self.stored.alarms.len()) {
+            true => self.save(),
+            false => Ok(()),
+        }
+    }
+
+    // checks every definition against the (room, device, status) of the devices, returning the
+    // alarms which were raised or cleared
+    pub fn evaluate(
+        &mut self,
+        statuses: &[(String, String, DeviceStatus)],
+    ) -> Result<Vec<Alarm>, AlarmError> {
+        let mut changed = vec![];
+        for defined in self.stored.definitions.clone() {
+            let Some((_, _, status)) = statuses
+                .iter()
+                .find(|(room, device, _)| *room == defined.room && *device == defined.device)
+            else {
+                continue;
+            };
+            for (kind, verdict) in self.verdicts(&defined, status) {
+                if let Some(alarm) = self.apply(&defined, kind, verdict) {
+                    changed.push(alarm);
+                }
+            }
+        }
+        if !changed.is_empty() {
+            self.save()?;
+        }
+        Ok(changed)
+    }
+
+    fn verdicts(
+        &mut self,
+        defined: &DefinedAlarm,
+        status: &DeviceStatus,
+    ) -> Vec<(AlarmKind, Verdict)> {
+        let definition = &defined.definition;
+        let metric = definition.metric.as_str();
+        // readings of devices never read are defaults, nothing to judge
+        let value = match status.updated() {
+            Some(updated) => status
+                .readings()
+                .iter()
+                .find(|(m, _)| m == metric)
+                .map(|(_, value)| (*value, updated)),
+            None => None,
+        };
+
+        let mut verdicts = vec![];
+        if let Some(min) = definition.min {
+            verdicts.push((
+                AlarmKind::Min,
+                value.map(|(v, _)| (v < min, format!("{} {} below {}", metric, v, min), Some(v))),
+            ));
+        }
+        if let Some(max) = definition.max {
+            verdicts.push((
+                AlarmKind::Max,
+                value.map(|(v, _)| (v > max, format!("{} {} above {}", metric, v, max), Some(v))),
+            ));
+        }
+        if let Some(max_rate) = definition.max_rate {
+            let key = (
+                defined.room.to_string(),
+                defined.device.to_string(),
+                metric.to_string(),
+            );
+            let verdict = match (value, self.last.get(&key)) {
+                // the same reading as last time tells nothing new
+                (Some((v, at)), Some((last, last_at))) if at > *last_at => {
+                    let minutes = at.duration_since(*last_at).as_secs_f32() / 60.0;
+                    let rate = (v - last).abs() / minutes;
+                    Some((
+                        rate > max_rate,
+                        format!(
+                            "{} changing {:.2} per minute, more than {}",
+                            metric, rate, max_rate
+                        ),
+                        Some(v),
+                    ))
+                }
+                _ => None,
+            };
+            if let Some((v, at)) = value {
+                self.last.insert(key, (v, at));
+            }
+            verdicts.push((AlarmKind::Rate, verdict));
+        }
+        if let Some(stale_after) = definition.stale_after {
+            let age = match value {
+                Some((_, updated)) => updated.elapsed(),
+                None => self.started.elapsed(),
+            };
+            verdicts.push((
+                AlarmKind::Stale,
+                Some((
+                    age.as_secs() > stale_after,
+                    format!(
+                        "no {} for {}s, more than {}s",
+                        metric,
+                        age.as_secs(),
+                        stale_after
+                    ),
+                    None,
+                )),
+            ));
+        }
+        verdicts
+    }
+
+    fn apply(
+        &mut self,
+        defined: &DefinedAlarm,
+        kind: AlarmKind,
+        verdict: Verdict,
+    ) -> Option<Alarm> {
+        let (holds, message, value) = verdict?;
+        let id = format!(
+            "{}/{}/{}/{}",
+            defined.room, defined.device, defined.definition.metric, kind
+        );
+        let now = Timestamp::now();
+        match self.stored.alarms.get_mut(&id) {
+            Some(alarm) if alarm.is_active() => {
+                if holds {
+                    alarm.message = message;
+                    alarm.value = value;
+                    return None;
+                }
+                alarm.state = AlarmState::Cleared;
+                alarm.changed_at = now;
+                info!(alarm = %alarm, "alarm cleared");
+                Some(alarm.clone())
+            }
+            _ if holds => {
+                let alarm = Alarm {
+                    id: id.to_string(),
+                    room: defined.room.to_string(),
+                    device: defined.device.to_string(),
+                    metric: defined.definition.metric.to_string(),
+                    kind,
+                    state: AlarmState::Raised,
+                    message,
+                    value,
+                    raised_at: now,
+                    changed_at: now,
+                };
+                warn!(alarm = %alarm, "alarm raised");
+                self.stored.alarms.insert(id, alarm.clone());
+                Some(alarm)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alarms::{AlarmDefinition, AlarmError, AlarmKind, Alarms};
+    use crate::devices::DeviceStatus;
+    use s_home_proto::AlarmState;
+    use std::time::{Duration, Instant};
+
+    fn status(
+        metric: &str,
+        value: f32,
+        updated: Option<Instant>,
+    ) -> Vec<(String, String, DeviceStatus)> {
+        let status = DeviceStatus::quick_unknown("thermometer", "THRM");
+        let status = match updated {
+            Some(updated) => status.with_readings(&[(metric, value)], updated),
+            None => status,
+        };
+        vec![("hall".to_string(), "thermometer".to_string(), status)]
+    }
+
+    #[test]
+    fn test_min_max() {
+        let mut alarms = Alarms::new();
+        alarms
+            .define(
+                "hall",
+                "thermometer",
+                AlarmDefinition::new("temperature")
+                    .with_min(5.0)
+                    .with_max(30.0),
+            )
+            .unwrap();
+
+        // never read, the default reading means nothing
+        assert!(alarms
+            .evaluate(&status("temperature", 0.0, None))
+            .unwrap()
+            .is_empty());
+
+        let changed = alarms
+            .evaluate(&status("temperature", 3.5, Some(Instant::now())))
+            .unwrap();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].id, "hall/thermometer/temperature/min");
+        assert_eq!(changed[0].kind, AlarmKind::Min);
+        assert_eq!(
+            changed[0].to_string(),
+            "[RAISED] hall/thermometer/temperature/min: temperature 3.5 below 5"
+        );
+
+        // still cold, nothing new
+        assert!(alarms
+            .evaluate(&status("temperature", 3.0, Some(Instant::now())))
+            .unwrap()
+            .is_empty());
+        assert_eq!(alarms.active().next().unwrap().value, Some(3.0));
+
+        let changed = alarms
+            .evaluate(&status("temperature", 20.0, Some(Instant::now())))
+            .unwrap();
+        assert_eq!(changed[0].state, AlarmState::Cleared);
+        assert_eq!(alarms.active().count(), 0);
+    }
+
+    #[test]
+    fn test_acknowledge() {
+        let mut alarms = Alarms::new();
+        alarms
+            .define(
+                "hall",
+                "thermometer",
+                AlarmDefinition::new("temperature").with_max(30.0),
+            )
+            .unwrap();
+        alarms
+            .evaluate(&status("temperature", 35.0, Some(Instant::now())))
+            .unwrap();
+
+        let id = "hall/thermometer/temperature/max";
+        assert!(matches!(
+            alarms.acknowledge("nope"),
+            Err(AlarmError::UnknownAlarm(_))
+        ));
+        alarms.acknowledge(id).unwrap();
+        assert_eq!(alarms.get(id).unwrap().state, AlarmState::Acknowledged);
+        // acknowledged alarms stay active until cleared
+        assert_eq!(alarms.active().count(), 1);
+
+        alarms
+            .evaluate(&status("temperature", 20.0, Some(Instant::now())))
+            .unwrap();
+        assert!(matches!(
+            alarms.acknowledge(id),
+            Err(AlarmError::Cleared(_))
+        ));
+    }
+
+    #[test]
+    fn test_rate_and_stale() {
+        let mut alarms = Alarms::new();
+        alarms
+            .define(
+                "hall",
+                "thermometer",
+                AlarmDefinition::new("temperature")
+                    .with_max_rate(1.0)
+                    .with_stale_after(Duration::from_secs(60)),
+            )
+            .unwrap();
+
+        let earlier = Instant::now() - Duration::from_secs(120);
+        assert!(alarms
+            .evaluate(&status("temperature", 20.0, Some(earlier)))
+            .unwrap()
+            .iter()
+            .all(|alarm| alarm.kind == AlarmKind::Stale));
+        assert!(alarms
+            .get("hall/thermometer/temperature/stale")
+            .unwrap()
+            .is_active());
+
+        // 10 degrees in two minutes
+        let changed = alarms
+            .evaluate(&status("temperature", 30.0, Some(Instant::now())))
+            .unwrap();
+        let kinds: Vec<(AlarmKind, AlarmState)> = changed
+            .iter()
+            .map(|alarm| (alarm.kind, alarm.state))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (AlarmKind::Rate, AlarmState::Raised),
+                (AlarmKind::Stale, AlarmState::Cleared)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_persistence() {
+        let path = std::env::temp_dir().join(format!("alarms-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut alarms = Alarms::load(&path).unwrap();
+        alarms
+            .define(
+                "hall",
+                "thermometer",
+                AlarmDefinition::new("temperature").with_min(5.0),
+            )
+            .unwrap();
+        alarms
+            .evaluate(&status("temperature", 1.0, Some(Instant::now())))
+            .unwrap();
+        alarms
+            .acknowledge("hall/thermometer/temperature/min")
+            .unwrap();
+
+        let restarted = Alarms::load(&path).unwrap();
+        assert_eq!(restarted.definitions(), alarms.definitions());
+        assert_eq!(
+            restarted
+                .get("hall/thermometer/temperature/min")
+                .unwrap()
+                .state,
+            AlarmState::Acknowledged
+        );
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        assert!(!std::path::Path::new(&tmp).exists());
+
+        // a store cut short is refused rather than taken for an empty one
+        let saved = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, &saved[..saved.len() / 2]).unwrap();
+        assert!(matches!(Alarms::load(&path), Err(AlarmError::Parse(_))));
+        std::fs::write(&path, "not json").unwrap();
+        assert!(matches!(Alarms::load(&path), Err(AlarmError::Parse(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
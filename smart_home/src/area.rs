@@ -0,0 +1,112 @@
+pub use s_home_proto::{AreaKind, AreaSummary};
+use std::fmt::Write as _;
+
+// areas and rooms are addressed by paths, e.g. "floor1/east/kitchen"; the home itself is ""
+static SEPARATOR: char = '/';
+
+// the path of the area right above, "" when that is the home
+pub fn parent(path: &str) -> &str {
+    path.rsplit_once(SEPARATOR)
+        .map(|(parent, _)| parent)
+        .unwrap_or("")
+}
+
+// whether `path` is somewhere below `area`, not `area` itself
+pub fn is_below(path: &str, area: &str) -> bool {
+    if area.is_empty() {
+        return !path.is_empty();
+    }
+    path.strip_prefix(area)
+        .is_some_and(|rest| rest.starts_with(SEPARATOR))
+}
+
+// no empty names, e.g. "floor1//kitchen" or "floor1/"
+pub fn is_valid(path: &str) -> bool {
+    !path.is_empty() && path.split(SEPARATOR).all(|name| !name.is_empty())
+}
+
+// the summary and every area below it, one line each
+pub fn format_summary(summary: &AreaSummary) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "AREA '{}' ({}) SUMMARY:",
+        summary.path,
+        summary.kind.to_string().to_uppercase()
+    );
+    format_totals(&mut out, summary, 1);
+    out
+}
+
+fn format_totals(out: &mut String, summary: &AreaSummary, depth: usize) {
+    let indent = "\t".repeat(depth);
+    if depth > 1 {
+        let _ = writeln!(
+            out,
+            "{}AREA '{}' ({}):",
+            "\t".repeat(depth - 1),
+            summary.path,
+            summary.kind.to_string().to_uppercase()
+        );
+    }
+    let _ = writeln!(
+        out,
+        "{}rooms: {}, devices: {}, faulty: {}, power: {:.1} W, energy: {:.3} kWh",
+        indent, summary.rooms, summary.devices, summary.faulty, summary.power, summary.energy
+    );
+    for area in summary.areas.iter() {
+        format_totals(out, area, depth + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::area::{format_summary, is_below, is_valid, parent, AreaKind, AreaSummary};
+
+    #[test]
+    fn test_paths() {
+        assert_eq!(parent("floor1/east/kitchen"), "floor1/east");
+        assert_eq!(parent("kitchen"), "");
+
+        assert!(is_below("floor1/kitchen", "floor1"));
+        assert!(is_below("floor1/east/kitchen", "floor1"));
+        assert!(is_below("kitchen", ""));
+        assert!(!is_below("floor10/kitchen", "floor1"));
+        assert!(!is_below("floor1", "floor1"));
+
+        assert!(is_valid("floor1/kitchen"));
+        for path in ["", "/kitchen", "floor1/", "floor1//kitchen"] {
+            assert!(!is_valid(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_format_summary() {
+        let summary = AreaSummary {
+            path: "floor1".to_string(),
+            kind: AreaKind::Floor,
+            rooms: 2,
+            devices: 3,
+            faulty: 1,
+            power: 120.5,
+            energy: 0.0,
+            areas: vec![AreaSummary {
+                path: "floor1/east".to_string(),
+                kind: AreaKind::Zone,
+                rooms: 1,
+                devices: 1,
+                faulty: 0,
+                power: 60.0,
+                energy: 0.25,
+                areas: vec![],
+            }],
+        };
+        assert_eq!(
+            format_summary(&summary),
+            "AREA 'floor1' (FLOOR) SUMMARY:\n\
+             \trooms: 2, devices: 3, faulty: 1, power: 120.5 W, energy: 0.000 kWh\n\
+             \tAREA 'floor1/east' (ZONE):\n\
+             \t\trooms: 1, devices: 1, faulty: 0, power: 60.0 W, energy: 0.250 kWh\n"
+        );
+    }
+}
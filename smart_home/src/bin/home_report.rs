@@ -0,0 +1,85 @@
+use smart_home::config::HomeConfig;
+use smart_home::dashboard::render_html;
+use smart_home::history::ReadingHistory;
+use smart_home::report::{Report, ReportFormat};
+use std::fmt::Display;
+use std::process::exit;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::warn;
+
+static USAGE: &str =
+    "usage: HOME_CONFIG=<path> [REPORT_FORMAT=html|json|table|markdown|prometheus] \
+[REPORT_SAMPLES=<count>] [REPORT_INTERVAL_MS=<millis>] home_report";
+
+// what the report is rendered as
+enum Output {
+    Html,
+    Report(ReportFormat),
+}
+
+fn usage(err: impl Display) -> ! {
+    eprintln!("{}\n{}", err, USAGE);
+    exit(2)
+}
+
+// the value of an optional variable, `default` when it is unset
+fn var<T: FromStr>(name: &str, default: T) -> T
+where
+    T::Err: Display,
+{
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|err| usage(format!("bad {} '{}': {}", name, value, err))),
+        Err(_) => default,
+    }
+}
+
+// reads every device of a home a few times and prints a report of it, e.g.
+//   HOME_CONFIG=home.toml REPORT_FORMAT=html home_report > home.html
+#[tokio::main]
+async fn main() {
+    s_home_proto::logging::init();
+
+    let path = std::env::var("HOME_CONFIG").unwrap_or_else(|_| usage("HOME_CONFIG is not set"));
+    // html unless given, or any of the report formats, e.g. json, table, markdown
+    let output = match std::env::var("REPORT_FORMAT") {
+        Ok(format) if format.eq_ignore_ascii_case("html") => Output::Html,
+        Ok(format) => Output::Report(
+            format
+                .parse()
+                .unwrap_or_else(|err| usage(format!("bad REPORT_FORMAT: {}", err))),
+        ),
+        Err(_) => Output::Html,
+    };
+    // the sparklines of the html report are drawn from these samples
+    let samples: usize = var("REPORT_SAMPLES", 10);
+    let interval = Duration::from_millis(var("REPORT_INTERVAL_MS", 1000));
+
+    let mut home = match HomeConfig::load(&path).and_then(|config| config.build()) {
+        Ok(home) => home,
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(1)
+        }
+    };
+
+    let history = ReadingHistory::with_capacity(samples);
+    for sample in 0..samples.max(1) {
+        if sample > 0 {
+            tokio::time::sleep(interval).await;
+        }
+        for (room, device, err) in home.refresh().await {
+            warn!(room = room.as_str(), device = device.as_str(), %err, "err reading device");
+        }
+        history.sample_home(&home);
+    }
+
+    let report = home.report();
+    let out = match output {
+        Output::Html => render_html(&report, &history),
+        Output::Report(format) => report.render(format),
+    };
+    print!("{}", out);
+}
@@ -0,0 +1,383 @@
+use crate::store;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+// (room, device)
+type SocketKey = (String, String);
+
+#[derive(Error, Debug)]
+pub enum BudgetError {
+    #[error("err accessing power budget store: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("err parsing power budget store: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+// a socket switched off by the budget, as kept in the store
+#[derive(Serialize, Deserialize)]
+struct ShedSocket {
+    room: String,
+    device: String,
+    watts: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadAction {
+    // switched off to get under the budget
+    Shed,
+    // switched back on once there was room for it again
+    Restore,
+}
+
+impl Display for LoadAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Shed => write!(f, "shed"),
+            Self::Restore => write!(f, "restore"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadDecision {
+    pub room: String,
+    pub device: String,
+    pub action: LoadAction,
+    // what the socket draws, or drew when it was shed
+    pub watts: f32,
+    // the total once the decision is carried out
+    pub load: f32,
+}
+
+impl Display for LoadDecision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}/{} ({:.1} W), load {:.1} W",
+            self.action, self.room, self.device, self.watts, self.load
+        )
+    }
+}
+
+// the most power the sockets of a home may draw together; over it sockets are switched off
+// lowest priority first, and back on highest priority first once they fit again
+pub struct PowerBudget {
+    // watts
+    limit: f32,
+    // how far under the limit a restored socket has to keep the load, against flapping
+    restore_margin: f32,
+    // only sockets with a priority are ever switched, higher ones are kept on longer
+    priorities: HashMap<SocketKey, u32>,
+    // sockets switched off by the budget and what they drew then
+    shed: BTreeMap<SocketKey, f32>,
+    // where the shed sockets are kept, so they are switched back on after a restart
+    store: Option<PathBuf>,
+}
+
+impl PowerBudget {
+    pub fn new(limit_watts: f32) -> Self {
+        Self {
+            limit: limit_watts,
+            restore_margin: limit_watts / 10.0,
+            priorities: HashMap::new(),
+            shed: BTreeMap::new(),
+            store: None,
+        }
+    }
+
+    // picks up the sockets an earlier run shed, saving them from now on
+    pub fn with_store(mut self, path: impl AsRef<Path>) -> Result<Self, BudgetError> {
+        let path = path.as_ref();
+        let stored: Vec<ShedSocket> = store::read::<_, BudgetError>(path)?;
+        self.shed = stored
+            .into_iter()
+            .map(|socket| ((socket.room, socket.device), socket.watts))
+            .collect();
+        self.store = Some(path.to_path_buf());
+        Ok(self)
+    }
+
+    pub(crate) fn save(&self) -> Result<(), BudgetError> {
+        let Some(path) = &self.store else {
+            return Ok(());
+        };
+        let stored: Vec<ShedSocket> = self
+            .shed
+            .iter()
+            .map(|((room, device), watts)| ShedSocket {
+                room: room.to_string(),
+                device: device.to_string(),
+                watts: *watts,
+            })
+            .collect();
+        store::write_atomically(path, &stored)
+    }
+
+    pub fn with_priority(mut self, room: &str, device: &str, priority: u32) -> Self {
+        self.set_priority(room, device, priority);
+        self
+    }
+
+    pub fn with_restore_margin(mut self, watts: f32) -> Self {
+        self.restore_margin = watts;
+        self
+    }
+
+    pub fn set_priority(&mut self, room: &str, device: &str, priority: u32) {
+        self.priorities
+            .insert((room.to_string(), device.to_string()), priority);
+    }
+
+    // priorities and shed sockets follow renamed or moved sockets; `moved` tells the new
+    // (room, device) of a (room, device), none when it stayed where it was
+    pub(crate) fn relocate(
+        &mut self,
+        moved: impl Fn(&str, &str) -> Option<(String, String)>,
+    ) -> Result<(), BudgetError> {
+        // only the shed sockets are stored
+        let changed = self
+            .shed
+            .keys()
+            .any(|(room, device)| moved(room, device).is_some());
+        let relocated = |key: SocketKey| moved(&key.0, &key.1).unwrap_or(key);
+        self.priorities = std::mem::take(&mut self.priorities)
+            .into_iter()
+            .map(|(key, priority)| (relocated(key), priority))
+            .collect();
+        self.shed = std::mem::take(&mut self.shed)
+            .into_iter()
+            .map(|(key, watts)| (relocated(key), watts))
+            .collect();
+        match changed {
+            true => self.save(),
+            false => Ok(()),
+        }
+    }
+
+    // removed sockets are neither switched nor waited for any more
+    pub(crate) fn forget(&mut self, gone: impl Fn(&str, &str) -> bool) -> Result<(), BudgetError> {
+        self.priorities
+            .retain(|(room, device), _| !gone(room, device));
+        let before = self.shed.len();
+        self.shed.retain(|(room, device), _| !gone(room, device));
+        match before != self.shed.len() {
+            true => self.save(),
+            false => Ok(()),
+        }
+    }
+
+    pub fn limit(&self) -> f32 {
+        self.limit
+    }
+
+    pub fn is_shed(&self, room: &str, device: &str) -> bool {
+        self.shed
+            .contains_key(&(room.to_string(), device.to_string()))
+    }
+
+    // (room, device) of the sockets switched off by the budget
+    pub fn shed(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.shed
+            .keys()
+            .map(|(room, device)| (room.as_str(), device.as_str()))
+    }
+
+    // what the sockets draw together, leaving out the ones switched off by the budget
+    pub fn load(&self, loads: &[(String, String, f32)]) -> f32 {
+        loads
+            .iter()
+            .filter(|(room, device, _)| !self.is_shed(room, device))
+            .map(|(_, _, watts)| watts)
+            .sum()
+    }
+
+    // decides which sockets to switch given what every socket of the home draws, assuming
+    // the decisions get carried out; see `undo` for those which could not be. Shed sockets
+    // missing from `loads`, e.g. unreadable for now, stay shed
+    pub fn plan(&mut self, loads: &[(String, String, f32)]) -> Vec<LoadDecision> {
+        let mut load = self.load(loads);
+        let mut decisions = vec![];
+        if load > self.limit {
+            let mut candidates: Vec<(u32, &String, &String, f32)> = loads
+                .iter()
+                .filter(|(room, device, _)| !self.is_shed(room, device))
+                .filter_map(|(room, device, watts)| {
+                    let priority = self
+                        .priorities
+                        .get(&(room.to_string(), device.to_string()))?;
+                    Some((*priority, room, device, *watts))
+                })
+                .collect();
+            candidates.sort_by(|a, b| (a.0, a.1, a.2).cmp(&(b.0, b.1, b.2)));
+            for (_, room, device, watts) in candidates {
+                if load <= self.limit {
+                    break;
+                }
+                load -= watts;
+                decisions.push(LoadDecision {
+                    room: room.to_string(),
+                    device: device.to_string(),
+                    action: LoadAction::Shed,
+                    watts,
+                    load,
+                });
+            }
+        } else {
+            let mut candidates: Vec<(u32, &SocketKey, f32)> = self
+                .shed
+                .iter()
+                .map(|(key, watts)| (self.priorities.get(key).copied().unwrap_or(0), key, *watts))
+                .collect();
+            candidates.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+            for (_, (room, device), watts) in candidates {
+                // the more important ones come back first, nothing jumps the queue
+                if load + watts > self.limit - self.restore_margin {
+                    break;
+                }
+                load += watts;
+                decisions.push(LoadDecision {
+                    room: room.to_string(),
+                    device: device.to_string(),
+                    action: LoadAction::Restore,
+                    watts,
+                    load,
+                });
+            }
+        }
+
+        for decision in decisions.iter() {
+            let key = (decision.room.to_string(), decision.device.to_string());
+            match decision.action {
+                LoadAction::Shed => self.shed.insert(key, decision.watts),
+                LoadAction::Restore => self.shed.remove(&key),
+            };
+        }
+        decisions
+    }
+
+    // forgets a decision which could not be carried out
+    pub fn undo(&mut self, decision: &LoadDecision) {
+        let key = (decision.room.to_string(), decision.device.to_string());
+        match decision.action {
+            LoadAction::Shed => self.shed.remove(&key),
+            LoadAction::Restore => self.shed.insert(key, decision.watts),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::budget::{BudgetError, LoadAction, PowerBudget};
+
+    fn loads(sockets: &[(&str, f32)]) -> Vec<(String, String, f32)> {
+        sockets
+            .iter()
+            .map(|(device, watts)| ("kitchen".to_string(), device.to_string(), *watts))
+            .collect()
+    }
+
+    fn budget() -> PowerBudget {
+        PowerBudget::new(3000.0)
+            .with_priority("kitchen", "kettle", 1)
+            .with_priority("kitchen", "toaster", 2)
+            .with_priority("kitchen", "fridge", 10)
+    }
+
+    #[test]
+    fn test_shed() {
+        let mut budget = budget();
+        let under = loads(&[("kettle", 1000.0), ("toaster", 800.0), ("fridge", 150.0)]);
+        assert!(budget.plan(&under).is_empty());
+
+        // the oven has no priority, it is never switched
+        let over = loads(&[
+            ("kettle", 2000.0),
+            ("toaster", 800.0),
+            ("fridge", 150.0),
+            ("oven", 2200.0),
+        ]);
+        let decisions = budget.plan(&over);
+        let shed: Vec<(&str, LoadAction)> = decisions
+            .iter()
+            .map(|d| (d.device.as_str(), d.action))
+            .collect();
+        assert_eq!(
+            shed,
+            [("kettle", LoadAction::Shed), ("toaster", LoadAction::Shed)]
+        );
+        assert_eq!(decisions[1].load, 2350.0);
+        assert_eq!(
+            decisions[0].to_string(),
+            "shed kitchen/kettle (2000.0 W), load 3150.0 W"
+        );
+        assert!(budget.is_shed("kitchen", "toaster"));
+        assert!(!budget.is_shed("kitchen", "fridge"));
+
+        // switched off, whatever they still report does not count
+        assert_eq!(budget.load(&over), 2350.0);
+        // and the toaster would take it over the margin again
+        assert!(budget.plan(&over).is_empty());
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut budget = budget().with_restore_margin(500.0);
+        let mut sockets = loads(&[
+            ("kettle", 2000.0),
+            ("toaster", 800.0),
+            ("fridge", 150.0),
+            ("oven", 2500.0),
+        ]);
+        assert_eq!(budget.plan(&sockets).len(), 2);
+
+        // the toaster fits under the margin again, the kettle does not
+        sockets[3].2 = 1000.0;
+        let decisions = budget.plan(&sockets);
+        assert_eq!(decisions.len(), 1);
+        assert_eq!(decisions[0].device, "toaster");
+        assert_eq!(decisions[0].action, LoadAction::Restore);
+        assert_eq!(decisions[0].load, 1950.0);
+
+        budget.undo(&decisions[0]);
+        assert!(budget.is_shed("kitchen", "toaster"));
+
+        // the oven is gone, still no room for the kettle
+        sockets.pop();
+        let decisions = budget.plan(&sockets);
+        assert_eq!(decisions.len(), 1);
+        assert_eq!(budget.shed().collect::<Vec<_>>(), [("kitchen", "kettle")]);
+    }
+
+    #[test]
+    fn test_store() {
+        let path = std::env::temp_dir().join(format!("budget-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let over = loads(&[("kettle", 2000.0), ("toaster", 800.0), ("oven", 2500.0)]);
+
+        let mut before = budget().with_store(&path).unwrap();
+        // nothing shed moved, nothing to write
+        before.relocate(|_, _| None).unwrap();
+        assert!(!path.exists());
+        assert_eq!(before.plan(&over).len(), 2);
+        before.save().unwrap();
+        assert!(!path.with_extension("json.tmp").exists());
+
+        // a restart still knows which sockets to switch back on, even before reading them
+        let mut after = budget().with_store(&path).unwrap();
+        assert!(after.is_shed("kitchen", "kettle"));
+        let decisions = after.plan(&loads(&[("oven", 0.0)]));
+        assert_eq!(decisions.len(), 1);
+        assert_eq!(decisions[0].device, "toaster");
+        assert_eq!(decisions[0].action, LoadAction::Restore);
+
+        std::fs::write(&path, "[{\"room\": ").unwrap();
+        assert!(matches!(
+            budget().with_store(&path),
+            Err(BudgetError::Parse(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+use crate::alarms::{AlarmDefinition, AlarmError, Alarms};
+use crate::area::AreaKind;
+use crate::budget::{BudgetError, PowerBudget};
+use crate::devices::power_socket::PowerSocket;
+use crate::devices::sensor::Sensor;
+use crate::devices::thermometer::{Thermometer, ThermometerConfig};
+use crate::devices::Device;
+use crate::home::{Home, HomeUpdateError};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum HomeConfigError {
+    #[error("err reading home config: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("err parsing home config: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("bad home config: {0}")]
+    Invalid(#[from] HomeUpdateError),
+    #[error("err setting up alarms: {0}")]
+    Alarms(#[from] AlarmError),
+    #[error("err setting up power budget: {0}")]
+    Budget(#[from] BudgetError),
+}
+
+// a home and the devices in it, e.g.
+//
+//   name = "home"
+//   power_budget = 3000.0        # optional, watts all sockets may draw together
+//   budget_store = "shed.json"   # optional, sockets the budget switched off, kept across restarts
+//   [[area]]
+//   path = "floor1"
+//   kind = "floor"               # floor or zone
+//   [[room]]
+//   name = "floor1/kitchen"      # the path of the room, "kitchen" when not in an area
+//   [[room.device]]
+//   name = "kettle"
+//   kind = "socket"              # socket, thermometer or sensor
+//   dsn = "127.0.0.1:1234"
+//   device_id = "kitchen-socket" # optional, for fleet servers
+//   priority = 1                 # optional, sockets with one are shed over the budget, lowest first
+//   tags = ["appliances"]        # optional, groups spanning rooms
+//   [[room.device.alarm]]
+//   metric = "power"
+//   max = 2000.0                 # also min, max_rate (per minute) and stale_after (seconds)
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HomeConfig {
+    pub name: String,
+    // where raised alarms are kept across restarts
+    pub alarm_store: Option<PathBuf>,
+    // watts
+    pub power_budget: Option<f32>,
+    // where sockets shed by the power budget are kept, to be switched back on after a restart
+    pub budget_store: Option<PathBuf>,
+    // added in order, parents first
+    #[serde(default, rename = "area")]
+    pub areas: Vec<AreaConfig>,
+    #[serde(default, rename = "room")]
+    pub rooms: Vec<RoomConfig>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AreaConfig {
+    pub path: String,
+    pub kind: AreaKind,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RoomConfig {
+    pub name: String,
+    #[serde(default, rename = "device")]
+    pub devices: Vec<DeviceConfig>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceConfig {
+    pub name: String,
+    pub kind: DeviceKind,
+    pub dsn: String,
+    pub device_id: Option<String>,
+    // sockets only, see `PowerBudget`
+    pub priority: Option<u32>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // thermometers only
+    pub thermometer: Option<ThermometerConfig>,
+    #[serde(default, rename = "alarm")]
+    pub alarms: Vec<AlarmDefinition>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceKind {
+    Socket,
+    Thermometer,
+    Sensor,
+}
+
+impl DeviceConfig {
+    pub fn build(&self) -> Box<dyn Device> {
+        let id = self.device_id.as_deref();
+        match self.kind {
+            DeviceKind::Socket => {
+                let socket = PowerSocket::new(&self.name, &self.dsn);
+                Box::new(match id {
+                    Some(id) => socket.with_device_id(id),
+                    None => socket,
+                })
+            }
+            DeviceKind::Thermometer => {
+                let thermometer = Thermometer::new(&self.name, &self.dsn)
+                    .with_config(self.thermometer.unwrap_or_default());
+                Box::new(match id {
+                    Some(id) => thermometer.with_device_id(id),
+                    None => thermometer,
+                })
+            }
+            DeviceKind::Sensor => {
+                let sensor = Sensor::new(&self.name, &self.dsn);
+                Box::new(match id {
+                    Some(id) => sensor.with_device_id(id),
+                    None => sensor,
+                })
+            }
+        }
+    }
+}
+
+impl HomeConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, HomeConfigError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(s: &str) -> Result<Self, HomeConfigError> {
+        Ok(toml::from_str(s)?)
+    }
+
+    // rooms and devices named twice are rejected like they are by `Home` itself
+    pub fn build(&self) -> Result<Home, HomeConfigError> {
+        let alarms = match &self.alarm_store {
+            Some(path) => Alarms::load(path)?,
+            None => Alarms::new(),
+        };
+        let mut home = Home::new(&self.name).with_alarms(alarms);
+        if let Some(limit) = self.power_budget {
+            let mut budget = PowerBudget::new(limit);
+            if let Some(path) = &self.budget_store {
+                budget = budget.with_store(path)?;
+            }
+            for room in self.rooms.iter() {
+                for device in room.devices.iter() {
+                    if let Some(priority) = device.priority {
+                        budget.set_priority(&room.name, &device.name, priority);
+                    }
+                }
+            }
+            home = home.with_power_budget(budget);
+        }
+        for area in self.areas.iter() {
+            home.add_area(&area.path, area.kind)?;
+        }
+        for room in self.rooms.iter() {
+            home.add_room(&room.name)?;
+            for device in room.devices.iter() {
+                home.add_device(&room.name, &device.name, device.build())?;
+                for tag in device.tags.iter() {
+                    home.tag_device(&room.name, &device.name, tag)?;
+                }
+                for alarm in device.alarms.iter() {
+                    home.alarms_mut()
+                        .define(&room.name, &device.name, alarm.clone())?;
+                }
+            }
+        }
+        Ok(home)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{DeviceKind, HomeConfig, HomeConfigError};
+    use s_home_proto::TemperatureUnit;
+
+    static CONFIG: &str = r#"
+        name = "home"
+        power_budget = 3000.0
+
+        [[room]]
+        name = "kitchen"
+
+        [[room.device]]
+        name = "kettle"
+        kind = "socket"
+        dsn = "127.0.0.1:1234"
+        priority = 1
+        tags = ["appliances"]
+
+        [[room.device.alarm]]
+        metric = "power"
+        max = 2000.0
+
+        [[room.device]]
+        name = "thermometer"
+        kind = "thermometer"
+        dsn = "127.0.0.1:12345"
+        device_id = "kitchen"
+        thermometer = { unit = "Fahrenheit" }
+
+        [[area]]
+        path = "floor1"
+        kind = "floor"
+
+        [[room]]
+        name = "floor1/hall"
+    "#;
+
+    #[test]
+    fn test_parse() {
+        let config = HomeConfig::parse(CONFIG).unwrap();
+        assert_eq!(config.rooms.len(), 2);
+        let thermometer = &config.rooms[0].devices[1];
+        assert_eq!(thermometer.kind, DeviceKind::Thermometer);
+        assert_eq!(thermometer.device_id.as_deref(), Some("kitchen"));
+        assert_eq!(
+            thermometer.thermometer.as_ref().unwrap().unit,
+            TemperatureUnit::Fahrenheit
+        );
+
+        assert!(matches!(
+            HomeConfig::parse("name = \"home\"\n[[room]]\nname = \"hall\"\n[[room.device]]\nname = \"x\"\nkind = \"toaster\"\ndsn = \"\""),
+            Err(HomeConfigError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_build() {
+        let home = HomeConfig::parse(CONFIG).unwrap().build().unwrap();
+        let report = home.report();
+        assert_eq!(report.name, "home");
+        assert_eq!(report.rooms[1].name, "kitchen");
+        assert_eq!(report.rooms[1].devices[0].device_type, "PSOC");
+        assert_eq!(report.rooms[1].devices[1].device_type, "THRM");
+        let alarms = home.alarms().definitions();
+        assert_eq!(alarms.len(), 1);
+        assert_eq!(alarms[0].device, "kettle");
+        assert_eq!(alarms[0].definition.max, Some(2000.0));
+        assert_eq!(home.power_budget().unwrap().limit(), 3000.0);
+        assert_eq!(home.area_summary("floor1").unwrap().rooms, 1);
+        assert_eq!(
+            home.group("appliances"),
+            [("kitchen".to_string(), "kettle".to_string())]
+        );
+
+        // sockets shed before a restart are still known to be
+        let store = std::env::temp_dir().join(format!("shed-{}.json", std::process::id()));
+        std::fs::write(
+            &store,
+            r#"[{"room": "kitchen", "device": "kettle", "watts": 2000.0}]"#,
+        )
+        .unwrap();
+        let stored = CONFIG.replace(
+            "power_budget = 3000.0",
+            &format!("power_budget = 3000.0\nbudget_store = {:?}", store),
+        );
+        let home = HomeConfig::parse(&stored).unwrap().build().unwrap();
+        assert!(home.power_budget().unwrap().is_shed("kitchen", "kettle"));
+        std::fs::remove_file(&store).unwrap();
+
+        let twice = format!("{}\n[[room]]\nname = \"floor1/hall\"\n", CONFIG);
+        assert!(matches!(
+            HomeConfig::parse(&twice).unwrap().build(),
+            Err(HomeConfigError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::history::ReadingHistory;
+use crate::report::{ConditionState, DeviceReport, HomeReport};
+use std::fmt::Write as _;
+use std::time::Duration;
+
+static SPARKLINE_WIDTH: f32 = 120.0;
+static SPARKLINE_HEIGHT: f32 = 24.0;
+
+static STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { margin-bottom: 0; }
+.generated { color: #777; margin-top: 0.2em; }
+section { margin-top: 2em; }
+table { border-collapse: collapse; width: 100%; }
+th, td { text-align: left; padding: 0.4em 0.8em; border-bottom: 1px solid #ddd; vertical-align: middle; }
+.condition { font-weight: bold; border-radius: 0.3em; padding: 0.1em 0.5em; color: #fff; }
+.ok { background: #2e7d32; }
+.error { background: #c62828; }
+.unknown { background: #757575; }
+.reason { color: #c62828; font-size: 0.9em; }
+.empty { color: #777; font-style: italic; }
+svg { vertical-align: middle; margin-left: 0.5em; }
+polyline { fill: none; stroke: #1565c0; stroke-width: 1.5; }
+";
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// e.g. "just now", "42s ago", "5m ago", "3h ago", "2d ago"
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0 => "just now".to_string(),
+        1..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+// an inline SVG line over the values, nothing when there are too few to draw a line
+pub fn sparkline(values: &[f32]) -> String {
+    if values.len() < 2 {
+        return String::new();
+    }
+    let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    // a flat line through the middle when nothing changed
+    let range = if max > min { max - min } else { 1.0 };
+    let offset = if max > min { 0.0 } else { 0.5 };
+    let step = SPARKLINE_WIDTH / (values.len() - 1) as f32;
+    let points: Vec<String> = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let y = 1.0 - ((value - min) / range + offset);
+            format!(
+                "{:.1},{:.1}",
+                i as f32 * step,
+                1.0 + y * (SPARKLINE_HEIGHT - 2.0)
+            )
+        })
+        .collect();
+    format!(
+        "<svg width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\"><polyline points=\"{}\"/></svg>",
+        points.join(" "),
+        w = SPARKLINE_WIDTH,
+        h = SPARKLINE_HEIGHT
+    )
+}
+
+fn condition_class(condition: ConditionState) -> &'static str {
+    match condition {
+        ConditionState::Ok => "ok",
+        ConditionState::Error => "error",
+        ConditionState::Unknown => "unknown",
+    }
+}
+
+fn device_row(out: &mut String, room: &str, device: &DeviceReport, history: &ReadingHistory) {
+    let _ = write!(
+        out,
+        "<tr><td>{}</td><td>{}</td><td><span class=\"condition {}\">{}</span>",
+        escape(&device.name),
+        escape(&device.device_type),
+        condition_class(device.condition),
+        device.condition
+    );
+    if let Some(err) = &device.error {
+        let _ = write!(out, "<div class=\"reason\">{}</div>", escape(err));
+    }
+    let _ = write!(out, "</td><td>{}", escape(&device.status));
+    for reading in device.readings.iter() {
+        let values = history.values(room, &device.name, &reading.metric);
+        if values.len() > 1 {
+            let _ = write!(
+                out,
+                "<div title=\"{}\">{} {}</div>",
+                escape(&reading.metric),
+                escape(&reading.metric),
+                sparkline(&values)
+            );
+        }
+    }
+    let updated = match device.updated {
+        Some(updated) => format!(
+            "<span title=\"{}\">{}</span>",
+            updated,
+            format_age(updated.age())
+        ),
+        None => "never".to_string(),
+    };
+    let _ = writeln!(out, "</td><td>{}</td></tr>", updated);
+}
+
+// a static page with no external resources, rooms as sections and a row per device
+pub fn render_html(report: &HomeReport, history: &ReadingHistory) -> String {
+    let title = escape(&report.name);
+    let mut out = String::new();
+    let _ = writeln!(out, "<!DOCTYPE html>");
+    let _ = writeln!(
+        out,
+        "<html lang=\"en\"><head><meta charset=\"utf-8\"><title>{}</title>",
+        title
+    );
+    let _ = writeln!(out, "<style>\n{}</style></head><body>", STYLE);
+    let _ = writeln!(out, "<h1>{}</h1>", title);
+    let _ = writeln!(
+        out,
+        "<p class=\"generated\">generated {}</p>",
+        report.generated
+    );
+    if report.rooms.is_empty() {
+        let _ = writeln!(out, "<p class=\"empty\">no rooms</p>");
+    }
+    for room in report.rooms.iter() {
+        let _ = writeln!(out, "<section><h2>{}</h2>", escape(&room.name));
+        if room.devices.is_empty() {
+            let _ = writeln!(out, "<p class=\"empty\">no devices</p></section>");
+            continue;
+        }
+        let _ = writeln!(
+            out,
+            "<table><tr><th>device</th><th>type</th><th>condition</th><th>reading</th><th>updated</th></tr>"
+        );
+        for device in room.devices.iter() {
+            device_row(&mut out, &room.name, device, history);
+        }
+        let _ = writeln!(out, "</table></section>");
+    }
+    let _ = writeln!(out, "</body></html>");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dashboard::{format_age, render_html, sparkline};
+    use crate::history::ReadingHistory;
+    use crate::report::{ConditionState, DeviceReport, HomeReport, Reading, RoomReport, Timestamp};
+    use std::time::Duration;
+
+    fn device(name: &str, condition: ConditionState) -> DeviceReport {
+        DeviceReport {
+            name: name.to_string(),
+            device_type: "THRM".to_string(),
+            condition,
+            error: None,
+            status: "temperature: 21.0 °C".to_string(),
+            readings: vec![],
+            updated: None,
+        }
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(Duration::from_millis(300)), "just now");
+        assert_eq!(format_age(Duration::from_secs(42)), "42s ago");
+        assert_eq!(format_age(Duration::from_secs(300)), "5m ago");
+        assert_eq!(format_age(Duration::from_secs(3 * 3600)), "3h ago");
+        assert_eq!(format_age(Duration::from_secs(2 * 86400)), "2d ago");
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[1.0]), "");
+        let svg = sparkline(&[0.0, 10.0]);
+        assert!(svg.contains("points=\"0.0,23.0 120.0,1.0\""), "{}", svg);
+        let flat = sparkline(&[5.0, 5.0]);
+        assert!(flat.contains("points=\"0.0,12.0 120.0,12.0\""), "{}", flat);
+    }
+
+    #[test]
+    fn test_render_html() {
+        let mut broken = device("<attic>", ConditionState::Error);
+        broken.error = Some("timed out".to_string());
+        let mut hall = device("hall", ConditionState::Ok);
+        hall.readings = vec![Reading {
+            metric: "temperature".to_string(),
+            value: 21.0,
+        }];
+        hall.updated = Some(Timestamp::now());
+        let report = HomeReport {
+            name: "home".to_string(),
+            generated: Timestamp::from_unix_millis(0),
+            rooms: vec![
+                RoomReport {
+                    name: "empty".to_string(),
+                    devices: vec![],
+                },
+                RoomReport {
+                    name: "house".to_string(),
+                    devices: vec![broken, hall],
+                },
+            ],
+        };
+        let history = ReadingHistory::new();
+        for value in [20.0, 20.5, 21.0] {
+            history.record("house", "hall", "temperature", Timestamp::now(), value);
+        }
+
+        let html = render_html(&report, &history);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h2>empty</h2>\n<p class=\"empty\">no devices</p>"));
+        assert!(html.contains("&lt;attic&gt;"));
+        assert!(html.contains("<span class=\"condition error\">ERROR</span>"));
+        assert!(html.contains("<div class=\"reason\">timed out</div>"));
+        assert!(html.contains("<span class=\"condition ok\">OK</span>"));
+        assert!(html.contains("just now"));
+        assert_eq!(html.matches("<svg").count(), 1);
+        assert!(!html.contains("http"));
+    }
+}
@@ -1,24 +1,41 @@
-use s_home_proto::{DeviceRequest, Marshal, Response};
+use crate::events::DeviceEvents;
+use crate::report::DeviceReport;
+use s_home_proto::auth::Credentials;
+use s_home_proto::{
+    AddressedRequest, Codec, DeviceAction, DeviceDescription, DeviceRequest, Marshal, Response,
+    PROTOCOL_VERSION,
+};
 use std::fmt::{write, Debug, Display, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+#[cfg(feature = "tls")]
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::io;
 use tokio::net::{TcpStream, UdpSocket};
+use tracing::{debug, instrument, trace};
 
 pub mod power_socket;
+pub mod sensor;
 pub mod thermometer;
+pub mod thermostat;
 
 static UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+// a datagram may be lost or land on something that is not a device at all
+static UDP_TIMEOUT: Duration = Duration::from_secs(2);
 
 fn device_needs_update(updated: Option<Instant>) -> bool {
     updated.is_none() || updated.unwrap().lt(&(Instant::now() - UPDATE_INTERVAL))
 }
 
 #[derive(Eq, PartialEq)]
-enum DeviceCondition {
+pub(crate) enum DeviceCondition {
     Ok,
     Err(String),
     Unknown,
+    // cut its output after a fault, until reset
+    Tripped(String),
 }
 
 impl Clone for DeviceCondition {
@@ -27,6 +44,18 @@ impl Clone for DeviceCondition {
             Self::Ok => Self::Ok,
             Self::Unknown => Self::Unknown,
             Self::Err(err) => Self::Err(err.to_string()),
+            Self::Tripped(fault) => Self::Tripped(fault.to_string()),
+        }
+    }
+}
+
+impl DeviceCondition {
+    // the condition once a ping came back; a fault stays until the device is reset
+    pub(crate) fn after_ping(&self, result: &Result<(), DeviceReadError>) -> Self {
+        match (self, result) {
+            (_, Err(err)) => Self::Err(err.to_string()),
+            (Self::Tripped(fault), Ok(())) => Self::Tripped(fault.to_string()),
+            (_, Ok(())) => Self::Ok,
         }
     }
 }
@@ -37,16 +66,18 @@ impl Display for DeviceCondition {
             Self::Ok => write!(f, "OK"),
             Self::Err(err) => write!(f, "ERROR: {}", err),
             Self::Unknown => write!(f, "UNKNOWN"),
+            Self::Tripped(fault) => write!(f, "TRIPPED: {}", fault),
         }
     }
 }
 
-#[derive(Eq, PartialEq)]
+#[derive(PartialEq)]
 pub struct DeviceStatus {
     device_type: String,
     name: String,
     condition: DeviceCondition,
     status: String,
+    readings: Vec<(String, f32)>,
     updated: Option<Instant>,
 }
 
@@ -57,10 +88,47 @@ impl DeviceStatus {
             name: name.to_string(),
             condition: DeviceCondition::Unknown,
             status: "UNKNOWN".to_string(),
+            readings: vec![],
             updated: None,
         }
     }
 
+    // a device which has just been read, for checks working on readings
+    pub(crate) fn with_readings(mut self, readings: &[(&str, f32)], updated: Instant) -> Self {
+        self.condition = DeviceCondition::Ok;
+        self.readings = readings
+            .iter()
+            .map(|(metric, value)| (metric.to_string(), *value))
+            .collect();
+        self.updated = Some(updated);
+        self
+    }
+
+    pub fn device_type(&self) -> &str {
+        &self.device_type
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    // (metric, value) pairs of the last known readings
+    pub fn readings(&self) -> &[(String, f32)] {
+        &self.readings
+    }
+
+    pub(crate) fn condition(&self) -> &DeviceCondition {
+        &self.condition
+    }
+
+    pub(crate) fn updated(&self) -> Option<Instant> {
+        self.updated
+    }
+
     pub fn as_string(&self) -> String {
         format!(
             "[{}]{}\n\tcondition: {}\n\tstatus: {}\n",
@@ -71,11 +139,14 @@ impl DeviceStatus {
         )
     }
 
+    pub fn report(&self) -> DeviceReport {
+        DeviceReport::from(self)
+    }
+
     fn as_compact_string(&self) -> String {
-        let updated = if self.updated.is_none() {
-            "NEVER".to_string()
-        } else {
-            format!("{:.1}", self.updated.unwrap().elapsed().as_secs_f32())
+        let updated = match self.updated {
+            None => "NEVER".to_string(),
+            Some(updated) => format!("{:.1}", updated.elapsed().as_secs_f32()),
         };
         format!(
             "[{}]{}-{}*{} UPD: {}",
@@ -90,20 +161,170 @@ impl Debug for DeviceStatus {
     }
 }
 
+pub type DeviceActionFuture<'a> = Pin<Box<dyn Future<Output = Result<(), DeviceUpdateError>> + 'a>>;
+pub type DeviceVerifyFuture<'a> = Pin<Box<dyn Future<Output = Result<(), DeviceReadError>> + 'a>>;
+pub type DeviceRefreshFuture<'a> = DeviceVerifyFuture<'a>;
+// owns what it needs, so that it can be awaited without holding on to the device
+pub type DevicePingFuture = Pin<Box<dyn Future<Output = Result<(), DeviceReadError>>>>;
+
 pub trait Device {
     fn get_status(&self) -> DeviceStatus;
     // fn start_poll(&mut self);
+
+    // called by a room when the device is placed into it
+    fn attach_events(&mut self, _events: DeviceEvents) {}
+
+    // the home's credentials, used unless the device was given its own
+    fn attach_credentials(&mut self, _credentials: &Credentials) {}
+
+    // lets callers holding a `dyn Device` drive it, e.g. from remote commands
+    fn perform(&mut self, action: DeviceAction) -> DeviceActionFuture<'_> {
+        let device_type = self.get_status().device_type;
+        Box::pin(async move {
+            Err(DeviceUpdateError::UnsupportedAction(format!(
+                "{:?} on {}",
+                action, device_type
+            )))
+        })
+    }
+
+    // asks the device to describe itself and checks the DSN points at the right kind of device
+    fn verify(&mut self) -> DeviceVerifyFuture<'_> {
+        Box::pin(async { Ok(()) })
+    }
+
+    // fetches fresh readings, so that `get_status` reports them
+    fn refresh(&mut self) -> DeviceRefreshFuture<'_> {
+        Box::pin(async { Ok(()) })
+    }
+
+    // the cheapest request there is, to tell whether the device answers at all
+    fn ping(&self) -> DevicePingFuture {
+        Box::pin(async { Ok(()) })
+    }
+
+    // what a health check found, so that the status reports it
+    fn pinged(&mut self, _result: &Result<(), DeviceReadError>) {}
 }
 
-type DeviceRequestResult = Result<s_home_proto::Response, Box<dyn std::error::Error>>;
+pub(crate) fn check_description(
+    resp: Response,
+    device_type: &str,
+    required: &[&str],
+) -> Result<DeviceDescription, DeviceReadError> {
+    let description = unpack_description(resp)?;
+    if description.device_type != device_type {
+        return Err(DeviceReadError::WrongDevice {
+            expected: device_type.to_string(),
+            found: description.device_type,
+        });
+    }
+    check_required(description, required)
+}
 
-pub(crate) async fn make_device_tcp_request(dsn: &str, req: DeviceRequest) -> DeviceRequestResult {
-    println!("[TCP FUNC] making request: {:?}", &req);
+// like `check_description`, for clients working with any device type handling `required`
+pub(crate) fn check_capabilities(
+    resp: Response,
+    required: &[&str],
+) -> Result<DeviceDescription, DeviceReadError> {
+    check_required(unpack_description(resp)?, required)
+}
 
+pub(crate) fn check_pong(resp: Response) -> Result<(), DeviceReadError> {
+    match resp {
+        Response::Pong => Ok(()),
+        Response::Err(err_msg) => Err(DeviceReadError::ErrMakingRequest(err_msg)),
+        _ => Err(DeviceReadError::UnexpectedResponse(resp)),
+    }
+}
+
+fn unpack_description(resp: Response) -> Result<DeviceDescription, DeviceReadError> {
+    let description = match resp {
+        Response::Description(description) => description,
+        // servers predating the handshake answer with an error
+        Response::Err(err_msg) => return Err(DeviceReadError::ErrMakingRequest(err_msg)),
+        _ => return Err(DeviceReadError::UnexpectedResponse(resp)),
+    };
+    if description.protocol_version != PROTOCOL_VERSION {
+        return Err(DeviceReadError::IncompatibleProtocol(
+            description.protocol_version,
+        ));
+    }
+    Ok(description)
+}
+
+fn check_required(
+    description: DeviceDescription,
+    required: &[&str],
+) -> Result<DeviceDescription, DeviceReadError> {
+    if let Some(missing) = required.iter().find(|kind| !description.supports(kind)) {
+        return Err(DeviceReadError::MissingCapability(missing.to_string()));
+    }
+    Ok(description)
+}
+
+// how a client reaches its device
+#[derive(Clone, Default, Debug)]
+pub(crate) struct Transport {
+    pub(crate) codec: Codec,
+    pub(crate) credentials: Option<Credentials>,
+    // which of the devices served behind the DSN is meant, for fleet servers
+    pub(crate) device_id: Option<String>,
+    // TCP only
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<Arc<rustls::ClientConfig>>,
+}
+
+impl Transport {
+    // signed when there are credentials, bare otherwise; addressed when there is a device id
+    fn encode(&self, req: DeviceRequest) -> Result<Vec<u8>, s_home_proto::CodecError> {
+        match (&self.credentials, &self.device_id) {
+            (Some(credentials), device) => credentials
+                .sign_for(device.as_deref(), req)
+                .encode(self.codec),
+            (None, Some(device)) => AddressedRequest {
+                device: device.clone(),
+                request: req,
+            }
+            .encode(self.codec),
+            (None, None) => req.encode(self.codec),
+        }
+    }
+}
+
+type DeviceRequestResult = Result<s_home_proto::Response, Box<dyn std::error::Error>>;
+
+#[instrument(level = "debug", skip(transport, req), fields(request = req.kind()), err)]
+pub(crate) async fn make_device_tcp_request(
+    dsn: &str,
+    transport: &Transport,
+    req: DeviceRequest,
+) -> DeviceRequestResult {
+    let started = Instant::now();
     let stream = TcpStream::connect(dsn).await?;
-    let msg = req.marshal()?;
-    let bytes_written = stream.try_write(msg.as_bytes())?;
-    println!("[TCP FUNC] WRITTEN {} bytes", bytes_written);
+    let msg = transport.encode(req)?;
+
+    #[cfg(feature = "tls")]
+    let buf = match &transport.tls {
+        Some(config) => tls_exchange(dsn, config, stream, &msg).await?,
+        None => tcp_exchange(stream, &msg).await?,
+    };
+    #[cfg(not(feature = "tls"))]
+    let buf = tcp_exchange(stream, &msg).await?;
+
+    let resp = Response::decode(transport.codec, &buf)?;
+    debug!(
+        response = ?resp,
+        latency_us = started.elapsed().as_micros() as u64,
+        "response received"
+    );
+    Ok(resp)
+}
+
+// the server answers once and closes the connection
+async fn tcp_exchange(stream: TcpStream, msg: &[u8]) -> io::Result<Vec<u8>> {
+    let bytes_written = stream.try_write(msg)?;
+    trace!(bytes = bytes_written, "written");
 
     let mut buf = Vec::with_capacity(512);
     loop {
@@ -111,30 +332,62 @@ pub(crate) async fn make_device_tcp_request(dsn: &str, req: DeviceRequest) -> De
 
         match stream.try_read_buf(&mut buf) {
             Ok(0) => break,
-            Ok(n) => println!("[TCP FUNC] READ {} bytes", n),
+            Ok(n) => trace!(bytes = n, "read"),
             Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
-                eprintln!("got error @ try_read_buf: {}", &e);
+                trace!(err = %e, "spurious wakeup @ try_read_buf");
                 continue;
             }
-            Err(e) => return Err(e.into()),
+            Err(e) => return Err(e),
         }
     }
+    Ok(buf)
+}
 
-    let resp = Response::unmarshal(std::str::from_utf8(&buf)?).unwrap();
-    Ok(resp)
+#[cfg(feature = "tls")]
+async fn tls_exchange(
+    dsn: &str,
+    config: &Arc<rustls::ClientConfig>,
+    stream: TcpStream,
+    msg: &[u8],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // the certificate must be issued for the host part of the DSN
+    let host = dsn.rsplit_once(':').map_or(dsn, |(host, _)| host);
+    let server_name = rustls::pki_types::ServerName::try_from(host.to_string())?;
+    let mut stream = tokio_rustls::TlsConnector::from(Arc::clone(config))
+        .connect(server_name, stream)
+        .await?;
+    stream.write_all(msg).await?;
+    stream.flush().await?;
+
+    let mut buf = Vec::with_capacity(512);
+    stream.read_to_end(&mut buf).await?;
+    trace!(bytes = buf.len(), "read");
+    Ok(buf)
 }
 
-pub(crate) async fn make_device_udp_request(dsn: &str, req: DeviceRequest) -> DeviceRequestResult {
+#[instrument(level = "debug", skip(transport, req), fields(request = req.kind()), err)]
+pub(crate) async fn make_device_udp_request(
+    dsn: &str,
+    transport: &Transport,
+    req: DeviceRequest,
+) -> DeviceRequestResult {
+    let started = Instant::now();
     let socket = UdpSocket::bind("0.0.0.0:0").await?;
 
     socket.connect(dsn).await?;
-    socket.send(req.marshal().unwrap().as_bytes()).await?;
+    socket.send(&transport.encode(req)?).await?;
 
     let mut buf = [0u8; 512];
-    let bytes_read = socket.recv(&mut buf).await?;
+    let bytes_read = tokio::time::timeout(UDP_TIMEOUT, socket.recv(&mut buf)).await??;
 
-    let msg = String::from_utf8_lossy(&buf[..bytes_read]).to_string();
-    let resp = Response::unmarshal(msg.as_str())?;
+    let resp = Response::decode(transport.codec, &buf[..bytes_read])?;
+    debug!(
+        response = ?resp,
+        latency_us = started.elapsed().as_micros() as u64,
+        "response received"
+    );
     Ok(resp)
 }
 
@@ -142,6 +395,10 @@ pub(crate) async fn make_device_udp_request(dsn: &str, req: DeviceRequest) -> De
 pub enum DeviceUpdateError {
     #[error("unexpected response")]
     UnexpectedResponse(s_home_proto::Response),
+    #[error("unsupported action: {0}")]
+    UnsupportedAction(String),
+    #[error("device tripped: {0}")]
+    Tripped(s_home_proto::PowerFault),
     #[error("unknown error: {0}")]
     UnknownError(Box<dyn std::error::Error>),
 }
@@ -152,6 +409,59 @@ pub enum DeviceReadError {
     UnexpectedResponse(s_home_proto::Response),
     #[error("err making request: {0}")]
     ErrMakingRequest(String),
+    #[error("expected a '{expected}' device, found '{found}'")]
+    WrongDevice { expected: String, found: String },
+    #[error("incompatible protocol version {0}")]
+    IncompatibleProtocol(u32),
+    #[error("device does not support '{0}'")]
+    MissingCapability(String),
     #[error("unknown error: {0}")]
     UnknownError(Box<dyn std::error::Error>),
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::{check_capabilities, check_description, DeviceReadError};
+    use s_home_proto::{DeviceDescription, Response};
+
+    fn describe(device_type: &str) -> Response {
+        Response::Description(DeviceDescription::new(
+            device_type,
+            "test",
+            &["Ping", "GetPower"],
+        ))
+    }
+
+    #[test]
+    fn test_check_description() {
+        assert!(check_description(describe("PSOC"), "PSOC", &["GetPower"]).is_ok());
+
+        let wrong = check_description(describe("THRM"), "PSOC", &[]);
+        assert!(matches!(wrong, Err(DeviceReadError::WrongDevice { .. })));
+
+        let missing = check_description(describe("PSOC"), "PSOC", &["DeviceAction"]);
+        assert!(matches!(
+            missing,
+            Err(DeviceReadError::MissingCapability(_))
+        ));
+
+        let mut description = DeviceDescription::new("PSOC", "test", &[]);
+        // from before tripped sockets answered with their fault
+        description.protocol_version = 1;
+        let old = check_description(Response::Description(description), "PSOC", &[]);
+        assert!(matches!(old, Err(DeviceReadError::IncompatibleProtocol(1))));
+
+        let legacy = check_description(Response::Err("bad request".to_string()), "PSOC", &[]);
+        assert!(matches!(legacy, Err(DeviceReadError::ErrMakingRequest(_))));
+    }
+
+    #[test]
+    fn test_check_capabilities() {
+        assert!(check_capabilities(describe("THRM"), &["GetPower"]).is_ok());
+        assert!(check_capabilities(describe("ANY"), &["Ping"]).is_ok());
+        assert!(matches!(
+            check_capabilities(describe("THRM"), &["GetMeasurements"]),
+            Err(DeviceReadError::MissingCapability(_))
+        ));
+    }
+}
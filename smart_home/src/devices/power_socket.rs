@@ -1,21 +1,29 @@
 use crate::devices::{
-    device_needs_update, make_device_tcp_request, Device, DeviceCondition, DeviceStatus,
-    DeviceUpdateError,
+    check_description, check_pong, device_needs_update, make_device_tcp_request, Device,
+    DeviceActionFuture, DeviceCondition, DevicePingFuture, DeviceRefreshFuture, DeviceStatus,
+    DeviceUpdateError, DeviceVerifyFuture, Transport,
 };
-use s_home_proto::{DeviceAction, DeviceRequest, Response};
+use s_home_proto::{Codec, DeviceAction, DeviceRequest, Power, PowerFault, Response};
 use std::time::Instant;
+use tracing::{debug, instrument, warn};
 
 use super::DeviceReadError;
+use crate::events::DeviceEvents;
+use s_home_proto::auth::Credentials;
 
-static DEVICE_NAME: &str = "PSOC";
+// requests this client relies on, checked by `verify`
+static REQUIRED: [&str; 2] = ["GetPower", "DeviceAction"];
+pub(crate) static DEVICE_NAME: &str = "PSOC";
 
 pub struct PowerSocket {
     name: String,
     dsn: String,
-    power: f32,
+    transport: Transport,
+    power: Power,
     is_on: bool,
     condition: DeviceCondition,
     last_updated: Option<Instant>,
+    events: DeviceEvents,
 }
 
 impl PowerSocket {
@@ -28,13 +36,43 @@ impl PowerSocket {
         Self {
             name: name.to_string(),
             dsn: dsn.to_string(),
-            power: 0.0,
+            transport: Transport::default(),
+            power: Power::default(),
             is_on: false,
             last_updated: None,
+            events: DeviceEvents::default(),
             condition,
         }
     }
 
+    // the wire encoding used to talk to the device, JSON unless set
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.transport.codec = codec;
+        self
+    }
+
+    // signs every request with these instead of the home's credentials
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.transport.credentials = Some(credentials);
+        self
+    }
+
+    // the id of the device when the DSN is a fleet server hosting several
+    pub fn with_device_id(mut self, id: &str) -> Self {
+        self.transport.device_id = Some(id.to_string());
+        self
+    }
+
+    // talks TLS, trusting the config's CA and presenting its identity when it has one
+    #[cfg(feature = "tls")]
+    pub fn with_tls(
+        mut self,
+        tls: &s_home_proto::tls::TlsConfig,
+    ) -> Result<Self, s_home_proto::tls::TlsError> {
+        self.transport.tls = Some(tls.client_config()?);
+        Ok(self)
+    }
+
     pub async fn power_on(&mut self) -> Result<(), DeviceUpdateError> {
         self.set_power(true).await
     }
@@ -43,57 +81,114 @@ impl PowerSocket {
         self.set_power(false).await
     }
 
-    async fn set_power(&mut self, state: bool) -> Result<(), DeviceUpdateError> {
-        if self.dsn.is_empty() {
-            self.is_on = state;
-            return Ok(());
-        }
+    // clears the fault of a tripped socket, it stays off until powered on
+    pub async fn reset(&mut self) -> Result<(), DeviceUpdateError> {
+        self.send_action(DeviceAction::Reset).await?;
+        self.switched(false);
+        self.set_condition(DeviceCondition::Ok);
+        Ok(())
+    }
 
+    pub fn is_tripped(&self) -> bool {
+        matches!(self.condition, DeviceCondition::Tripped(_))
+    }
+
+    async fn set_power(&mut self, state: bool) -> Result<(), DeviceUpdateError> {
         let method = if state {
             DeviceAction::TurnOn
         } else {
             DeviceAction::TurnOff
         };
+        self.send_action(method).await?;
+        self.switched(state);
+        Ok(())
+    }
+
+    #[instrument(skip(self), fields(device = %self.name))]
+    async fn send_action(&mut self, method: DeviceAction) -> Result<(), DeviceUpdateError> {
+        if self.dsn.is_empty() {
+            return Ok(());
+        }
 
         let req = DeviceRequest::DeviceAction { method };
 
-        let result = make_device_tcp_request(self.dsn.as_str(), req).await;
+        let result = make_device_tcp_request(self.dsn.as_str(), &self.transport, req).await;
         match result {
             Err(err) => Err(DeviceUpdateError::UnknownError(err)),
             Ok(resp) => match resp {
-                Response::Ok => {
-                    self.is_on = state;
-                    Ok(())
+                Response::Ok => Ok(()),
+                Response::Fault(fault) => {
+                    self.tripped(fault);
+                    Err(DeviceUpdateError::Tripped(fault))
                 }
                 _ => {
-                    eprintln!("unexpected response: {:?}", resp);
+                    warn!(response = ?resp, "unexpected response");
                     Err(DeviceUpdateError::UnexpectedResponse(resp))
                 }
             },
         }
     }
 
-    pub async fn get_power_consumption(&mut self) -> Result<f32, DeviceReadError> {
+    // the socket cut its output, there is no load until it is reset
+    fn tripped(&mut self, fault: PowerFault) {
+        if !self.is_tripped() {
+            warn!(%fault, "socket tripped");
+        }
+        self.switched(false);
+        if self.power != Power::default() {
+            self.events.reading(DEVICE_NAME, "power", 0.0);
+        }
+        self.power = Power::default();
+        self.last_updated = Some(Instant::now());
+        self.set_condition(DeviceCondition::Tripped(fault.to_string()));
+    }
+
+    fn switched(&mut self, state: bool) {
+        if self.is_on != state {
+            self.events.power_switched(DEVICE_NAME, state);
+        }
+        self.is_on = state;
+    }
+
+    fn set_condition(&mut self, condition: DeviceCondition) {
+        if self.condition != condition {
+            self.events.condition(DEVICE_NAME, &condition);
+            self.condition = condition;
+        }
+    }
+
+    #[instrument(skip(self), fields(device = %self.name))]
+    pub async fn get_power_consumption(&mut self) -> Result<Power, DeviceReadError> {
         if !self.dsn.is_empty() && device_needs_update(self.last_updated) {
-            let request_result  = make_device_tcp_request(&self.dsn, DeviceRequest::GetPower).await;
+            let request_result =
+                make_device_tcp_request(&self.dsn, &self.transport, DeviceRequest::GetPower).await;
             let resp = if let Err(err) = request_result {
                 return Err(DeviceReadError::UnknownError(err));
             } else {
                 request_result.unwrap()
             };
             return match resp {
-                Response::Power(val) => {
-                    self.power = val;
+                Response::Power(watts) => {
+                    let power = Power::from_watts(watts);
+                    if self.power != power {
+                        self.events.reading(DEVICE_NAME, "power", watts);
+                    }
+                    self.power = power;
                     self.last_updated = Some(Instant::now());
-                    self.condition = DeviceCondition::Ok;
-                    Ok(val)
+                    self.set_condition(DeviceCondition::Ok);
+                    Ok(power)
+                }
+                // answered all the same, the socket is just off
+                Response::Fault(fault) => {
+                    self.tripped(fault);
+                    Ok(self.power)
                 }
                 Response::Err(err_msg) => {
-                    self.condition = DeviceCondition::Err(err_msg.to_string());
+                    self.set_condition(DeviceCondition::Err(err_msg.to_string()));
                     Err(DeviceReadError::ErrMakingRequest(err_msg.to_string()))
                 }
                 _ => {
-                    self.condition = DeviceCondition::Unknown;
+                    self.set_condition(DeviceCondition::Unknown);
                     Err(DeviceReadError::UnexpectedResponse(resp))
                 }
             };
@@ -109,15 +204,71 @@ impl Device for PowerSocket {
             name: self.name.to_string(),
             condition: self.condition.clone(),
             status: format!("power: {}", self.power),
+            readings: vec![("power".to_string(), self.power.watts())],
             updated: self.last_updated,
         }
     }
+
+    fn attach_events(&mut self, events: DeviceEvents) {
+        self.events = events;
+    }
+
+    fn attach_credentials(&mut self, credentials: &Credentials) {
+        self.transport
+            .credentials
+            .get_or_insert_with(|| credentials.clone());
+    }
+
+    fn refresh(&mut self) -> DeviceRefreshFuture<'_> {
+        Box::pin(async move { self.get_power_consumption().await.map(|_| ()) })
+    }
+
+    fn ping(&self) -> DevicePingFuture {
+        let dsn = self.dsn.clone();
+        let transport = self.transport.clone();
+        Box::pin(async move {
+            if dsn.is_empty() {
+                return Ok(());
+            }
+            let resp = make_device_tcp_request(&dsn, &transport, DeviceRequest::Ping)
+                .await
+                .map_err(DeviceReadError::UnknownError)?;
+            check_pong(resp)
+        })
+    }
+
+    fn pinged(&mut self, result: &Result<(), DeviceReadError>) {
+        self.set_condition(self.condition.after_ping(result));
+    }
+
+    fn verify(&mut self) -> DeviceVerifyFuture<'_> {
+        Box::pin(async move {
+            if self.dsn.is_empty() {
+                return Ok(());
+            }
+            let resp = make_device_tcp_request(&self.dsn, &self.transport, DeviceRequest::Describe)
+                .await
+                .map_err(DeviceReadError::UnknownError)?;
+            let description = check_description(resp, DEVICE_NAME, &REQUIRED)?;
+            debug!(firmware = description.firmware.as_str(), "device verified");
+            Ok(())
+        })
+    }
+
+    fn perform(&mut self, action: DeviceAction) -> DeviceActionFuture<'_> {
+        match action {
+            DeviceAction::TurnOn => Box::pin(self.power_on()),
+            DeviceAction::TurnOff => Box::pin(self.power_off()),
+            DeviceAction::Reset => Box::pin(self.reset()),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::devices::power_socket::{PowerSocket, DEVICE_NAME};
     use crate::devices::{Device, DeviceCondition, DeviceStatus};
+    use crate::events::{DeviceEvents, EventBus, EventSink, HomeEvent};
     use tokio;
 
     const NAME: &str = "test power socket";
@@ -142,10 +293,33 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_power_switched_event() {
+        let bus = EventBus::new();
+        let mut events = bus.subscribe();
+
+        let mut device = new_power_socket();
+        device.attach_events(DeviceEvents::new(EventSink::new(bus), "hall", NAME));
+        device.power_on().await.unwrap();
+        // switching to the same state is not an event
+        device.power_on().await.unwrap();
+
+        assert_eq!(
+            events.try_recv(),
+            Some(HomeEvent::PowerSwitched {
+                room: "hall".to_string(),
+                device: NAME.to_string(),
+                device_type: DEVICE_NAME.to_string(),
+                is_on: true,
+            })
+        );
+        assert_eq!(events.try_recv(), None);
+    }
+
     #[tokio::test]
     async fn test_get_power_consumption() {
         let mut device = new_power_socket();
-        assert_eq!(device.get_power_consumption().await.unwrap(), 0.0)
+        assert_eq!(device.get_power_consumption().await.unwrap().watts(), 0.0)
     }
 
     #[test]
@@ -157,7 +331,8 @@ mod tests {
             device_type: DEVICE_NAME.to_string(),
             name: NAME.to_string(),
             condition: DeviceCondition::Ok,
-            status: format!("power: {}", 0.0),
+            status: "power: 0.0 W".to_string(),
+            readings: vec![("power".to_string(), 0.0)],
             updated: None,
         };
         assert_eq!(have, want);
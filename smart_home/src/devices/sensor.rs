@@ -0,0 +1,240 @@
+use crate::devices::{
+    check_capabilities, check_pong, device_needs_update, make_device_udp_request, Device,
+    DeviceCondition, DevicePingFuture, DeviceReadError, DeviceRefreshFuture, DeviceStatus,
+    DeviceVerifyFuture, Transport,
+};
+use crate::events::DeviceEvents;
+use s_home_proto::auth::Credentials;
+use s_home_proto::{Codec, DeviceRequest, Measurement, Metric, Response};
+use std::time::Instant;
+use tracing::{debug, instrument};
+
+// requests this client relies on, checked by `verify`
+static REQUIRED: [&str; 1] = ["GetMeasurements"];
+pub(crate) static DEVICE_NAME: &str = "SENS";
+
+// any device measuring one or more metrics, e.g. a thermometer server or a humidity sensor
+pub struct Sensor {
+    name: String,
+    dsn: String,
+    transport: Transport,
+    measurements: Vec<Measurement>,
+    condition: DeviceCondition,
+    last_updated: Option<Instant>,
+    events: DeviceEvents,
+}
+
+impl Sensor {
+    pub fn new(name: &str, dsn: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            dsn: dsn.to_string(),
+            transport: Transport::default(),
+            measurements: vec![],
+            condition: if dsn.is_empty() {
+                DeviceCondition::Ok
+            } else {
+                DeviceCondition::Unknown
+            },
+            last_updated: None,
+            events: DeviceEvents::default(),
+        }
+    }
+
+    // the wire encoding used to talk to the device, JSON unless set
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.transport.codec = codec;
+        self
+    }
+
+    // signs every request with these instead of the home's credentials
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.transport.credentials = Some(credentials);
+        self
+    }
+
+    // the id of the device when the DSN is a fleet server hosting several
+    pub fn with_device_id(mut self, id: &str) -> Self {
+        self.transport.device_id = Some(id.to_string());
+        self
+    }
+
+    fn set_condition(&mut self, condition: DeviceCondition) {
+        if self.condition != condition {
+            self.events.condition(DEVICE_NAME, &condition);
+            self.condition = condition;
+        }
+    }
+
+    // the last known reading of the metric, without asking the device
+    pub fn measurement(&self, metric: Metric) -> Option<&Measurement> {
+        self.measurements.iter().find(|m| m.metric == metric)
+    }
+
+    // a fresh reading of the metric, an error if the device does not measure it
+    pub async fn get(&mut self, metric: Metric) -> Result<Measurement, DeviceReadError> {
+        self.get_measurements().await?;
+        self.measurement(metric)
+            .copied()
+            .ok_or_else(|| DeviceReadError::MissingCapability(metric.to_string()))
+    }
+
+    #[instrument(skip(self), fields(device = %self.name))]
+    pub async fn get_measurements(&mut self) -> Result<&[Measurement], DeviceReadError> {
+        if !self.dsn.is_empty() && device_needs_update(self.last_updated) {
+            let resp =
+                make_device_udp_request(&self.dsn, &self.transport, DeviceRequest::GetMeasurements)
+                    .await
+                    .map_err(DeviceReadError::UnknownError)?;
+            match resp {
+                Response::Measurements(measurements) => {
+                    for measurement in measurements.iter() {
+                        if self.measurement(measurement.metric).map(|m| m.value)
+                            != Some(measurement.value)
+                        {
+                            self.events.reading(
+                                DEVICE_NAME,
+                                &measurement.metric.to_string(),
+                                measurement.value,
+                            );
+                        }
+                    }
+                    self.measurements = measurements;
+                    self.last_updated = Some(Instant::now());
+                    self.set_condition(DeviceCondition::Ok);
+                }
+                Response::Err(err_msg) => {
+                    self.set_condition(DeviceCondition::Err(err_msg.to_string()));
+                    return Err(DeviceReadError::ErrMakingRequest(err_msg));
+                }
+                _ => {
+                    self.set_condition(DeviceCondition::Unknown);
+                    return Err(DeviceReadError::UnexpectedResponse(resp));
+                }
+            }
+        }
+        Ok(&self.measurements)
+    }
+}
+
+impl Device for Sensor {
+    fn get_status(&self) -> DeviceStatus {
+        let status = match self.measurements.is_empty() {
+            true => "no readings".to_string(),
+            false => self
+                .measurements
+                .iter()
+                .map(Measurement::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        DeviceStatus {
+            device_type: DEVICE_NAME.to_string(),
+            name: self.name.to_string(),
+            condition: self.condition.clone(),
+            status,
+            readings: self
+                .measurements
+                .iter()
+                .map(|m| (m.metric.to_string(), m.value))
+                .collect(),
+            updated: self.last_updated,
+        }
+    }
+
+    fn attach_events(&mut self, events: DeviceEvents) {
+        self.events = events;
+    }
+
+    fn attach_credentials(&mut self, credentials: &Credentials) {
+        self.transport
+            .credentials
+            .get_or_insert_with(|| credentials.clone());
+    }
+
+    fn refresh(&mut self) -> DeviceRefreshFuture<'_> {
+        Box::pin(async move { self.get_measurements().await.map(|_| ()) })
+    }
+
+    fn ping(&self) -> DevicePingFuture {
+        let dsn = self.dsn.clone();
+        let transport = self.transport.clone();
+        Box::pin(async move {
+            if dsn.is_empty() {
+                return Ok(());
+            }
+            let resp = make_device_udp_request(&dsn, &transport, DeviceRequest::Ping)
+                .await
+                .map_err(DeviceReadError::UnknownError)?;
+            check_pong(resp)
+        })
+    }
+
+    fn pinged(&mut self, result: &Result<(), DeviceReadError>) {
+        self.set_condition(self.condition.after_ping(result));
+    }
+
+    fn verify(&mut self) -> DeviceVerifyFuture<'_> {
+        Box::pin(async move {
+            if self.dsn.is_empty() {
+                return Ok(());
+            }
+            let resp = make_device_udp_request(&self.dsn, &self.transport, DeviceRequest::Describe)
+                .await
+                .map_err(DeviceReadError::UnknownError)?;
+            let description = check_capabilities(resp, &REQUIRED)?;
+            debug!(
+                firmware = description.firmware.as_str(),
+                device_type = description.device_type.as_str(),
+                "device verified"
+            );
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::sensor::{Sensor, DEVICE_NAME};
+    use crate::devices::{Device, DeviceCondition, DeviceReadError, DeviceStatus};
+    use s_home_proto::{Measurement, Metric};
+
+    const NAME: &str = "test sensor";
+
+    #[tokio::test]
+    async fn test_get() {
+        let mut device = Sensor::new(NAME, "");
+        assert!(device.get_measurements().await.unwrap().is_empty());
+        device.measurements = vec![Measurement::new(Metric::Humidity, 45.0)];
+        assert_eq!(device.get(Metric::Humidity).await.unwrap().value, 45.0);
+        assert!(matches!(
+            device.get(Metric::Co2).await,
+            Err(DeviceReadError::MissingCapability(_))
+        ));
+    }
+
+    #[test]
+    fn test_get_status() {
+        let mut device = Sensor::new(NAME, "");
+        assert_eq!(device.get_status().status, "no readings");
+
+        device.measurements = vec![
+            Measurement::new(Metric::Temperature, 21.5),
+            Measurement::new(Metric::Co2, 600.0),
+        ];
+        assert_eq!(
+            device.get_status(),
+            DeviceStatus {
+                device_type: DEVICE_NAME.to_string(),
+                name: NAME.to_string(),
+                condition: DeviceCondition::Ok,
+                status: "temperature: 21.5 °C, co2: 600 ppm".to_string(),
+                readings: vec![
+                    ("temperature".to_string(), 21.5),
+                    ("co2".to_string(), 600.0)
+                ],
+                updated: None,
+            }
+        );
+    }
+}
@@ -1,19 +1,39 @@
 use crate::devices::{
-    device_needs_update, make_device_udp_request, Device, DeviceCondition, DeviceStatus,
+    check_description, check_pong, device_needs_update, make_device_udp_request, Device,
+    DeviceCondition, DevicePingFuture, DeviceRefreshFuture, DeviceStatus, DeviceVerifyFuture,
+    Transport,
 };
-use s_home_proto::{DeviceRequest, Response};
+use s_home_proto::{Calibration, Codec, DeviceRequest, Response, Temperature, TemperatureUnit};
+use serde::{Deserialize, Serialize};
 use std::time::Instant;
+use tracing::{debug, instrument};
 
 use super::DeviceReadError;
+use crate::events::DeviceEvents;
+use s_home_proto::auth::Credentials;
 
-static DEVICE_NAME: &str = "THRM";
+// requests this client relies on, checked by `verify`
+static REQUIRED: [&str; 1] = ["GetTemperature"];
+pub(crate) static DEVICE_NAME: &str = "THRM";
+
+// how a thermometer's readings are corrected and shown
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ThermometerConfig {
+    pub unit: TemperatureUnit,
+    // applied to the raw degrees Celsius the device reports
+    pub calibration: Calibration,
+}
 
 pub struct Thermometer {
     name: String,
     dsn: String,
-    temp: f32,
+    transport: Transport,
+    config: ThermometerConfig,
+    temp: Temperature,
     condition: DeviceCondition,
     last_updated: Option<Instant>,
+    events: DeviceEvents,
 }
 
 impl Thermometer {
@@ -21,37 +41,95 @@ impl Thermometer {
         Self {
             name: name.to_string(),
             dsn: dsn.to_string(),
-            temp: 0.0,
+            transport: Transport::default(),
+            config: ThermometerConfig::default(),
+            temp: Temperature::default(),
             condition: if dsn.is_empty() {
                 DeviceCondition::Ok
             } else {
                 DeviceCondition::Unknown
             },
             last_updated: None,
+            events: DeviceEvents::default(),
+        }
+    }
+
+    // the wire encoding used to talk to the device, JSON unless set
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.transport.codec = codec;
+        self
+    }
+
+    // signs every request with these instead of the home's credentials
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.transport.credentials = Some(credentials);
+        self
+    }
+
+    // the id of the device when the DSN is a fleet server hosting several
+    pub fn with_device_id(mut self, id: &str) -> Self {
+        self.transport.device_id = Some(id.to_string());
+        self
+    }
+
+    pub fn with_config(mut self, config: ThermometerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    // the unit the status is shown in, Celsius unless set
+    pub fn with_unit(mut self, unit: TemperatureUnit) -> Self {
+        self.config.unit = unit;
+        self
+    }
+
+    // corrects a sensor that reads off, e.g. `Calibration::offset(-1.5)` for one 1.5 °C high
+    pub fn with_calibration(mut self, calibration: Calibration) -> Self {
+        self.config.calibration = calibration;
+        self
+    }
+
+    pub fn config(&self) -> &ThermometerConfig {
+        &self.config
+    }
+
+    fn set_condition(&mut self, condition: DeviceCondition) {
+        if self.condition != condition {
+            self.events.condition(DEVICE_NAME, &condition);
+            self.condition = condition;
         }
     }
 
-    pub async fn get_temp(&mut self) -> Result<f32, DeviceReadError> {
+    #[instrument(skip(self), fields(device = %self.name))]
+    // calibrated
+    pub async fn get_temp(&mut self) -> Result<Temperature, DeviceReadError> {
         if !self.dsn.is_empty() && device_needs_update(self.last_updated) {
-            let request_result = make_device_udp_request(&self.dsn, DeviceRequest::GetTemperature).await;
+            let request_result =
+                make_device_udp_request(&self.dsn, &self.transport, DeviceRequest::GetTemperature)
+                    .await;
             let resp = if let Err(err) = request_result {
                 return Err(DeviceReadError::UnknownError(err));
             } else {
                 request_result.unwrap()
             };
             return match resp {
-                Response::Temperature(val) => {
-                    self.temp = val;
+                Response::Temperature(raw) => {
+                    let temp = Temperature::from_celsius(self.config.calibration.apply(raw));
+                    if self.temp != temp {
+                        self.events
+                            .reading(DEVICE_NAME, "temperature", temp.celsius());
+                    }
+                    self.temp = temp;
                     self.last_updated = Some(Instant::now());
-                    self.condition = DeviceCondition::Ok;
-                    Ok(val)
+                    self.set_condition(DeviceCondition::Ok);
+                    Ok(temp)
                 }
                 Response::Err(err_msg) => {
-                    self.condition = DeviceCondition::Err(err_msg.to_string());
+                    self.set_condition(DeviceCondition::Err(err_msg.to_string()));
                     Err(DeviceReadError::ErrMakingRequest(err_msg.to_string()))
                 }
                 _ => {
-                    self.condition = DeviceCondition::Unknown;
+                    self.set_condition(DeviceCondition::Unknown);
                     Err(DeviceReadError::UnexpectedResponse(resp))
                 }
             };
@@ -66,16 +144,65 @@ impl Device for Thermometer {
             device_type: DEVICE_NAME.to_string(),
             name: self.name.to_string(),
             condition: self.condition.clone(),
-            status: format!("temperature: {}", self.temp),
+            status: format!("temperature: {}", self.temp.format(self.config.unit)),
+            // always in degrees Celsius, whatever the status is shown in
+            readings: vec![("temperature".to_string(), self.temp.celsius())],
             updated: self.last_updated,
         }
     }
+
+    fn attach_events(&mut self, events: DeviceEvents) {
+        self.events = events;
+    }
+
+    fn attach_credentials(&mut self, credentials: &Credentials) {
+        self.transport
+            .credentials
+            .get_or_insert_with(|| credentials.clone());
+    }
+
+    fn refresh(&mut self) -> DeviceRefreshFuture<'_> {
+        Box::pin(async move { self.get_temp().await.map(|_| ()) })
+    }
+
+    fn ping(&self) -> DevicePingFuture {
+        let dsn = self.dsn.clone();
+        let transport = self.transport.clone();
+        Box::pin(async move {
+            if dsn.is_empty() {
+                return Ok(());
+            }
+            let resp = make_device_udp_request(&dsn, &transport, DeviceRequest::Ping)
+                .await
+                .map_err(DeviceReadError::UnknownError)?;
+            check_pong(resp)
+        })
+    }
+
+    fn pinged(&mut self, result: &Result<(), DeviceReadError>) {
+        self.set_condition(self.condition.after_ping(result));
+    }
+
+    fn verify(&mut self) -> DeviceVerifyFuture<'_> {
+        Box::pin(async move {
+            if self.dsn.is_empty() {
+                return Ok(());
+            }
+            let resp = make_device_udp_request(&self.dsn, &self.transport, DeviceRequest::Describe)
+                .await
+                .map_err(DeviceReadError::UnknownError)?;
+            let description = check_description(resp, DEVICE_NAME, &REQUIRED)?;
+            debug!(firmware = description.firmware.as_str(), "device verified");
+            Ok(())
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::devices::thermometer::{Thermometer, DEVICE_NAME};
     use crate::devices::{Device, DeviceCondition, DeviceStatus};
+    use s_home_proto::{Temperature, TemperatureUnit};
     use tokio;
 
     const NAME: &str = "test thermometer";
@@ -88,7 +215,7 @@ mod tests {
     async fn test_get_temp() {
         let mut device = new_thermometer();
         let temp = device.get_temp().await.unwrap();
-        assert_eq!(temp, 0.0)
+        assert_eq!(temp.celsius(), 0.0)
     }
 
     #[test]
@@ -100,7 +227,8 @@ mod tests {
             device_type: DEVICE_NAME.to_string(),
             name: NAME.to_string(),
             condition: DeviceCondition::Ok,
-            status: format!("temperature: {}", 0.0),
+            status: "temperature: 0.0 °C".to_string(),
+            readings: vec![("temperature".to_string(), 0.0)],
             updated: None,
         };
         assert_eq!(have, want);
@@ -108,4 +236,13 @@ mod tests {
         want.status = "changed".to_string();
         assert_ne!(have, want)
     }
+
+    #[test]
+    fn test_status_unit() {
+        let mut device = new_thermometer().with_unit(TemperatureUnit::Fahrenheit);
+        device.temp = Temperature::from_celsius(21.0);
+        let status = device.get_status();
+        assert_eq!(status.status, "temperature: 69.8 °F");
+        assert_eq!(status.readings(), &[("temperature".to_string(), 21.0)]);
+    }
 }
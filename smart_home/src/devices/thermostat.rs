@@ -0,0 +1,640 @@
+use crate::devices::power_socket::PowerSocket;
+use crate::devices::thermometer::Thermometer;
+use crate::devices::{
+    Device, DeviceActionFuture, DeviceCondition, DevicePingFuture, DeviceReadError,
+    DeviceRefreshFuture, DeviceStatus, DeviceUpdateError, DeviceVerifyFuture,
+};
+use crate::events::DeviceEvents;
+use s_home_proto::auth::Credentials;
+use s_home_proto::{DeviceAction, Temperature, TemperatureUnit};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+pub(crate) static DEVICE_NAME: &str = "TSTAT";
+static CONTROL_INTERVAL: Duration = Duration::from_secs(5);
+// degrees Celsius around the setpoint within which nothing is switched
+static HYSTERESIS: f32 = 1.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ThermostatMode {
+    // the sockets drive a heater
+    #[default]
+    Heat,
+    // the sockets drive a cooler
+    Cool,
+    // the sockets stay off
+    Off,
+}
+
+impl Display for ThermostatMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Heat => write!(f, "heat"),
+            Self::Cool => write!(f, "cool"),
+            Self::Off => write!(f, "off"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay {
+    hour: u8,
+    minute: u8,
+}
+
+impl TimeOfDay {
+    pub fn new(hour: u8, minute: u8) -> Option<Self> {
+        (hour < 24 && minute < 60).then_some(Self { hour, minute })
+    }
+
+    // the clock `utc_offset` minutes off UTC shows right now
+    pub fn now(utc_offset: i32) -> Self {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs() as i64);
+        let minutes = (secs / 60 + utc_offset as i64).rem_euclid(24 * 60);
+        Self {
+            hour: (minutes / 60) as u8,
+            minute: (minutes % 60) as u8,
+        }
+    }
+}
+
+impl Display for TimeOfDay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = String;
+
+    // e.g. "07:30"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hour, minute) = s
+            .split_once(':')
+            .ok_or_else(|| format!("bad time '{}'", s))?;
+        let (Ok(hour), Ok(minute)) = (hour.parse(), minute.parse()) else {
+            return Err(format!("bad time '{}'", s));
+        };
+        Self::new(hour, minute).ok_or_else(|| format!("bad time '{}'", s))
+    }
+}
+
+// setpoints by time of day, each one holding until the next starts, the last one past midnight
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Schedule {
+    entries: Vec<(TimeOfDay, Temperature)>,
+    // minutes the schedule's clock is ahead of UTC
+    utc_offset: i32,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_utc_offset(mut self, minutes: i32) -> Self {
+        self.utc_offset = minutes;
+        self
+    }
+
+    pub fn at(mut self, start: TimeOfDay, setpoint: Temperature) -> Self {
+        self.entries.retain(|(time, _)| *time != start);
+        self.entries.push((start, setpoint));
+        self.entries.sort_by_key(|(time, _)| *time);
+        self
+    }
+
+    pub fn setpoint_at(&self, time: TimeOfDay) -> Option<Temperature> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= time)
+            .or(self.entries.last())
+            .map(|(_, setpoint)| *setpoint)
+    }
+
+    fn setpoint_now(&self) -> Option<Temperature> {
+        self.setpoint_at(TimeOfDay::now(self.utc_offset))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ThermostatError {
+    #[error("err reading the temperature: {0}")]
+    Read(#[from] DeviceReadError),
+    #[error("err switching a socket: {0}")]
+    Switch(#[from] DeviceUpdateError),
+}
+
+// whether the sockets should be on; inside the band around the setpoint they stay as they are
+fn decide(
+    mode: ThermostatMode,
+    temp: Temperature,
+    setpoint: Temperature,
+    hysteresis: f32,
+    active: bool,
+) -> bool {
+    let (low, high) = (
+        setpoint.celsius() - hysteresis / 2.0,
+        setpoint.celsius() + hysteresis / 2.0,
+    );
+    let temp = temp.celsius();
+    match mode {
+        ThermostatMode::Off => false,
+        ThermostatMode::Heat if temp < low => true,
+        ThermostatMode::Heat if temp > high => false,
+        ThermostatMode::Cool if temp > high => true,
+        ThermostatMode::Cool if temp < low => false,
+        _ => active,
+    }
+}
+
+// what the control loop and the status share
+struct State {
+    mode: ThermostatMode,
+    // restored by `TurnOn` after a `TurnOff`
+    last_mode: ThermostatMode,
+    setpoint: Temperature,
+    hysteresis: f32,
+    schedule: Schedule,
+    unit: TemperatureUnit,
+    temp: Option<Temperature>,
+    // watts the sockets draw together, as of the last refresh
+    power: Option<f32>,
+    // whether the sockets are on, `None` until first switched
+    active: Option<bool>,
+    condition: DeviceCondition,
+    last_updated: Option<Instant>,
+    events: DeviceEvents,
+}
+
+impl State {
+    fn setpoint(&self) -> Temperature {
+        self.schedule.setpoint_now().unwrap_or(self.setpoint)
+    }
+
+    fn set_condition(&mut self, condition: DeviceCondition) {
+        if self.condition != condition {
+            self.events.condition(DEVICE_NAME, &condition);
+            self.condition = condition;
+        }
+    }
+}
+
+// the devices the control loop drives
+struct Controlled {
+    thermometer: Thermometer,
+    sockets: Vec<PowerSocket>,
+}
+
+// keeps a room at a setpoint by switching the sockets of a heater or a cooler on the
+// readings of a thermometer
+pub struct Thermostat {
+    name: String,
+    interval: Duration,
+    state: Arc<Mutex<State>>,
+    controlled: Arc<tokio::sync::Mutex<Controlled>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl Thermostat {
+    pub fn new(name: &str, thermometer: Thermometer, sockets: Vec<PowerSocket>) -> Self {
+        let state = State {
+            mode: ThermostatMode::default(),
+            last_mode: ThermostatMode::default(),
+            setpoint: Temperature::from_celsius(20.0),
+            hysteresis: HYSTERESIS,
+            schedule: Schedule::default(),
+            unit: thermometer.config().unit,
+            temp: None,
+            power: None,
+            active: None,
+            condition: DeviceCondition::Unknown,
+            last_updated: None,
+            events: DeviceEvents::default(),
+        };
+        Self {
+            name: name.to_string(),
+            interval: CONTROL_INTERVAL,
+            state: Arc::new(Mutex::new(state)),
+            controlled: Arc::new(tokio::sync::Mutex::new(Controlled {
+                thermometer,
+                sockets,
+            })),
+            task: None,
+        }
+    }
+
+    pub fn with_setpoint(self, setpoint: Temperature) -> Self {
+        self.set_setpoint(setpoint);
+        self
+    }
+
+    // the width of the band around the setpoint, degrees Celsius
+    pub fn with_hysteresis(self, hysteresis: f32) -> Self {
+        self.state.lock().unwrap().hysteresis = hysteresis.abs();
+        self
+    }
+
+    pub fn with_mode(self, mode: ThermostatMode) -> Self {
+        self.set_mode(mode);
+        self
+    }
+
+    // overrides the setpoint whenever it has entries
+    pub fn with_schedule(self, schedule: Schedule) -> Self {
+        self.state.lock().unwrap().schedule = schedule;
+        self
+    }
+
+    // how often the control loop reads the thermometer
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn set_setpoint(&self, setpoint: Temperature) {
+        self.state.lock().unwrap().setpoint = setpoint;
+    }
+
+    pub fn set_mode(&self, mode: ThermostatMode) {
+        let mut state = self.state.lock().unwrap();
+        if state.mode != ThermostatMode::Off {
+            state.last_mode = state.mode;
+        }
+        state.mode = mode;
+    }
+
+    pub fn mode(&self) -> ThermostatMode {
+        self.state.lock().unwrap().mode
+    }
+
+    // the one in effect now, from the schedule if there is one
+    pub fn setpoint(&self) -> Temperature {
+        self.state.lock().unwrap().setpoint()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.task.as_ref().is_some_and(|task| !task.is_finished())
+    }
+
+    // runs the control loop in the background until `stop` or until the thermostat is dropped
+    pub fn start(&mut self) {
+        if self.is_running() {
+            return;
+        }
+        let state = Arc::clone(&self.state);
+        let controlled = Arc::clone(&self.controlled);
+        let mut interval = tokio::time::interval(self.interval);
+        let name = self.name.clone();
+        self.task = Some(tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+                if let Err(err) = control(&state, &controlled).await {
+                    warn!(thermostat = name.as_str(), %err, "control step failed");
+                }
+            }
+        }));
+        info!(thermostat = self.name.as_str(), "control loop started");
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+            info!(thermostat = self.name.as_str(), "control loop stopped");
+        }
+    }
+
+    // a single pass of the control loop; true when the sockets are on afterwards
+    pub async fn step(&self) -> Result<bool, ThermostatError> {
+        control(&self.state, &self.controlled).await
+    }
+}
+
+impl Drop for Thermostat {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+async fn control(
+    state: &Mutex<State>,
+    controlled: &tokio::sync::Mutex<Controlled>,
+) -> Result<bool, ThermostatError> {
+    let mut controlled = controlled.lock().await;
+    let temp = match controlled.thermometer.get_temp().await {
+        Ok(temp) => temp,
+        Err(err) => {
+            state
+                .lock()
+                .unwrap()
+                .set_condition(DeviceCondition::Err(err.to_string()));
+            return Err(err.into());
+        }
+    };
+
+    let (on, switch) = {
+        let mut state = state.lock().unwrap();
+        state.temp = Some(temp);
+        state.last_updated = Some(Instant::now());
+        let setpoint = state.setpoint();
+        let active = state.active.unwrap_or(false);
+        let on = decide(state.mode, temp, setpoint, state.hysteresis, active);
+        debug!(%temp, %setpoint, mode = %state.mode, on, "control step");
+        // switched the first time whatever the sockets were left at
+        (on, state.active != Some(on))
+    };
+    if switch {
+        for socket in controlled.sockets.iter_mut() {
+            let result = match on {
+                true => socket.power_on().await,
+                false => socket.power_off().await,
+            };
+            if let Err(err) = result {
+                state
+                    .lock()
+                    .unwrap()
+                    .set_condition(DeviceCondition::Err(err.to_string()));
+                return Err(err.into());
+            }
+        }
+        let mut state = state.lock().unwrap();
+        if state.active.is_some() {
+            state.events.power_switched(DEVICE_NAME, on);
+        }
+        state.active = Some(on);
+    }
+    state.lock().unwrap().set_condition(DeviceCondition::Ok);
+    Ok(on)
+}
+
+impl Device for Thermostat {
+    fn get_status(&self) -> DeviceStatus {
+        let state = self.state.lock().unwrap();
+        let setpoint = state.setpoint();
+        let temp = match state.temp {
+            Some(temp) => temp.format(state.unit),
+            None => "unknown".to_string(),
+        };
+        let activity = match state.active {
+            Some(true) if state.mode == ThermostatMode::Cool => "cooling",
+            Some(true) => "heating",
+            Some(false) => "idle",
+            None => "not started",
+        };
+        let mut readings = vec![("setpoint".to_string(), setpoint.celsius())];
+        if let Some(temp) = state.temp {
+            readings.push(("temperature".to_string(), temp.celsius()));
+        }
+        // for the power budget, which switches the thermostat as it would a socket
+        if let Some(watts) = state.power {
+            readings.push(("power".to_string(), watts));
+        }
+        DeviceStatus {
+            device_type: DEVICE_NAME.to_string(),
+            name: self.name.to_string(),
+            condition: state.condition.clone(),
+            status: format!(
+                "mode: {}, setpoint: {}, temperature: {}, {}",
+                state.mode,
+                setpoint.format(state.unit),
+                temp,
+                activity
+            ),
+            readings,
+            updated: state.last_updated,
+        }
+    }
+
+    fn attach_events(&mut self, events: DeviceEvents) {
+        self.state.lock().unwrap().events = events;
+    }
+
+    fn attach_credentials(&mut self, credentials: &Credentials) {
+        // only ever busy while the loop runs, by then the devices have theirs
+        if let Ok(mut controlled) = self.controlled.try_lock() {
+            controlled.thermometer.attach_credentials(credentials);
+            for socket in controlled.sockets.iter_mut() {
+                socket.attach_credentials(credentials);
+            }
+        }
+    }
+
+    // `TurnOff` switches the thermostat off, `TurnOn` back to the mode it was in
+    fn perform(&mut self, action: DeviceAction) -> DeviceActionFuture<'_> {
+        let mode = match action {
+            DeviceAction::TurnOff => ThermostatMode::Off,
+            // a running thermostat keeps the mode it is in
+            DeviceAction::TurnOn => {
+                let state = self.state.lock().unwrap();
+                match state.mode {
+                    ThermostatMode::Off => state.last_mode,
+                    mode => mode,
+                }
+            }
+            DeviceAction::Reset => {
+                return Box::pin(async {
+                    Err(DeviceUpdateError::UnsupportedAction("Reset".to_string()))
+                })
+            }
+        };
+        self.set_mode(mode);
+        Box::pin(async move {
+            match self.step().await {
+                Ok(_) => Ok(()),
+                Err(ThermostatError::Switch(err)) => Err(err),
+                Err(ThermostatError::Read(err)) => Err(DeviceUpdateError::UnknownError(err.into())),
+            }
+        })
+    }
+
+    // reads the thermometer and what the sockets draw without switching anything
+    fn refresh(&mut self) -> DeviceRefreshFuture<'_> {
+        Box::pin(async move {
+            let mut controlled = self.controlled.lock().await;
+            let temp = controlled.thermometer.get_temp().await?;
+            let mut watts = 0.0;
+            for socket in controlled.sockets.iter_mut() {
+                watts += socket.get_power_consumption().await?.watts();
+            }
+            let mut state = self.state.lock().unwrap();
+            state.temp = Some(temp);
+            state.power = Some(watts);
+            state.last_updated = Some(Instant::now());
+            Ok(())
+        })
+    }
+
+    // answers when the thermometer and every socket do
+    fn ping(&self) -> DevicePingFuture {
+        let controlled = Arc::clone(&self.controlled);
+        Box::pin(async move {
+            let controlled = controlled.lock().await;
+            controlled.thermometer.ping().await?;
+            for socket in controlled.sockets.iter() {
+                socket.ping().await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn pinged(&mut self, result: &Result<(), DeviceReadError>) {
+        let mut state = self.state.lock().unwrap();
+        let condition = state.condition.after_ping(result);
+        state.set_condition(condition);
+    }
+
+    fn verify(&mut self) -> DeviceVerifyFuture<'_> {
+        Box::pin(async move {
+            let mut controlled = self.controlled.lock().await;
+            controlled.thermometer.verify().await?;
+            for socket in controlled.sockets.iter_mut() {
+                socket.verify().await?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::power_socket::PowerSocket;
+    use crate::devices::thermometer::Thermometer;
+    use crate::devices::thermostat::{
+        decide, Schedule, Thermostat, ThermostatMode, TimeOfDay, DEVICE_NAME,
+    };
+    use crate::devices::{Device, DeviceCondition};
+    use s_home_proto::{DeviceAction, Temperature, TemperatureUnit};
+
+    fn celsius(c: f32) -> Temperature {
+        Temperature::from_celsius(c)
+    }
+
+    fn time(s: &str) -> TimeOfDay {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_decide() {
+        let heat = |temp, active| {
+            decide(
+                ThermostatMode::Heat,
+                celsius(temp),
+                celsius(20.0),
+                1.0,
+                active,
+            )
+        };
+        assert!(heat(19.0, false));
+        assert!(!heat(21.0, true));
+        // inside the band nothing changes
+        assert!(heat(20.3, true));
+        assert!(!heat(19.7, false));
+
+        let cool = |temp, active| {
+            decide(
+                ThermostatMode::Cool,
+                celsius(temp),
+                celsius(20.0),
+                1.0,
+                active,
+            )
+        };
+        assert!(cool(21.0, false));
+        assert!(!cool(19.0, true));
+        assert!(cool(19.7, true));
+
+        assert!(!decide(
+            ThermostatMode::Off,
+            celsius(0.0),
+            celsius(20.0),
+            1.0,
+            true
+        ));
+    }
+
+    #[test]
+    fn test_schedule() {
+        assert_eq!(Schedule::new().setpoint_at(time("12:00")), None);
+
+        let schedule = Schedule::new()
+            .at(time("22:00"), celsius(17.0))
+            .at(time("07:00"), celsius(21.0));
+        assert_eq!(schedule.setpoint_at(time("07:00")), Some(celsius(21.0)));
+        assert_eq!(schedule.setpoint_at(time("21:59")), Some(celsius(21.0)));
+        assert_eq!(schedule.setpoint_at(time("23:00")), Some(celsius(17.0)));
+        // before the first entry of the day the last one of the night before holds
+        assert_eq!(schedule.setpoint_at(time("03:00")), Some(celsius(17.0)));
+    }
+
+    #[test]
+    fn test_time_of_day() {
+        assert_eq!(time("07:05").to_string(), "07:05");
+        assert!("24:00".parse::<TimeOfDay>().is_err());
+        assert!("7".parse::<TimeOfDay>().is_err());
+        assert!(TimeOfDay::now(0) <= time("23:59"));
+    }
+
+    #[tokio::test]
+    async fn test_step_and_status() {
+        // devices without a DSN read 0 °C and switch without asking anybody
+        let thermometer =
+            Thermometer::new("thermometer", "").with_unit(TemperatureUnit::Fahrenheit);
+        let sockets = vec![PowerSocket::new("heater", "")];
+        let mut thermostat =
+            Thermostat::new("thermostat", thermometer, sockets).with_setpoint(celsius(20.0));
+
+        let status = thermostat.get_status();
+        assert_eq!(status.device_type(), DEVICE_NAME);
+        assert!(status.as_string().contains("not started"));
+
+        assert!(thermostat.step().await.unwrap());
+        let status = thermostat.get_status();
+        assert!(matches!(status.condition(), DeviceCondition::Ok));
+        assert_eq!(
+            status.as_string(),
+            format!(
+                "[{}]thermostat\n\tcondition: OK\n\tstatus: mode: heat, setpoint: 68.0 °F, \
+                 temperature: 32.0 °F, heating\n",
+                DEVICE_NAME
+            )
+        );
+
+        // what the sockets draw is read along with the temperature
+        thermostat.refresh().await.unwrap();
+        let status = thermostat.get_status();
+        assert!(status.readings().contains(&("power".to_string(), 0.0)));
+
+        thermostat.perform(DeviceAction::TurnOff).await.unwrap();
+        assert_eq!(thermostat.mode(), ThermostatMode::Off);
+        assert!(thermostat.get_status().as_string().contains("idle"));
+        thermostat.perform(DeviceAction::TurnOn).await.unwrap();
+        assert_eq!(thermostat.mode(), ThermostatMode::Heat);
+    }
+
+    #[tokio::test]
+    async fn test_turn_on_running() {
+        let thermometer = Thermometer::new("thermometer", "");
+        let sockets = vec![PowerSocket::new("cooler", "")];
+        let mut thermostat = Thermostat::new("thermostat", thermometer, sockets)
+            .with_mode(ThermostatMode::Cool)
+            .with_setpoint(celsius(-5.0));
+
+        // already on, it must not turn into a heater
+        thermostat.perform(DeviceAction::TurnOn).await.unwrap();
+        assert_eq!(thermostat.mode(), ThermostatMode::Cool);
+        assert!(thermostat.get_status().as_string().contains("cooling"));
+
+        thermostat.perform(DeviceAction::TurnOff).await.unwrap();
+        thermostat.perform(DeviceAction::TurnOn).await.unwrap();
+        assert_eq!(thermostat.mode(), ThermostatMode::Cool);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::devices::power_socket::{self, PowerSocket};
+use crate::devices::thermometer::{self, Thermometer};
+use crate::devices::Device;
+use crate::home::{Home, HomeUpdateError};
+use s_home_proto::discovery::{DiscoveryMessage, DISCOVERY_PORT};
+use s_home_proto::Marshal;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::Instant;
+use tracing::{debug, info, instrument, warn};
+
+static SCAN_TIMEOUT: Duration = Duration::from_secs(1);
+
+// a device that answered a probe
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoundDevice {
+    pub name: String,
+    pub device_type: String,
+    pub dsn: String,
+}
+
+impl FoundDevice {
+    // a client for the device, `None` for device types this home does not know
+    pub fn to_device(&self) -> Option<Box<dyn Device>> {
+        match self.device_type.as_str() {
+            t if t == power_socket::DEVICE_NAME => {
+                Some(Box::new(PowerSocket::new(&self.name, &self.dsn)))
+            }
+            t if t == thermometer::DEVICE_NAME => {
+                Some(Box::new(Thermometer::new(&self.name, &self.dsn)))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub struct Scanner {
+    targets: Vec<String>,
+    timeout: Duration,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self {
+            targets: vec![format!("255.255.255.255:{}", DISCOVERY_PORT)],
+            timeout: SCAN_TIMEOUT,
+        }
+    }
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // addresses probes are sent to instead of the broadcast address, e.g. responders on other ports
+    pub fn with_targets(mut self, targets: &[&str]) -> Self {
+        self.targets = targets.iter().map(|t| t.to_string()).collect();
+        self
+    }
+
+    // how long to wait for answers
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    #[instrument(skip(self), fields(targets = ?self.targets))]
+    pub async fn scan(&self) -> std::io::Result<Vec<FoundDevice>> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.set_broadcast(true)?;
+        let probe = DiscoveryMessage::Probe.marshal()?;
+        for target in self.targets.iter() {
+            socket.send_to(probe.as_bytes(), target).await?;
+        }
+
+        let mut found: Vec<FoundDevice> = vec![];
+        let deadline = Instant::now() + self.timeout;
+        let mut buf = [0u8; 512];
+        while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await
+        {
+            let (len, peer) = received?;
+            let Some(device) = parse_announcement(&buf[..len], peer) else {
+                debug!(%peer, "ignoring datagram");
+                continue;
+            };
+            // a responder may be reached through several targets
+            if !found.iter().any(|d| d.dsn == device.dsn) {
+                debug!(?device, "device found");
+                found.push(device);
+            }
+        }
+        info!(devices = found.len(), "scan finished");
+        Ok(found)
+    }
+}
+
+fn parse_announcement(bytes: &[u8], peer: SocketAddr) -> Option<FoundDevice> {
+    let msg = std::str::from_utf8(bytes).ok()?;
+    match DiscoveryMessage::unmarshal(msg).ok()? {
+        DiscoveryMessage::Announce(announcement) => Some(FoundDevice {
+            name: announcement.name,
+            device_type: announcement.device_type,
+            dsn: SocketAddr::new(peer.ip(), announcement.port).to_string(),
+        }),
+        DiscoveryMessage::Probe => None,
+    }
+}
+
+// verifies and adds every found device of a known type to the room, under its announced name
+pub async fn add_discovered(
+    home: &mut Home,
+    room_name: &str,
+    found: &[FoundDevice],
+) -> Vec<(String, Result<(), HomeUpdateError>)> {
+    let mut results = vec![];
+    for device in found {
+        let Some(client) = device.to_device() else {
+            warn!(?device, "skipping device of unknown type");
+            continue;
+        };
+        let result = home
+            .add_verified_device(room_name, &device.name, client)
+            .await;
+        if let Err(err) = &result {
+            warn!(?device, %err, "err adding discovered device");
+        }
+        results.push((device.name.clone(), result));
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::discovery::{parse_announcement, FoundDevice};
+    use s_home_proto::discovery::{DeviceAnnouncement, DiscoveryMessage};
+    use s_home_proto::Marshal;
+
+    #[test]
+    fn test_parse_announcement() {
+        let peer = "192.168.1.20:41234".parse().unwrap();
+        let msg = DiscoveryMessage::Announce(DeviceAnnouncement::new("THRM", "porch", 12345))
+            .marshal()
+            .unwrap();
+        let found = parse_announcement(msg.as_bytes(), peer).unwrap();
+        assert_eq!(
+            found,
+            FoundDevice {
+                name: "porch".to_string(),
+                device_type: "THRM".to_string(),
+                dsn: "192.168.1.20:12345".to_string(),
+            }
+        );
+        assert!(found.to_device().is_some());
+
+        let probe = DiscoveryMessage::Probe.marshal().unwrap();
+        assert_eq!(parse_announcement(probe.as_bytes(), peer), None);
+        assert_eq!(parse_announcement(b"garbage", peer), None);
+    }
+
+    #[test]
+    fn test_unknown_type() {
+        let found = FoundDevice {
+            name: "toaster".to_string(),
+            device_type: "TOST".to_string(),
+            dsn: "127.0.0.1:1".to_string(),
+        };
+        assert!(found.to_device().is_none());
+    }
+}
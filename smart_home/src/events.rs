@@ -0,0 +1,363 @@
+use crate::devices::DeviceCondition;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+use tracing::warn;
+
+static EVENTS_CAPACITY: usize = 256;
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "event")]
+pub enum HomeEvent {
+    RoomAdded {
+        room: String,
+    },
+    RoomRemoved {
+        room: String,
+    },
+    DeviceAdded {
+        room: String,
+        device: String,
+        device_type: String,
+    },
+    DeviceRemoved {
+        room: String,
+        device: String,
+        device_type: String,
+    },
+    ReadingChanged {
+        room: String,
+        device: String,
+        device_type: String,
+        metric: String,
+        value: f32,
+    },
+    // a device was read, whether anything changed or not
+    DeviceRefreshed {
+        room: String,
+        device: String,
+        device_type: String,
+    },
+    ConditionChanged {
+        room: String,
+        device: String,
+        device_type: String,
+        condition: String,
+    },
+    PowerSwitched {
+        room: String,
+        device: String,
+        device_type: String,
+        is_on: bool,
+    },
+    // published by the health monitor, see `health::HealthState`
+    HealthChanged {
+        room: String,
+        device: String,
+        device_type: String,
+        health: String,
+    },
+    // a socket switched off or back on to keep the home within its power budget
+    LoadShedding {
+        room: String,
+        device: String,
+        device_type: String,
+        shed: bool,
+        // what the socket draws, and the home altogether once it was switched
+        watts: f32,
+        load: f32,
+        budget: f32,
+    },
+}
+
+impl HomeEvent {
+    pub fn room(&self) -> &str {
+        match self {
+            Self::RoomAdded { room }
+            | Self::RoomRemoved { room }
+            | Self::DeviceAdded { room, .. }
+            | Self::DeviceRemoved { room, .. }
+            | Self::ReadingChanged { room, .. }
+            | Self::DeviceRefreshed { room, .. }
+            | Self::ConditionChanged { room, .. }
+            | Self::PowerSwitched { room, .. }
+            | Self::HealthChanged { room, .. }
+            | Self::LoadShedding { room, .. } => room,
+        }
+    }
+
+    pub fn device(&self) -> Option<&str> {
+        match self {
+            Self::RoomAdded { .. } | Self::RoomRemoved { .. } => None,
+            Self::DeviceAdded { device, .. }
+            | Self::DeviceRemoved { device, .. }
+            | Self::ReadingChanged { device, .. }
+            | Self::DeviceRefreshed { device, .. }
+            | Self::ConditionChanged { device, .. }
+            | Self::PowerSwitched { device, .. }
+            | Self::HealthChanged { device, .. }
+            | Self::LoadShedding { device, .. } => Some(device),
+        }
+    }
+
+    pub fn device_type(&self) -> Option<&str> {
+        match self {
+            Self::RoomAdded { .. } | Self::RoomRemoved { .. } => None,
+            Self::DeviceAdded { device_type, .. }
+            | Self::DeviceRemoved { device_type, .. }
+            | Self::ReadingChanged { device_type, .. }
+            | Self::DeviceRefreshed { device_type, .. }
+            | Self::ConditionChanged { device_type, .. }
+            | Self::PowerSwitched { device_type, .. }
+            | Self::HealthChanged { device_type, .. }
+            | Self::LoadShedding { device_type, .. } => Some(device_type),
+        }
+    }
+}
+
+type EventFilter = Box<dyn Fn(&HomeEvent) -> bool + Send + Sync>;
+
+// every mutation and reading in a home is published here; cloning shares the same bus
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<HomeEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            sender: broadcast::channel(EVENTS_CAPACITY).0,
+        }
+    }
+
+    // returns the number of subscribers the event was delivered to
+    pub fn publish(&self, event: HomeEvent) -> usize {
+        // an error only means there are no subscribers at the moment
+        self.sender.send(event).unwrap_or(0)
+    }
+
+    pub fn subscribe(&self) -> EventSubscription {
+        self.subscribe_filtered(|_| true)
+    }
+
+    pub fn subscribe_filtered<F>(&self, filter: F) -> EventSubscription
+    where
+        F: Fn(&HomeEvent) -> bool + Send + Sync + 'static,
+    {
+        EventSubscription {
+            rx: self.sender.subscribe(),
+            filter: Box::new(filter),
+            lagged: 0,
+        }
+    }
+
+    pub fn subscribers(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct EventSubscription {
+    rx: broadcast::Receiver<HomeEvent>,
+    filter: EventFilter,
+    lagged: u64,
+}
+
+impl EventSubscription {
+    // waits for the next matching event, None means the bus is gone
+    pub async fn recv(&mut self) -> Option<HomeEvent> {
+        loop {
+            match self.rx.recv().await {
+                Ok(event) if (self.filter)(&event) => return Some(event),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => self.skipped(skipped),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    // returns the next matching event if one is already queued
+    pub fn try_recv(&mut self) -> Option<HomeEvent> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(event) if (self.filter)(&event) => return Some(event),
+                Ok(_) => continue,
+                Err(TryRecvError::Lagged(skipped)) => self.skipped(skipped),
+                Err(_) => return None,
+            }
+        }
+    }
+
+    // number of events dropped because this subscriber was too slow
+    pub fn lagged(&self) -> u64 {
+        self.lagged
+    }
+
+    fn skipped(&mut self, skipped: u64) {
+        self.lagged += skipped;
+        warn!(
+            skipped,
+            lagged = self.lagged,
+            "subscriber lagged, events skipped"
+        );
+    }
+}
+
+// logs every event on the bus, handy while running simulated homes
+pub fn spawn_logger(bus: &EventBus) -> tokio::task::JoinHandle<()> {
+    let mut events = bus.subscribe();
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            tracing::info!(
+                room = event.room(),
+                device = event.device(),
+                ?event,
+                "home event"
+            )
+        }
+    })
+}
+
+// a sink which silently drops events when no bus is attached
+#[derive(Clone, Default)]
+pub(crate) struct EventSink {
+    bus: Option<EventBus>,
+}
+
+impl EventSink {
+    pub(crate) fn new(bus: EventBus) -> Self {
+        Self { bus: Some(bus) }
+    }
+
+    pub(crate) fn publish(&self, event: HomeEvent) {
+        if let Some(bus) = &self.bus {
+            bus.publish(event);
+        }
+    }
+}
+
+// events published on behalf of a single device placed in a room
+#[derive(Clone, Default)]
+pub struct DeviceEvents {
+    sink: EventSink,
+    room: String,
+    device: String,
+}
+
+impl DeviceEvents {
+    pub(crate) fn new(sink: EventSink, room: &str, device: &str) -> Self {
+        Self {
+            sink,
+            room: room.to_string(),
+            device: device.to_string(),
+        }
+    }
+
+    pub(crate) fn reading(&self, device_type: &str, metric: &str, value: f32) {
+        self.sink.publish(HomeEvent::ReadingChanged {
+            room: self.room.to_string(),
+            device: self.device.to_string(),
+            device_type: device_type.to_string(),
+            metric: metric.to_string(),
+            value,
+        })
+    }
+
+    pub(crate) fn power_switched(&self, device_type: &str, is_on: bool) {
+        self.sink.publish(HomeEvent::PowerSwitched {
+            room: self.room.to_string(),
+            device: self.device.to_string(),
+            device_type: device_type.to_string(),
+            is_on,
+        })
+    }
+
+    pub(crate) fn condition(&self, device_type: &str, condition: &DeviceCondition) {
+        self.sink.publish(HomeEvent::ConditionChanged {
+            room: self.room.to_string(),
+            device: self.device.to_string(),
+            device_type: device_type.to_string(),
+            condition: condition.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::{DeviceEvents, EventBus, EventSink, HomeEvent};
+
+    #[test]
+    fn test_device_events() {
+        let bus = EventBus::new();
+        let mut rx = bus.subscribe();
+
+        let events = DeviceEvents::new(EventSink::new(bus), "kitchen", "socket");
+        events.reading("PSOC", "power", 12.5);
+
+        let event = rx.try_recv().unwrap();
+        assert_eq!(
+            event,
+            HomeEvent::ReadingChanged {
+                room: "kitchen".to_string(),
+                device: "socket".to_string(),
+                device_type: "PSOC".to_string(),
+                metric: "power".to_string(),
+                value: 12.5,
+            }
+        );
+        assert_eq!(event.room(), "kitchen");
+        assert_eq!(event.device_type(), Some("PSOC"));
+    }
+
+    #[test]
+    fn test_filtered_subscription() {
+        let bus = EventBus::new();
+        let mut all = bus.subscribe();
+        let mut hall = bus.subscribe_filtered(|e| e.room() == "hall");
+        assert_eq!(bus.subscribers(), 2);
+
+        for room in ["kitchen", "hall"] {
+            bus.publish(HomeEvent::RoomAdded {
+                room: room.to_string(),
+            });
+        }
+
+        assert_eq!(all.try_recv().unwrap().room(), "kitchen");
+        assert_eq!(all.try_recv().unwrap().room(), "hall");
+        assert_eq!(hall.try_recv().unwrap().room(), "hall");
+        assert!(hall.try_recv().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_recv_closed() {
+        let bus = EventBus::new();
+        let mut events = bus.subscribe();
+        bus.publish(HomeEvent::RoomRemoved {
+            room: "kitchen".to_string(),
+        });
+        drop(bus);
+
+        assert!(events.recv().await.is_some());
+        assert!(events.recv().await.is_none());
+    }
+
+    #[test]
+    fn test_detached_sink() {
+        // must not panic without a channel
+        DeviceEvents::default().reading("THRM", "temperature", 1.0);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let event = HomeEvent::RoomAdded {
+            room: "kitchen".to_string(),
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(json, r#"{"event":"RoomAdded","room":"kitchen"}"#)
+    }
+}
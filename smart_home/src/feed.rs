@@ -0,0 +1,142 @@
+use crate::events::{EventBus, EventSubscription, HomeEvent};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+type FeedResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+// subscriber-side filter taken from the query string, e.g. `ws://host/?room=kitchen&type=PSOC`
+#[derive(Default, Debug, PartialEq)]
+pub struct FeedFilter {
+    pub room: Option<String>,
+    pub device_type: Option<String>,
+}
+
+impl FeedFilter {
+    pub fn from_query(query: &str) -> Self {
+        let mut filter = Self::default();
+        for pair in query.split('&') {
+            match pair.split_once('=') {
+                Some(("room", room)) if !room.is_empty() => filter.room = Some(room.to_string()),
+                Some(("type", device_type)) if !device_type.is_empty() => {
+                    filter.device_type = Some(device_type.to_uppercase())
+                }
+                _ => {}
+            }
+        }
+        filter
+    }
+
+    pub fn matches(&self, event: &HomeEvent) -> bool {
+        if let Some(room) = &self.room {
+            if event.room() != room {
+                return false;
+            }
+        }
+        if let Some(device_type) = &self.device_type {
+            return event.device_type() == Some(device_type.as_str());
+        }
+        true
+    }
+}
+
+// forwards every home event as a JSON text message to each connected websocket client
+pub async fn serve_feed(listener: TcpListener, bus: EventBus) -> FeedResult {
+    info!(address = %listener.local_addr()?, "feed listening");
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        // subscribing before the handshake so no event is lost in between
+        let events = bus.subscribe();
+
+        tokio::spawn(
+            async move {
+                if let Err(err) = handle_subscriber(stream, events).await {
+                    warn!(%err, "subscriber failed")
+                }
+                debug!("subscriber gone")
+            }
+            .instrument(info_span!("feed_subscriber", %peer)),
+        );
+    }
+}
+
+// the handshake callback signature is dictated by tungstenite
+#[allow(clippy::result_large_err)]
+async fn handle_subscriber(stream: TcpStream, mut events: EventSubscription) -> FeedResult {
+    let mut filter = FeedFilter::default();
+    let ws = tokio_tungstenite::accept_hdr_async(stream, |req: &Request, resp: Response| {
+        filter = FeedFilter::from_query(req.uri().query().unwrap_or_default());
+        Ok(resp)
+    })
+    .await?;
+    info!(?filter, "new subscriber");
+
+    let (mut write, mut read) = ws.split();
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(event) => {
+                    if filter.matches(&event) {
+                        let msg = serde_json::to_string(&event)?;
+                        write.send(Message::Text(msg.into())).await?;
+                    }
+                }
+                None => break,
+            },
+            msg = read.next() => match msg {
+                None | Some(Ok(Message::Close(_))) => break,
+                Some(Err(err)) => return Err(err.into()),
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::HomeEvent;
+    use crate::feed::{serve_feed, FeedFilter};
+    use crate::home::Home;
+    use futures_util::StreamExt;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_filter() {
+        let filter = FeedFilter::from_query("room=kitchen&type=psoc");
+        assert_eq!(filter.room.as_deref(), Some("kitchen"));
+        assert_eq!(filter.device_type.as_deref(), Some("PSOC"));
+
+        let room_added = HomeEvent::RoomAdded {
+            room: "kitchen".to_string(),
+        };
+        assert!(!filter.matches(&room_added));
+        assert!(FeedFilter::from_query("room=kitchen").matches(&room_added));
+        assert!(FeedFilter::from_query("").matches(&room_added));
+        assert!(!FeedFilter::from_query("room=hall").matches(&room_added));
+    }
+
+    #[tokio::test]
+    async fn test_feed() {
+        let mut home = Home::new("test home");
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_feed(listener, home.bus()));
+
+        let url = format!("ws://{}/?room=kitchen", addr);
+        let (mut ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        home.add_room("hall").unwrap();
+        home.add_room("kitchen").unwrap();
+
+        let msg = ws.next().await.unwrap().unwrap();
+        assert_eq!(
+            msg.into_text().unwrap().as_str(),
+            r#"{"event":"RoomAdded","room":"kitchen"}"#
+        );
+    }
+}
@@ -0,0 +1,769 @@
+use crate::devices::{DevicePingFuture, DeviceReadError};
+use crate::events::HomeEvent;
+use crate::home::Home;
+use crate::report::Timestamp;
+use futures_util::future::join_all;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tracing::{debug, info, warn};
+
+static CHECK_INTERVAL: Duration = Duration::from_secs(10);
+static PING_TIMEOUT: Duration = Duration::from_secs(3);
+// answering slower than this counts as degraded
+static SLOW_PING: Duration = Duration::from_millis(500);
+static OFFLINE_AFTER: u32 = 3;
+// at most FLAP_LIMIT alerts per device within FLAP_WINDOW, the rest are suppressed
+static FLAP_WINDOW: Duration = Duration::from_secs(600);
+static FLAP_LIMIT: usize = 4;
+static WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+static COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HealthState {
+    // not checked yet
+    #[default]
+    Unknown,
+    Online,
+    // answering slowly, or missed a ping or two
+    Degraded,
+    // missed too many pings in a row
+    Offline,
+}
+
+impl Display for HealthState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unknown => write!(f, "UNKNOWN"),
+            Self::Online => write!(f, "ONLINE"),
+            Self::Degraded => write!(f, "DEGRADED"),
+            Self::Offline => write!(f, "OFFLINE"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DeviceHealth {
+    pub device_type: String,
+    pub state: HealthState,
+    // of the last answered ping
+    pub latency: Option<Duration>,
+    pub consecutive_failures: u32,
+    pub checks: u64,
+    pub answered: u64,
+    pub last_seen: Option<Timestamp>,
+    // when the device last started answering, `None` while offline
+    pub online_since: Option<Timestamp>,
+    pub last_error: Option<String>,
+    // alerts not sent because the device kept flapping
+    pub suppressed: u64,
+    // when the alerts within the flap window were sent
+    alerts: VecDeque<Instant>,
+}
+
+impl DeviceHealth {
+    // the share of pings answered, 0 before the first check
+    pub fn availability(&self) -> f32 {
+        match self.checks {
+            0 => 0.0,
+            checks => self.answered as f32 / checks as f32,
+        }
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.online_since
+            .map_or(Duration::ZERO, |online_since| online_since.age())
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct HealthAlert {
+    pub room: String,
+    pub device: String,
+    pub device_type: String,
+    pub previous: HealthState,
+    pub state: HealthState,
+    pub consecutive_failures: u32,
+    pub latency_ms: Option<f64>,
+    pub error: Option<String>,
+    pub at: Timestamp,
+}
+
+impl Display for HealthAlert {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{} ({}) is {}, was {}",
+            self.room, self.device, self.device_type, self.state, self.previous
+        )?;
+        match &self.error {
+            Some(err) => write!(f, ": {}", err),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum NotifyError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("bad webhook url '{0}'")]
+    BadUrl(String),
+    #[error("webhook answered '{0}'")]
+    Webhook(String),
+    #[error("notification timed out")]
+    Timeout,
+    #[error("command failed: {0}")]
+    Command(std::process::ExitStatus),
+}
+
+pub type NotifyFuture<'a> = Pin<Box<dyn Future<Output = Result<(), NotifyError>> + Send + 'a>>;
+
+// where alerts go
+pub trait Notifier: Send + Sync {
+    fn notify<'a>(&'a self, alert: &'a HealthAlert) -> NotifyFuture<'a>;
+}
+
+// writes alerts to the log
+pub struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn notify<'a>(&'a self, alert: &'a HealthAlert) -> NotifyFuture<'a> {
+        Box::pin(async move {
+            match alert.state {
+                HealthState::Online => info!(alert = %alert, "device health changed"),
+                _ => warn!(alert = %alert, "device health changed"),
+            }
+            Ok(())
+        })
+    }
+}
+
+// POSTs alerts as JSON over plain HTTP, meant for an endpoint on the local network
+pub struct WebhookNotifier {
+    addr: String,
+    path: String,
+}
+
+impl WebhookNotifier {
+    // e.g. "http://127.0.0.1:8080/alerts"
+    pub fn new(url: &str) -> Result<Self, NotifyError> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| NotifyError::BadUrl(url.to_string()))?;
+        let (addr, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        if addr.is_empty() {
+            return Err(NotifyError::BadUrl(url.to_string()));
+        }
+        Ok(Self {
+            addr: addr.to_string(),
+            path: path.to_string(),
+        })
+    }
+
+    async fn post(&self, body: String) -> Result<(), NotifyError> {
+        let mut stream = TcpStream::connect(&self.addr).await?;
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.path,
+            self.addr,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await?;
+        let mut status_line = String::new();
+        BufReader::new(stream).read_line(&mut status_line).await?;
+        let status_line = status_line.trim_end();
+        // e.g. "HTTP/1.1 204 No Content"
+        match status_line.split(' ').nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            _ => Err(NotifyError::Webhook(status_line.to_string())),
+        }
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify<'a>(&'a self, alert: &'a HealthAlert) -> NotifyFuture<'a> {
+        Box::pin(async move {
+            // alerts hold nothing that could fail to serialize
+            let body = serde_json::to_string(alert).unwrap();
+            tokio::time::timeout(WEBHOOK_TIMEOUT, self.post(body))
+                .await
+                .map_err(|_| NotifyError::Timeout)?
+        })
+    }
+}
+
+// runs a command for every alert, passing it in ALERT_* environment variables
+pub struct CommandNotifier {
+    program: String,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl CommandNotifier {
+    pub fn new(program: &str) -> Self {
+        Self {
+            program: program.to_string(),
+            args: vec![],
+            timeout: COMMAND_TIMEOUT,
+        }
+    }
+
+    pub fn with_args(mut self, args: &[&str]) -> Self {
+        self.args = args.iter().map(|arg| arg.to_string()).collect();
+        self
+    }
+
+    // commands still running by then are killed
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Notifier for CommandNotifier {
+    fn notify<'a>(&'a self, alert: &'a HealthAlert) -> NotifyFuture<'a> {
+        Box::pin(async move {
+            let status = tokio::process::Command::new(&self.program)
+                .args(&self.args)
+                .env("ALERT_ROOM", &alert.room)
+                .env("ALERT_DEVICE", &alert.device)
+                .env("ALERT_DEVICE_TYPE", &alert.device_type)
+                .env("ALERT_STATE", alert.state.to_string())
+                .env("ALERT_PREVIOUS", alert.previous.to_string())
+                .env("ALERT_MESSAGE", alert.to_string())
+                .kill_on_drop(true)
+                .status();
+            let status = tokio::time::timeout(self.timeout, status)
+                .await
+                .map_err(|_| NotifyError::Timeout)??;
+            match status.success() {
+                true => Ok(()),
+                false => Err(NotifyError::Command(status)),
+            }
+        })
+    }
+}
+
+// pings every device of a home, keeps track of how they are doing and alerts on changes
+pub struct HealthMonitor {
+    interval: Duration,
+    timeout: Duration,
+    slow: Duration,
+    offline_after: u32,
+    flap_window: Duration,
+    flap_limit: usize,
+    notifiers: Vec<Box<dyn Notifier>>,
+    // (room, device) -> health
+    devices: BTreeMap<(String, String), DeviceHealth>,
+}
+
+impl Default for HealthMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HealthMonitor {
+    pub fn new() -> Self {
+        Self {
+            interval: CHECK_INTERVAL,
+            timeout: PING_TIMEOUT,
+            slow: SLOW_PING,
+            offline_after: OFFLINE_AFTER,
+            flap_window: FLAP_WINDOW,
+            flap_limit: FLAP_LIMIT,
+            notifiers: vec![],
+            devices: BTreeMap::new(),
+        }
+    }
+
+    // how often `run` checks the devices
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    // pings not answered by then count as failed
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // pings answered slower than this leave the device degraded
+    pub fn with_slow_ping(mut self, slow: Duration) -> Self {
+        self.slow = slow;
+        self
+    }
+
+    // failed pings in a row before a device is offline, fewer leave it degraded
+    pub fn with_offline_after(mut self, failures: u32) -> Self {
+        self.offline_after = failures.max(1);
+        self
+    }
+
+    // at most `limit` alerts per device within `window`
+    pub fn with_flap_suppression(mut self, window: Duration, limit: usize) -> Self {
+        self.flap_window = window;
+        self.flap_limit = limit;
+        self
+    }
+
+    pub fn with_notifier(mut self, notifier: impl Notifier + 'static) -> Self {
+        self.notifiers.push(Box::new(notifier));
+        self
+    }
+
+    pub fn health(&self, room: &str, device: &str) -> Option<&DeviceHealth> {
+        self.devices.get(&(room.to_string(), device.to_string()))
+    }
+
+    // (room, device, health) of every device checked so far
+    pub fn devices(&self) -> impl Iterator<Item = (&str, &str, &DeviceHealth)> {
+        self.devices
+            .iter()
+            .map(|((room, device), health)| (room.as_str(), device.as_str(), health))
+    }
+
+    // pings every device once, writes what was found back to it and returns the alerts sent
+    pub async fn check(&mut self, home: &mut Home) -> Vec<HealthAlert> {
+        let pinged = self.ping_all(pings(home)).await;
+        let alerts = self.record(home, pinged);
+        self.notify(&alerts).await;
+        alerts
+    }
+
+    // checks the home every interval, forever; the home is locked only while the pings are
+    // started and while their results are written back, not while they are awaited
+    pub async fn run(&mut self, home: &tokio::sync::Mutex<Home>) {
+        let mut interval = tokio::time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            let pings = pings(&*home.lock().await);
+            let pinged = self.ping_all(pings).await;
+            let alerts = self.record(&mut *home.lock().await, pinged);
+            self.notify(&alerts).await;
+        }
+    }
+
+    // every device at once, each given `timeout` to answer
+    async fn ping_all(&self, pings: Vec<Ping>) -> Vec<Pinged> {
+        let timeout = self.timeout;
+        join_all(pings.into_iter().map(|ping| async move {
+            let started = Instant::now();
+            let result = match tokio::time::timeout(timeout, ping.future).await {
+                Ok(result) => result,
+                Err(_) => Err(DeviceReadError::ErrMakingRequest(
+                    "ping timed out".to_string(),
+                )),
+            };
+            Pinged {
+                room: ping.room,
+                device: ping.device,
+                device_type: ping.device_type,
+                result,
+                latency: started.elapsed(),
+            }
+        }))
+        .await
+    }
+
+    // updates the health of every device pinged, returning the alerts to send
+    fn record(&mut self, home: &mut Home, pinged: Vec<Pinged>) -> Vec<HealthAlert> {
+        let bus = home.bus();
+        let mut seen = HashSet::new();
+        let mut alerts = vec![];
+        for Pinged {
+            room,
+            device: name,
+            device_type,
+            result,
+            latency,
+        } in pinged
+        {
+            // removed while it was being pinged
+            let Some(device) = home
+                .room_mut(&room)
+                .and_then(|room| room.device_mut(&name).ok())
+            else {
+                continue;
+            };
+            device.pinged(&result);
+
+            let key = (room.to_string(), name.to_string());
+            let health = self
+                .devices
+                .entry(key.clone())
+                .or_insert_with(|| DeviceHealth {
+                    device_type,
+                    ..DeviceHealth::default()
+                });
+            let previous = health.state;
+            health.checks += 1;
+            match result {
+                Ok(()) => {
+                    health.answered += 1;
+                    health.consecutive_failures = 0;
+                    health.latency = Some(latency);
+                    health.last_seen = Some(Timestamp::now());
+                    health.last_error = None;
+                    health.online_since.get_or_insert_with(Timestamp::now);
+                    health.state = match latency > self.slow {
+                        true => HealthState::Degraded,
+                        false => HealthState::Online,
+                    };
+                }
+                Err(err) => {
+                    debug!(room = room.as_str(), device = name.as_str(), %err, "ping failed");
+                    health.consecutive_failures += 1;
+                    health.last_error = Some(err.to_string());
+                    health.state = match health.consecutive_failures >= self.offline_after {
+                        true => HealthState::Offline,
+                        false => HealthState::Degraded,
+                    };
+                    if health.state == HealthState::Offline {
+                        health.online_since = None;
+                    }
+                }
+            }
+            seen.insert(key);
+
+            if health.state == previous {
+                continue;
+            }
+            bus.publish(HomeEvent::HealthChanged {
+                room: room.to_string(),
+                device: name.to_string(),
+                device_type: health.device_type.to_string(),
+                health: health.state.to_string(),
+            });
+            // devices found answering on the first check are no news
+            if previous == HealthState::Unknown && health.state == HealthState::Online {
+                continue;
+            }
+            if !allow_alert(health, self.flap_window, self.flap_limit) {
+                debug!(
+                    room = room.as_str(),
+                    device = name.as_str(),
+                    "device flapping, alert suppressed"
+                );
+                continue;
+            }
+            alerts.push(HealthAlert {
+                room: room.to_string(),
+                device: name.to_string(),
+                device_type: health.device_type.to_string(),
+                previous,
+                state: health.state,
+                consecutive_failures: health.consecutive_failures,
+                latency_ms: health.latency.map(|latency| latency.as_secs_f64() * 1000.0),
+                error: health.last_error.clone(),
+                at: Timestamp::now(),
+            });
+        }
+        // forget devices which left the home
+        self.devices.retain(|key, _| seen.contains(key));
+        alerts
+    }
+
+    async fn notify(&self, alerts: &[HealthAlert]) {
+        for alert in alerts.iter() {
+            for notifier in self.notifiers.iter() {
+                if let Err(err) = notifier.notify(alert).await {
+                    warn!(%err, alert = %alert, "err sending alert");
+                }
+            }
+        }
+    }
+}
+
+// a ping on its way, started while the home was locked
+struct Ping {
+    room: String,
+    device: String,
+    device_type: String,
+    future: DevicePingFuture,
+}
+
+struct Pinged {
+    room: String,
+    device: String,
+    device_type: String,
+    result: Result<(), DeviceReadError>,
+    latency: Duration,
+}
+
+fn pings(home: &Home) -> Vec<Ping> {
+    let mut pings = vec![];
+    for room in home.list_rooms() {
+        for (name, device) in room.named_devices() {
+            pings.push(Ping {
+                room: room.name.to_string(),
+                device: name.to_string(),
+                device_type: device.get_status().device_type().to_string(),
+                future: device.ping(),
+            });
+        }
+    }
+    pings
+}
+
+fn allow_alert(health: &mut DeviceHealth, window: Duration, limit: usize) -> bool {
+    let now = Instant::now();
+    while let Some(sent) = health.alerts.front() {
+        if now.duration_since(*sent) < window {
+            break;
+        }
+        health.alerts.pop_front();
+    }
+    if health.alerts.len() >= limit {
+        health.suppressed += 1;
+        return false;
+    }
+    health.alerts.push_back(now);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::{Device, DevicePingFuture, DeviceReadError, DeviceStatus};
+    use crate::events::HomeEvent;
+    use crate::health::{
+        CommandNotifier, HealthAlert, HealthMonitor, HealthState, Notifier, NotifyError,
+        NotifyFuture, WebhookNotifier,
+    };
+    use crate::home::Home;
+    use crate::report::Timestamp;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // answers pings while `up` is set, after `delay`
+    struct FakeDevice {
+        up: Arc<AtomicBool>,
+        delay: Duration,
+    }
+
+    impl Device for FakeDevice {
+        fn get_status(&self) -> DeviceStatus {
+            DeviceStatus::quick_unknown("fake", "FAKE")
+        }
+
+        fn ping(&self) -> DevicePingFuture {
+            let up = self.up.load(Ordering::SeqCst);
+            let delay = self.delay;
+            Box::pin(async move {
+                tokio::time::sleep(delay).await;
+                match up {
+                    true => Ok(()),
+                    false => Err(DeviceReadError::ErrMakingRequest("down".to_string())),
+                }
+            })
+        }
+    }
+
+    #[derive(Default, Clone)]
+    struct Collect(Arc<Mutex<Vec<HealthAlert>>>);
+
+    impl Notifier for Collect {
+        fn notify<'a>(&'a self, alert: &'a HealthAlert) -> NotifyFuture<'a> {
+            self.0.lock().unwrap().push(alert.clone());
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    fn home_with_fake() -> (Home, Arc<AtomicBool>) {
+        let up = Arc::new(AtomicBool::new(true));
+        let mut home = Home::new("home");
+        home.add_room("hall").unwrap();
+        home.add_device(
+            "hall",
+            "fake",
+            Box::new(FakeDevice {
+                up: Arc::clone(&up),
+                delay: Duration::ZERO,
+            }),
+        )
+        .unwrap();
+        (home, up)
+    }
+
+    fn alert() -> HealthAlert {
+        HealthAlert {
+            room: "hall".to_string(),
+            device: "fake".to_string(),
+            device_type: "FAKE".to_string(),
+            previous: HealthState::Online,
+            state: HealthState::Offline,
+            consecutive_failures: 3,
+            latency_ms: None,
+            error: Some("down".to_string()),
+            at: Timestamp::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transitions() {
+        let (mut home, up) = home_with_fake();
+        let mut events = home.subscribe();
+        let collect = Collect::default();
+        let mut monitor = HealthMonitor::new()
+            .with_offline_after(2)
+            .with_notifier(collect.clone());
+
+        // found online, nothing to alert about
+        assert!(monitor.check(&mut home).await.is_empty());
+        let health = monitor.health("hall", "fake").unwrap();
+        assert_eq!(health.state, HealthState::Online);
+        assert!(health.latency.is_some());
+        assert_eq!(
+            events.try_recv().unwrap(),
+            HomeEvent::HealthChanged {
+                room: "hall".to_string(),
+                device: "fake".to_string(),
+                device_type: "FAKE".to_string(),
+                health: "ONLINE".to_string(),
+            }
+        );
+
+        up.store(false, Ordering::SeqCst);
+        let alerts = monitor.check(&mut home).await;
+        assert_eq!(alerts[0].state, HealthState::Degraded);
+        let alerts = monitor.check(&mut home).await;
+        assert_eq!(alerts[0].state, HealthState::Offline);
+        assert_eq!(
+            alerts[0].to_string(),
+            "hall/fake (FAKE) is OFFLINE, was DEGRADED: err making request: down"
+        );
+        // still offline, no news
+        assert!(monitor.check(&mut home).await.is_empty());
+
+        let health = monitor.health("hall", "fake").unwrap();
+        assert_eq!(health.consecutive_failures, 3);
+        assert_eq!(health.availability(), 0.25);
+        assert!(health.online_since.is_none());
+
+        up.store(true, Ordering::SeqCst);
+        assert_eq!(monitor.check(&mut home).await[0].state, HealthState::Online);
+        assert_eq!(collect.0.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_flap_suppression() {
+        let (mut home, up) = home_with_fake();
+        let collect = Collect::default();
+        let mut monitor = HealthMonitor::new()
+            .with_offline_after(1)
+            .with_flap_suppression(Duration::from_secs(60), 2)
+            .with_notifier(collect.clone());
+
+        monitor.check(&mut home).await;
+        for _ in 0..3 {
+            up.store(false, Ordering::SeqCst);
+            monitor.check(&mut home).await;
+            up.store(true, Ordering::SeqCst);
+            monitor.check(&mut home).await;
+        }
+        assert_eq!(collect.0.lock().unwrap().len(), 2);
+        assert_eq!(monitor.health("hall", "fake").unwrap().suppressed, 4);
+    }
+
+    #[tokio::test]
+    async fn test_pings_concurrently() {
+        let mut home = Home::new("home");
+        home.add_room("hall").unwrap();
+        for (name, delay) in [("a", 300), ("b", 300), ("stuck", 5000)] {
+            let device = FakeDevice {
+                up: Arc::new(AtomicBool::new(true)),
+                delay: Duration::from_millis(delay),
+            };
+            home.add_device("hall", name, Box::new(device)).unwrap();
+        }
+        let mut monitor = HealthMonitor::new()
+            .with_timeout(Duration::from_secs(1))
+            .with_slow_ping(Duration::from_secs(1));
+
+        let started = std::time::Instant::now();
+        monitor.check(&mut home).await;
+        assert!(started.elapsed() < Duration::from_millis(1500));
+        assert_eq!(
+            monitor.health("hall", "a").unwrap().state,
+            HealthState::Online
+        );
+        let stuck = monitor.health("hall", "stuck").unwrap();
+        assert_eq!(stuck.state, HealthState::Degraded);
+        assert_eq!(
+            stuck.last_error.as_deref(),
+            Some("err making request: ping timed out")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forgets_removed_devices() {
+        let (mut home, _) = home_with_fake();
+        let mut monitor = HealthMonitor::new();
+        monitor.check(&mut home).await;
+        home.remove_device("hall", "fake").unwrap();
+        monitor.check(&mut home).await;
+        assert_eq!(monitor.devices().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_notifier() {
+        assert!(WebhookNotifier::new("https://example.com").is_err());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let n = stream.read(&mut buf).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_string()
+        });
+
+        let notifier = WebhookNotifier::new(&format!("http://{}/alerts", addr)).unwrap();
+        notifier.notify(&alert()).await.unwrap();
+        let request = server.await.unwrap();
+        assert!(
+            request.starts_with("POST /alerts HTTP/1.1\r\n"),
+            "{}",
+            request
+        );
+        assert!(request.contains("\"state\":\"OFFLINE\""), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn test_command_notifier() {
+        let ok = CommandNotifier::new("sh").with_args(&["-c", "test \"$ALERT_STATE\" = OFFLINE"]);
+        ok.notify(&alert()).await.unwrap();
+        let failing = CommandNotifier::new("sh").with_args(&["-c", "exit 1"]);
+        assert!(matches!(
+            failing.notify(&alert()).await,
+            Err(NotifyError::Command(_))
+        ));
+        let hanging = CommandNotifier::new("sleep")
+            .with_args(&["10"])
+            .with_timeout(Duration::from_millis(100));
+        assert!(matches!(
+            hanging.notify(&alert()).await,
+            Err(NotifyError::Timeout)
+        ));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::events::{EventBus, HomeEvent};
+use crate::home::Home;
+use crate::report::Timestamp;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+static CAPACITY: usize = 60;
+
+// (room, device, metric)
+type SeriesKey = (String, String, String);
+
+// the last few readings of every device metric, oldest first, e.g. for sparklines
+pub struct ReadingHistory {
+    // readings kept per series
+    capacity: usize,
+    series: Mutex<BTreeMap<SeriesKey, VecDeque<(Timestamp, f32)>>>,
+}
+
+impl ReadingHistory {
+    pub fn new() -> Arc<Self> {
+        Self::with_capacity(CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            capacity: capacity.max(1),
+            series: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    pub fn record(&self, room: &str, device: &str, metric: &str, at: Timestamp, value: f32) {
+        let mut series = self.series.lock().unwrap();
+        let readings = series
+            .entry((room.to_string(), device.to_string(), metric.to_string()))
+            .or_default();
+        if readings.len() == self.capacity {
+            readings.pop_front();
+        }
+        readings.push_back((at, value));
+    }
+
+    // one sample of every reading the devices last reported; for callers polling the home
+    // rather than following its events
+    pub fn sample_home(&self, home: &Home) {
+        let now = Timestamp::now();
+        for room in home.list_rooms() {
+            for (name, device) in room.named_devices() {
+                let status = device.get_status();
+                // never read, nothing worth drawing
+                if status.updated().is_none() {
+                    continue;
+                }
+                for (metric, value) in status.readings() {
+                    self.record(&room.name, name, metric, now, *value);
+                }
+            }
+        }
+    }
+
+    pub fn observe_event(&self, event: &HomeEvent) {
+        match event {
+            HomeEvent::ReadingChanged {
+                room,
+                device,
+                metric,
+                value,
+                ..
+            } => self.record(room, device, metric, Timestamp::now(), *value),
+            HomeEvent::RoomRemoved { room } => {
+                self.series.lock().unwrap().retain(|(r, _, _), _| r != room)
+            }
+            HomeEvent::DeviceRemoved { room, device, .. } => self
+                .series
+                .lock()
+                .unwrap()
+                .retain(|(r, d, _), _| r != room || d != device),
+            _ => {}
+        }
+    }
+
+    pub fn spawn(self: &Arc<Self>, bus: &EventBus) -> JoinHandle<()> {
+        let history = Arc::clone(self);
+        let mut events = bus.subscribe();
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                history.observe_event(&event)
+            }
+        })
+    }
+
+    pub fn series(&self, room: &str, device: &str, metric: &str) -> Vec<(Timestamp, f32)> {
+        self.series
+            .lock()
+            .unwrap()
+            .get(&(room.to_string(), device.to_string(), metric.to_string()))
+            .map(|readings| readings.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    // just the values of `series`
+    pub fn values(&self, room: &str, device: &str, metric: &str) -> Vec<f32> {
+        self.series(room, device, metric)
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::power_socket::PowerSocket;
+    use crate::events::HomeEvent;
+    use crate::history::ReadingHistory;
+    use crate::home::Home;
+    use crate::report::Timestamp;
+
+    fn reading(device: &str, value: f32) -> HomeEvent {
+        HomeEvent::ReadingChanged {
+            room: "hall".to_string(),
+            device: device.to_string(),
+            device_type: "THRM".to_string(),
+            metric: "temperature".to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    fn test_observe_event() {
+        let history = ReadingHistory::with_capacity(3);
+        for value in [1.0, 2.0, 3.0, 4.0] {
+            history.observe_event(&reading("thrm", value));
+        }
+        history.observe_event(&reading("other", 10.0));
+        assert_eq!(
+            history.values("hall", "thrm", "temperature"),
+            [2.0, 3.0, 4.0]
+        );
+
+        history.observe_event(&HomeEvent::DeviceRemoved {
+            room: "hall".to_string(),
+            device: "thrm".to_string(),
+            device_type: "THRM".to_string(),
+        });
+        assert!(history.values("hall", "thrm", "temperature").is_empty());
+        assert_eq!(history.values("hall", "other", "temperature"), [10.0]);
+    }
+
+    #[test]
+    fn test_sample_home() {
+        let mut home = Home::new("home");
+        home.add_room("kitchen").unwrap();
+        home.add_device(
+            "kitchen",
+            "kettle",
+            Box::new(PowerSocket::new("kettle", "")),
+        )
+        .unwrap();
+
+        let history = ReadingHistory::new();
+        history.sample_home(&home);
+        // never read yet
+        assert!(history.values("kitchen", "kettle", "power").is_empty());
+
+        history.record("kitchen", "kettle", "power", Timestamp::now(), 5.0);
+        assert_eq!(history.series("kitchen", "kettle", "power").len(), 1);
+    }
+}
@@ -1,47 +1,161 @@
-use crate::room::Room;
-use std::collections::HashMap;
+use crate::alarms::{Alarm, AlarmError, Alarms};
+use crate::area::{self, AreaKind, AreaSummary};
+use crate::budget::{LoadAction, LoadDecision, PowerBudget};
+use crate::devices::{power_socket, thermostat, Device, DeviceReadError, DeviceUpdateError};
+use crate::events::{EventBus, EventSink, EventSubscription, HomeEvent};
+use crate::query::DeviceQuery;
+use crate::report::{ConditionState, HomeReport, RoomReport, Timestamp};
+use crate::room::{Room, RoomReadError, RoomUpdateError};
+use futures_util::future::join_all;
+use s_home_proto::auth::Credentials;
+use s_home_proto::{DeviceAction, HomeAction, HomeRequest, Response};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Deref;
+use std::time::Duration;
 use thiserror::Error;
+use tracing::{info, warn};
+
+// a device that does not answer must not hold up the others
+static DEVICE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// devices the power budget reads and switches: sockets, and thermostats along with theirs
+fn switches_load(device_type: &str) -> bool {
+    device_type == power_socket::DEVICE_NAME || device_type == thermostat::DEVICE_NAME
+}
 
 pub struct Home {
     name: String,
+    // by path, e.g. "floor1/kitchen"
     rooms: HashMap<String, Room>,
+    // floors and zones rooms can be in, by path
+    areas: BTreeMap<String, AreaKind>,
+    bus: EventBus,
+    // handed to every device added without credentials of its own
+    credentials: Option<Credentials>,
+    alarms: Alarms,
+    budget: Option<PowerBudget>,
 }
 
 impl Home {
     pub fn new(name: &str) -> Home {
+        Self::with_bus(name, EventBus::new())
+    }
+
+    pub fn with_bus(name: &str, bus: EventBus) -> Home {
         Home {
             name: name.to_string(),
             rooms: HashMap::new(),
+            areas: BTreeMap::new(),
+            bus,
+            credentials: None,
+            alarms: Alarms::new(),
+            budget: None,
         }
     }
+
+    // credentials device clients sign their requests with
+    pub fn with_credentials(mut self, credentials: Credentials) -> Home {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    // e.g. alarms loaded from the store of an earlier run
+    pub fn with_alarms(mut self, alarms: Alarms) -> Home {
+        self.alarms = alarms;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // the most the sockets may draw together, see `balance_load`
+    pub fn with_power_budget(mut self, budget: PowerBudget) -> Home {
+        self.budget = Some(budget);
+        self
+    }
+
+    pub fn power_budget(&self) -> Option<&PowerBudget> {
+        self.budget.as_ref()
+    }
+
+    pub fn power_budget_mut(&mut self) -> Option<&mut PowerBudget> {
+        self.budget.as_mut()
+    }
+
+    pub fn alarms(&self) -> &Alarms {
+        &self.alarms
+    }
+
+    pub fn alarms_mut(&mut self) -> &mut Alarms {
+        &mut self.alarms
+    }
+
+    pub fn subscribe(&self) -> EventSubscription {
+        self.bus.subscribe()
+    }
+
+    // a handle for subscribing from elsewhere, e.g. from the websocket feed
+    pub fn bus(&self) -> EventBus {
+        self.bus.clone()
+    }
 }
 
 #[derive(Error, Debug)]
 pub enum HomeReadError {
     #[error("home does not contain room '{0}'")]
     DoesNotContainRoom(String),
+    #[error("home does not contain area '{0}'")]
+    DoesNotContainArea(String),
 }
 
-
 #[derive(Error, Debug)]
 pub enum HomeUpdateError {
     #[error("home does not contain room '{0}'")]
     DoesNotContainRoom(String),
     #[error("home already contains room '{0}'")]
     AlreadyContainsRoom(String),
+    #[error("room update failed: {0}")]
+    RoomUpdate(#[from] RoomUpdateError),
+    #[error("room read failed: {0}")]
+    RoomRead(#[from] RoomReadError),
+    #[error("device update failed: {0}")]
+    DeviceUpdate(#[from] DeviceUpdateError),
+    #[error("device verification failed: {0}")]
+    DeviceVerification(#[from] DeviceReadError),
+    #[error("home does not contain area '{0}'")]
+    DoesNotContainArea(String),
+    #[error("home already contains area '{0}'")]
+    AlreadyContainsArea(String),
+    #[error("area '{0}' still contains rooms or areas")]
+    AreaNotEmpty(String),
+    #[error("bad area: {0}")]
+    InvalidArea(String),
+    #[error("room '{room}' does not contain device '{device}'")]
+    DoesNotContainDevice { room: String, device: String },
+    #[error("room '{room}' already contains device '{device}'")]
+    DeviceNameTaken { room: String, device: String },
+    #[error("device '{device}' in room '{room}' timed out")]
+    Timeout { room: String, device: String },
 }
 
 impl Home {
+    // the name is the path of the room, e.g. "floor1/kitchen" once there is a "floor1" area
     pub fn add_room(&mut self, name: &str) -> Result<(), HomeUpdateError> {
         if self.rooms.contains_key(name) {
             Err(HomeUpdateError::AlreadyContainsRoom(name.to_string()))
         } else {
-            self.rooms.insert(name.to_string(), Room::new(name));
+            self.check_area_path(name)?;
+            let room = Room::with_events(name, EventSink::new(self.bus.clone()));
+            self.rooms.insert(name.to_string(), room);
+            self.bus.publish(HomeEvent::RoomAdded {
+                room: name.to_string(),
+            });
             Ok(())
         }
     }
 
-    pub fn get_room(&mut self, name: &str) -> Result<&Room, HomeReadError> {
+    pub fn get_room(&self, name: &str) -> Result<&Room, HomeReadError> {
         if self.rooms.contains_key(name) {
             Ok(self.rooms.get(name).unwrap())
         } else {
@@ -49,15 +163,506 @@ impl Home {
         }
     }
 
+    // the room itself, for the crate's own device work that leaves the devices as they are
+    pub(crate) fn room_mut(&mut self, name: &str) -> Option<&mut Room> {
+        self.rooms.get_mut(name)
+    }
+
+    // devices added, removed or renamed through it are handled like by the `Home` methods
+    pub fn get_room_mut(&mut self, name: &str) -> Result<RoomMut<'_>, HomeReadError> {
+        if !self.rooms.contains_key(name) {
+            return Err(HomeReadError::DoesNotContainRoom(name.to_string()));
+        }
+        Ok(RoomMut {
+            home: self,
+            name: name.to_string(),
+        })
+    }
+
+    // the devices go along, and so do their alarms and power budget entries; nothing changes
+    // on errors
+    pub fn rename_room(&mut self, name: &str, new_name: &str) -> Result<(), HomeUpdateError> {
+        if !self.rooms.contains_key(name) {
+            return Err(HomeUpdateError::DoesNotContainRoom(name.to_string()));
+        }
+        if name == new_name {
+            return Ok(());
+        }
+        if self.rooms.contains_key(new_name) {
+            return Err(HomeUpdateError::AlreadyContainsRoom(new_name.to_string()));
+        }
+        self.check_area_path(new_name)?;
+        let mut room = self.rooms.remove(name).unwrap();
+        room.rename(new_name);
+        self.rooms.insert(new_name.to_string(), room);
+        self.relocate(|room, device| {
+            (room == name).then(|| (new_name.to_string(), device.to_string()))
+        });
+        Ok(())
+    }
+
     pub fn remove_room(&mut self, name: &str) -> Result<(), HomeUpdateError> {
         if !self.rooms.contains_key(name) {
             Err(HomeUpdateError::DoesNotContainRoom(name.to_string()))
         } else {
-            self.rooms.remove(name);
+            let room = self.rooms.remove(name).unwrap();
+            // subscribers tracking devices hear of each one, like when the room is renamed
+            for (device_name, device) in room.named_devices() {
+                self.bus.publish(HomeEvent::DeviceRemoved {
+                    room: name.to_string(),
+                    device: device_name.to_string(),
+                    device_type: device.get_status().device_type().to_string(),
+                });
+            }
+            self.forget(|room, _| room == name);
+            self.bus.publish(HomeEvent::RoomRemoved {
+                room: name.to_string(),
+            });
             Ok(())
         }
     }
 
+    // floors go right below the home, zones below the home or a floor
+    pub fn add_area(&mut self, path: &str, kind: AreaKind) -> Result<(), HomeUpdateError> {
+        if kind == AreaKind::Home {
+            return Err(HomeUpdateError::InvalidArea(
+                "there is only the one home".to_string(),
+            ));
+        }
+        if !area::is_valid(path) {
+            return Err(HomeUpdateError::InvalidArea(format!("bad path '{}'", path)));
+        }
+        if self.rooms.contains_key(path) {
+            return Err(HomeUpdateError::AlreadyContainsRoom(path.to_string()));
+        }
+        let parent_kind = self.check_area_path(path)?;
+        if parent_kind >= kind {
+            return Err(HomeUpdateError::InvalidArea(format!(
+                "a {} cannot be in a {}",
+                kind, parent_kind
+            )));
+        }
+        self.areas.insert(path.to_string(), kind);
+        Ok(())
+    }
+
+    pub fn remove_area(&mut self, path: &str) -> Result<(), HomeUpdateError> {
+        if !self.areas.contains_key(path) {
+            return Err(HomeUpdateError::DoesNotContainArea(path.to_string()));
+        }
+        let below = |other: &String| area::is_below(other, path);
+        if self.areas.keys().any(below) || self.rooms.keys().any(below) {
+            return Err(HomeUpdateError::AreaNotEmpty(path.to_string()));
+        }
+        self.areas.remove(path);
+        Ok(())
+    }
+
+    // the kind of the area a room or area would go in, as long as nothing is there yet
+    fn check_area_path(&self, path: &str) -> Result<AreaKind, HomeUpdateError> {
+        if path.contains('/') && !area::is_valid(path) {
+            return Err(HomeUpdateError::InvalidArea(format!("bad path '{}'", path)));
+        }
+        if self.areas.contains_key(path) {
+            return Err(HomeUpdateError::AlreadyContainsArea(path.to_string()));
+        }
+        self.area_kind(area::parent(path))
+            .ok_or_else(|| HomeUpdateError::DoesNotContainArea(area::parent(path).to_string()))
+    }
+
+    // `AreaKind::Home` for "", none for paths which are not an area
+    pub fn area_kind(&self, path: &str) -> Option<AreaKind> {
+        match path {
+            "" => Some(AreaKind::Home),
+            _ => self.areas.get(path).copied(),
+        }
+    }
+
+    // (path, kind) of every floor and zone, sorted
+    pub fn list_areas(&self) -> Vec<(&str, AreaKind)> {
+        self.areas
+            .iter()
+            .map(|(path, kind)| (path.as_str(), *kind))
+            .collect()
+    }
+
+    // the rooms anywhere below the area, all of them for ""
+    pub fn rooms_in(&self, path: &str) -> Vec<&Room> {
+        let mut rooms: Vec<&Room> = self
+            .rooms
+            .iter()
+            .filter(|(name, _)| area::is_below(name, path))
+            .map(|(_, room)| room)
+            .collect();
+        rooms.sort_by(|a, b| a.name.cmp(&b.name));
+        rooms
+    }
+
+    // what the rooms below the area hold and draw, with the same for every area below
+    pub fn area_summary(&self, path: &str) -> Result<AreaSummary, HomeReadError> {
+        let kind = self
+            .area_kind(path)
+            .ok_or_else(|| HomeReadError::DoesNotContainArea(path.to_string()))?;
+        let mut summary = AreaSummary {
+            path: path.to_string(),
+            kind,
+            rooms: 0,
+            devices: 0,
+            faulty: 0,
+            power: 0.0,
+            energy: 0.0,
+            areas: vec![],
+        };
+        for room in self.rooms_in(path) {
+            summary.rooms += 1;
+            for (_, device) in room.named_devices() {
+                let status = device.get_status();
+                summary.devices += 1;
+                if status.report().condition == ConditionState::Error {
+                    summary.faulty += 1;
+                }
+                for (metric, value) in status.readings() {
+                    match metric.as_str() {
+                        "power" => summary.power += value,
+                        "energy" => summary.energy += value,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        for child in self.areas.keys() {
+            if area::parent(child) == path {
+                summary.areas.push(self.area_summary(child)?);
+            }
+        }
+        Ok(summary)
+    }
+
+    pub fn collect_area_summary(&self, path: &str) -> Result<String, HomeReadError> {
+        Ok(area::format_summary(&self.area_summary(path)?))
+    }
+
+    pub fn add_device(
+        &mut self,
+        room_name: &str,
+        name: &str,
+        mut device: Box<dyn Device>,
+    ) -> Result<(), HomeUpdateError> {
+        match self.rooms.get_mut(room_name) {
+            None => Err(HomeUpdateError::DoesNotContainRoom(room_name.to_string())),
+            Some(room) => {
+                if let Some(credentials) = &self.credentials {
+                    device.attach_credentials(credentials);
+                }
+                Ok(room.add_device(name, device)?)
+            }
+        }
+    }
+
+    // like `add_device`, but only after the device confirmed it is what it claims to be
+    pub async fn add_verified_device(
+        &mut self,
+        room_name: &str,
+        name: &str,
+        mut device: Box<dyn Device>,
+    ) -> Result<(), HomeUpdateError> {
+        if !self.rooms.contains_key(room_name) {
+            return Err(HomeUpdateError::DoesNotContainRoom(room_name.to_string()));
+        }
+        if let Some(credentials) = &self.credentials {
+            device.attach_credentials(credentials);
+        }
+        device.verify().await?;
+        self.add_device(room_name, name, device)
+    }
+
+    pub fn remove_device(&mut self, room_name: &str, name: &str) -> Result<(), HomeUpdateError> {
+        match self.rooms.get_mut(room_name) {
+            None => Err(HomeUpdateError::DoesNotContainRoom(room_name.to_string())),
+            Some(room) => {
+                room.remove_device(name)?;
+                self.forget(|room, device| room == room_name && device == name);
+                Ok(())
+            }
+        }
+    }
+
+    // unlike `Room::rename_device`, alarms and power budget entries of the device follow it
+    pub fn rename_device(
+        &mut self,
+        room_name: &str,
+        name: &str,
+        new_name: &str,
+    ) -> Result<(), HomeUpdateError> {
+        self.relocate_device(room_name, name, room_name, new_name)
+    }
+
+    // the device keeps its name, tags, alarms and power budget entries; nothing changes on errors
+    pub fn move_device(
+        &mut self,
+        from_room: &str,
+        to_room: &str,
+        name: &str,
+    ) -> Result<(), HomeUpdateError> {
+        self.relocate_device(from_room, name, to_room, name)
+    }
+
+    fn relocate_device(
+        &mut self,
+        from_room: &str,
+        name: &str,
+        to_room: &str,
+        new_name: &str,
+    ) -> Result<(), HomeUpdateError> {
+        let from = self
+            .rooms
+            .get(from_room)
+            .ok_or_else(|| HomeUpdateError::DoesNotContainRoom(from_room.to_string()))?;
+        if from.get_device(name).is_err() {
+            return Err(HomeUpdateError::DoesNotContainDevice {
+                room: from_room.to_string(),
+                device: name.to_string(),
+            });
+        }
+        let to = self
+            .rooms
+            .get(to_room)
+            .ok_or_else(|| HomeUpdateError::DoesNotContainRoom(to_room.to_string()))?;
+        if (from_room, name) == (to_room, new_name) {
+            return Ok(());
+        }
+        if to.get_device(new_name).is_ok() {
+            return Err(HomeUpdateError::DeviceNameTaken {
+                room: to_room.to_string(),
+                device: new_name.to_string(),
+            });
+        }
+
+        let (device, tags) = self.rooms.get_mut(from_room).unwrap().take_device(name)?;
+        self.rooms
+            .get_mut(to_room)
+            .unwrap()
+            .put_device(new_name, device, tags)?;
+        self.relocate(|room, device| {
+            (room == from_room && device == name)
+                .then(|| (to_room.to_string(), new_name.to_string()))
+        });
+        Ok(())
+    }
+
+    // points alarms and the power budget at the new (room, device) of whatever `moved` tells
+    fn relocate(&mut self, moved: impl Fn(&str, &str) -> Option<(String, String)>) {
+        if let Some(Err(err)) = self.budget.as_mut().map(|budget| budget.relocate(&moved)) {
+            warn!(%err, "err saving relocated power budget");
+        }
+        if let Err(err) = self.alarms.relocate(&moved) {
+            warn!(%err, "err saving relocated alarms");
+        }
+    }
+
+    // drops the alarms and power budget entries of whatever `gone` tells was removed
+    fn forget(&mut self, gone: impl Fn(&str, &str) -> bool) {
+        if let Some(Err(err)) = self.budget.as_mut().map(|budget| budget.forget(&gone)) {
+            warn!(%err, "err saving power budget of removed devices");
+        }
+        if let Err(err) = self.alarms.forget(&gone) {
+            warn!(%err, "err saving alarms of removed devices");
+        }
+    }
+
+    pub async fn perform_action(
+        &mut self,
+        room_name: &str,
+        name: &str,
+        action: DeviceAction,
+    ) -> Result<(), HomeUpdateError> {
+        let room = self
+            .rooms
+            .get_mut(room_name)
+            .ok_or_else(|| HomeUpdateError::DoesNotContainRoom(room_name.to_string()))?;
+        room.device_mut(name)?.perform(action).await?;
+        Ok(())
+    }
+
+    // reads every device, returning (room, device, error) for those which could not be read,
+    // those not answering within `DEVICE_TIMEOUT` among them
+    pub async fn refresh(&mut self) -> Vec<(String, String, DeviceReadError)> {
+        let mut failed = vec![];
+        for (room_name, room) in self.rooms.iter_mut() {
+            for (name, err) in room.refresh_where(|_| true, Some(DEVICE_TIMEOUT)).await {
+                failed.push((room_name.to_string(), name, err));
+            }
+        }
+        failed
+    }
+
+    pub fn tag_device(
+        &mut self,
+        room_name: &str,
+        name: &str,
+        tag: &str,
+    ) -> Result<(), HomeUpdateError> {
+        match self.rooms.get_mut(room_name) {
+            None => Err(HomeUpdateError::DoesNotContainRoom(room_name.to_string())),
+            Some(room) => Ok(room.tag_device(name, tag)?),
+        }
+    }
+
+    pub fn untag_device(
+        &mut self,
+        room_name: &str,
+        name: &str,
+        tag: &str,
+    ) -> Result<(), HomeUpdateError> {
+        match self.rooms.get_mut(room_name) {
+            None => Err(HomeUpdateError::DoesNotContainRoom(room_name.to_string())),
+            Some(room) => Ok(room.untag_device(name, tag)?),
+        }
+    }
+
+    // (room, device) of every device the query picks, sorted
+    pub fn find_devices(&self, query: &DeviceQuery) -> Vec<(String, String)> {
+        let untagged = BTreeSet::new();
+        let mut found = vec![];
+        for (room_name, room) in self.rooms.iter() {
+            for (name, device) in room.named_devices() {
+                let tags = room.tags(name).unwrap_or(&untagged);
+                if query.matches(room_name, &device.get_status(), tags) {
+                    found.push((room_name.to_string(), name.to_string()));
+                }
+            }
+        }
+        found.sort();
+        found
+    }
+
+    // the devices tagged with `tag`, wherever they are
+    pub fn group(&self, tag: &str) -> Vec<(String, String)> {
+        self.find_devices(&DeviceQuery::new().with_tag(tag))
+    }
+
+    // performs the action on every device the query picks, all at once, returning how it
+    // went for each (room, device); devices not done within `DEVICE_TIMEOUT` are given up on
+    pub async fn perform_bulk(
+        &mut self,
+        query: &DeviceQuery,
+        action: DeviceAction,
+    ) -> BTreeMap<(String, String), Result<(), HomeUpdateError>> {
+        let picked = self.find_devices(query);
+        let mut keys = vec![];
+        let mut actions = vec![];
+        for (room_name, room) in self.rooms.iter_mut() {
+            for (name, device) in room.named_devices_mut() {
+                let key = (room_name.to_string(), name.to_string());
+                if picked.binary_search(&key).is_ok() {
+                    keys.push(key);
+                    actions.push(tokio::time::timeout(DEVICE_TIMEOUT, device.perform(action)));
+                }
+            }
+        }
+        let results = join_all(actions).await;
+        keys.into_iter()
+            .zip(results)
+            .map(|((room, device), result)| {
+                let result = match result {
+                    Ok(result) => result.map_err(HomeUpdateError::from),
+                    Err(_) => Err(HomeUpdateError::Timeout {
+                        room: room.to_string(),
+                        device: device.to_string(),
+                    }),
+                };
+                ((room, device), result)
+            })
+            .collect()
+    }
+
+    // (room, device, watts) of every power socket which was read, thermostats counting with
+    // what their sockets draw
+    pub fn socket_loads(&self) -> Vec<(String, String, f32)> {
+        let mut loads = vec![];
+        for (room_name, room) in self.rooms.iter() {
+            for (name, device) in room.named_devices() {
+                let status = device.get_status();
+                if !switches_load(status.device_type()) || status.updated().is_none() {
+                    continue;
+                }
+                if let Some((_, watts)) = status.readings().iter().find(|(m, _)| m == "power") {
+                    loads.push((room_name.to_string(), name.to_string(), *watts));
+                }
+            }
+        }
+        loads
+    }
+
+    // reads the power sockets and switches them as the power budget decides, returning the
+    // decisions which were carried out; nothing without a budget
+    pub async fn balance_load(&mut self) -> Vec<LoadDecision> {
+        let Some(budget) = self.budget.as_mut() else {
+            return vec![];
+        };
+        // e.g. shed by an earlier run, before the device was dropped from the config
+        let rooms = &self.rooms;
+        let gone = |room: &str, device: &str| {
+            rooms
+                .get(room)
+                .is_none_or(|room| room.get_device(device).is_err())
+        };
+        if let Err(err) = budget.forget(gone) {
+            warn!(%err, "err saving power budget of removed devices");
+        }
+        for (room_name, room) in self.rooms.iter_mut() {
+            let switchable = |device: &dyn Device| switches_load(device.get_status().device_type());
+            for (name, err) in room.refresh_where(switchable, Some(DEVICE_TIMEOUT)).await {
+                warn!(room = room_name.as_str(), device = name.as_str(), %err, "err reading socket");
+            }
+        }
+
+        let loads = self.socket_loads();
+        let budget = self.budget.as_mut().unwrap();
+        let limit = budget.limit();
+        let mut carried_out = vec![];
+        for decision in budget.plan(&loads) {
+            let action = match decision.action {
+                LoadAction::Shed => DeviceAction::TurnOff,
+                LoadAction::Restore => DeviceAction::TurnOn,
+            };
+            let mut device_type = power_socket::DEVICE_NAME.to_string();
+            let switched = match self.rooms.get_mut(&decision.room) {
+                Some(room) => match room.device_mut(&decision.device) {
+                    Ok(device) => {
+                        device_type = device.get_status().device_type().to_string();
+                        match tokio::time::timeout(DEVICE_TIMEOUT, device.perform(action)).await {
+                            Ok(result) => result.map_err(|err| err.to_string()),
+                            Err(_) => Err("timed out".to_string()),
+                        }
+                    }
+                    Err(err) => Err(err.to_string()),
+                },
+                None => Err(format!("no room '{}'", decision.room)),
+            };
+            if let Err(err) = switched {
+                warn!(%decision, %err, "err switching socket for the power budget");
+                self.budget.as_mut().unwrap().undo(&decision);
+                continue;
+            }
+            info!(%decision, budget = limit, "power budget");
+            self.bus.publish(HomeEvent::LoadShedding {
+                room: decision.room.to_string(),
+                device: decision.device.to_string(),
+                device_type,
+                shed: decision.action == LoadAction::Shed,
+                watts: decision.watts,
+                load: decision.load,
+                budget: limit,
+            });
+            carried_out.push(decision);
+        }
+        if let Err(err) = self.budget.as_ref().unwrap().save() {
+            warn!(%err, "err saving power budget");
+        }
+        carried_out
+    }
+
     pub fn list_rooms(&self) -> Vec<&Room> {
         let mut out = vec![];
 
@@ -67,6 +672,28 @@ impl Home {
         out
     }
 
+    pub fn report(&self) -> HomeReport {
+        let mut rooms: Vec<RoomReport> = self.rooms.values().map(Room::report).collect();
+        rooms.sort_by(|a, b| a.name.cmp(&b.name));
+        HomeReport {
+            name: self.name.to_string(),
+            generated: Timestamp::now(),
+            rooms,
+        }
+    }
+
+    // checks the alarm definitions against the last readings, returning the alarms raised or
+    // cleared by it
+    pub fn evaluate_alarms(&mut self) -> Result<Vec<Alarm>, AlarmError> {
+        let mut statuses = vec![];
+        for (room_name, room) in self.rooms.iter() {
+            for (name, device) in room.named_devices() {
+                statuses.push((room_name.to_string(), name.to_string(), device.get_status()));
+            }
+        }
+        self.alarms.evaluate(&statuses)
+    }
+
     pub fn collect_summary(&self) -> String {
         let mut out = String::new();
         out.push_str(format!("HOME '{}' SUMMARY:\n", &self.name).as_str());
@@ -74,13 +701,138 @@ impl Home {
         for room in self.rooms.values() {
             out.push_str(room.get_summary().as_str())
         }
+
+        let mut alarms = self.alarms.active().peekable();
+        if alarms.peek().is_some() {
+            out.push_str("ALARMS:\n");
+            for alarm in alarms {
+                out.push_str(format!("\t{}\n", alarm).as_str())
+            }
+        }
         out
     }
+
+    // answers a request of a home client
+    pub fn handle_request(&mut self, request: HomeRequest) -> Response {
+        let result = match request {
+            HomeRequest::Ping => return Response::Pong,
+            HomeRequest::Status => return Response::Ok,
+            HomeRequest::HomeAction { method, room_name } => match method {
+                HomeAction::AddRoom => self.add_room(&room_name),
+                HomeAction::RemoveRoom => self.remove_room(&room_name),
+            }
+            .map_err(|err| err.to_string()),
+            HomeRequest::GetAlarms => {
+                return Response::Alarms(self.alarms.active().map(Alarm::status).collect())
+            }
+            HomeRequest::AcknowledgeAlarm { id } => {
+                self.alarms.acknowledge(&id).map_err(|err| err.to_string())
+            }
+            HomeRequest::RenameRoom {
+                room_name,
+                new_name,
+            } => self
+                .rename_room(&room_name, &new_name)
+                .map_err(|err| err.to_string()),
+            HomeRequest::RenameDevice {
+                room_name,
+                device,
+                new_name,
+            } => self
+                .rename_device(&room_name, &device, &new_name)
+                .map_err(|err| err.to_string()),
+            HomeRequest::MoveDevice {
+                from_room,
+                to_room,
+                device,
+            } => self
+                .move_device(&from_room, &to_room, &device)
+                .map_err(|err| err.to_string()),
+            HomeRequest::AddArea { path, kind } => {
+                self.add_area(&path, kind).map_err(|err| err.to_string())
+            }
+            HomeRequest::RemoveArea { path } => {
+                self.remove_area(&path).map_err(|err| err.to_string())
+            }
+            HomeRequest::GetArea { path } => {
+                return match self.area_summary(&path) {
+                    Ok(summary) => Response::Area(summary),
+                    Err(err) => Response::Err(err.to_string()),
+                }
+            }
+        };
+        match result {
+            Ok(()) => Response::Ok,
+            Err(err) => Response::Err(err),
+        }
+    }
+}
+
+// a room of a home open for changes; alarms, power budget entries and credentials of the
+// devices are kept in step as they would be by `Home`
+pub struct RoomMut<'a> {
+    home: &'a mut Home,
+    name: String,
+}
+
+impl Deref for RoomMut<'_> {
+    type Target = Room;
+
+    fn deref(&self) -> &Room {
+        &self.home.rooms[&self.name]
+    }
+}
+
+impl RoomMut<'_> {
+    fn room_mut(&mut self) -> &mut Room {
+        self.home.rooms.get_mut(&self.name).unwrap()
+    }
+
+    pub fn add_device(
+        &mut self,
+        name: &str,
+        device: Box<dyn Device>,
+    ) -> Result<(), HomeUpdateError> {
+        self.home.add_device(&self.name, name, device)
+    }
+
+    pub fn remove_device(&mut self, name: &str) -> Result<(), HomeUpdateError> {
+        self.home.remove_device(&self.name, name)
+    }
+
+    pub fn rename_device(&mut self, name: &str, new_name: &str) -> Result<(), HomeUpdateError> {
+        self.home.rename_device(&self.name, name, new_name)
+    }
+
+    pub fn tag_device(&mut self, name: &str, tag: &str) -> Result<(), HomeUpdateError> {
+        self.home.tag_device(&self.name, name, tag)
+    }
+
+    pub fn untag_device(&mut self, name: &str, tag: &str) -> Result<(), HomeUpdateError> {
+        self.home.untag_device(&self.name, name, tag)
+    }
+
+    pub fn get_device_mut(&mut self, name: &str) -> Result<&mut dyn Device, RoomReadError> {
+        self.room_mut().get_device_mut(name)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::home::Home;
+    use crate::alarms::AlarmDefinition;
+    use crate::area::AreaKind;
+    use crate::budget::{LoadAction, PowerBudget};
+    use crate::devices::power_socket::PowerSocket;
+    use crate::devices::thermometer::Thermometer;
+    use crate::devices::{Device, DeviceActionFuture, DeviceRefreshFuture, DeviceStatus};
+    use crate::events::HomeEvent;
+    use crate::home::{Home, HomeUpdateError};
+    use crate::query::DeviceQuery;
+    use crate::report::ConditionState;
+    use s_home_proto::{AlarmState, DeviceAction, HomeAction, HomeRequest, Response};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Instant;
 
     static KITCHEN: &str = "kitchen";
 
@@ -142,6 +894,88 @@ mod tests {
         assert_eq!(2, rooms.len())
     }
 
+    #[test]
+    fn test_events() {
+        let mut home = new_home();
+        let mut rx = home.subscribe();
+
+        home.add_room(KITCHEN).unwrap();
+        home.add_device(KITCHEN, "socket", Box::new(PowerSocket::new("socket", "")))
+            .unwrap();
+        home.remove_room(KITCHEN).unwrap();
+
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            HomeEvent::RoomAdded {
+                room: KITCHEN.to_string()
+            }
+        );
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            HomeEvent::DeviceAdded {
+                room: KITCHEN.to_string(),
+                device: "socket".to_string(),
+                device_type: "PSOC".to_string(),
+            }
+        );
+        // the devices go before the room does
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            HomeEvent::DeviceRemoved {
+                room: KITCHEN.to_string(),
+                device: "socket".to_string(),
+                device_type: "PSOC".to_string(),
+            }
+        );
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            HomeEvent::RoomRemoved {
+                room: KITCHEN.to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_perform_action() {
+        let mut home = new_home();
+        home.add_room(KITCHEN).unwrap();
+        home.add_device(KITCHEN, "socket", Box::new(PowerSocket::new("socket", "")))
+            .unwrap();
+        home.add_device(KITCHEN, "thrm", Box::new(Thermometer::new("thrm", "")))
+            .unwrap();
+
+        home.perform_action(KITCHEN, "socket", DeviceAction::TurnOn)
+            .await
+            .unwrap();
+        assert!(home
+            .perform_action(KITCHEN, "thrm", DeviceAction::TurnOn)
+            .await
+            .is_err());
+        assert!(home
+            .perform_action(KITCHEN, "missing", DeviceAction::TurnOn)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_report() {
+        let mut home = new_home();
+        for room in ["living room", KITCHEN] {
+            home.add_room(room).unwrap();
+        }
+        home.add_device(KITCHEN, "socket", Box::new(PowerSocket::new("socket", "")))
+            .unwrap();
+
+        let report = home.report();
+        assert_eq!(report.name, "test home");
+        let rooms: Vec<&str> = report.rooms.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(rooms, [KITCHEN, "living room"]);
+        let socket = &report.rooms[0].devices[0];
+        assert_eq!(socket.device_type, "PSOC");
+        assert_eq!(socket.condition, ConditionState::Ok);
+        assert!(report.rooms[1].devices.is_empty());
+    }
+
     #[test]
     fn test_collect_summary() {
         let blank_summary = format!("HOME '{}' SUMMARY:\n", "test home");
@@ -158,4 +992,556 @@ mod tests {
         let summary = home.collect_summary();
         assert_eq!(summary, summary_with_kitchen)
     }
+
+    // a thermometer of a freezing room
+    struct Frozen;
+
+    impl Device for Frozen {
+        fn get_status(&self) -> DeviceStatus {
+            DeviceStatus::quick_unknown("thermometer", "THRM")
+                .with_readings(&[("temperature", 2.5)], Instant::now())
+        }
+    }
+
+    #[test]
+    fn test_alarms() {
+        let mut home = new_home();
+        home.add_room(KITCHEN).unwrap();
+        home.add_device(KITCHEN, "thermometer", Box::new(Frozen))
+            .unwrap();
+        home.alarms_mut()
+            .define(
+                KITCHEN,
+                "thermometer",
+                AlarmDefinition::new("temperature").with_min(5.0),
+            )
+            .unwrap();
+
+        assert!(!home.collect_summary().contains("ALARMS"));
+        assert_eq!(home.evaluate_alarms().unwrap().len(), 1);
+        let id = "kitchen/thermometer/temperature/min";
+        assert!(home.collect_summary().ends_with(&format!(
+            "ALARMS:\n\t[RAISED] {}: temperature 2.5 below 5\n",
+            id
+        )));
+
+        let Response::Alarms(alarms) = home.handle_request(HomeRequest::GetAlarms) else {
+            panic!("alarms expected")
+        };
+        assert_eq!(alarms.len(), 1);
+        assert_eq!(alarms[0].value, Some(2.5));
+        assert_eq!(
+            home.handle_request(HomeRequest::AcknowledgeAlarm { id: id.to_string() }),
+            Response::Ok
+        );
+        assert_eq!(
+            home.alarms().get(id).unwrap().state,
+            AlarmState::Acknowledged
+        );
+        assert!(matches!(
+            home.handle_request(HomeRequest::AcknowledgeAlarm {
+                id: "missing".to_string()
+            }),
+            Response::Err(_)
+        ));
+
+        // a device added again under the name starts without the old one's alarms
+        home.remove_device(KITCHEN, "thermometer").unwrap();
+        assert!(home.alarms().definitions().is_empty());
+        assert!(home.alarms().get(id).is_none());
+
+        home.add_device(KITCHEN, "thermometer", Box::new(Frozen))
+            .unwrap();
+        home.alarms_mut()
+            .define(
+                KITCHEN,
+                "thermometer",
+                AlarmDefinition::new("temperature").with_min(5.0),
+            )
+            .unwrap();
+        home.evaluate_alarms().unwrap();
+        home.remove_room(KITCHEN).unwrap();
+        assert!(home.alarms().definitions().is_empty());
+        assert_eq!(home.alarms().active().count(), 0);
+    }
+
+    #[test]
+    fn test_handle_request() {
+        let mut home = new_home();
+        assert_eq!(home.handle_request(HomeRequest::Ping), Response::Pong);
+        let add = || HomeRequest::HomeAction {
+            method: HomeAction::AddRoom,
+            room_name: KITCHEN.to_string(),
+        };
+        assert_eq!(home.handle_request(add()), Response::Ok);
+        assert_eq!(
+            home.handle_request(add()),
+            Response::Err("home already contains room 'kitchen'".to_string())
+        );
+        assert_eq!(
+            home.handle_request(HomeRequest::HomeAction {
+                method: HomeAction::RemoveRoom,
+                room_name: KITCHEN.to_string(),
+            }),
+            Response::Ok
+        );
+        assert!(home.list_rooms().is_empty());
+    }
+
+    // a socket drawing `watts` while it is on
+    struct Load {
+        watts: f32,
+        on: Arc<AtomicBool>,
+    }
+
+    impl Device for Load {
+        fn get_status(&self) -> DeviceStatus {
+            let watts = match self.on.load(Ordering::SeqCst) {
+                true => self.watts,
+                false => 0.0,
+            };
+            DeviceStatus::quick_unknown("load", "PSOC")
+                .with_readings(&[("power", watts)], Instant::now())
+        }
+
+        fn perform(&mut self, action: DeviceAction) -> DeviceActionFuture<'_> {
+            self.on
+                .store(action == DeviceAction::TurnOn, Ordering::SeqCst);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_balance_load() {
+        let mut home = new_home().with_power_budget(
+            PowerBudget::new(3000.0)
+                .with_priority(KITCHEN, "kettle", 1)
+                .with_priority(KITCHEN, "oven", 2),
+        );
+        let mut events = home
+            .bus()
+            .subscribe_filtered(|e| matches!(e, HomeEvent::LoadShedding { .. }));
+        home.add_room(KITCHEN).unwrap();
+        let mut switches = vec![];
+        for (name, watts) in [("kettle", 2000.0), ("oven", 2500.0)] {
+            let on = Arc::new(AtomicBool::new(true));
+            switches.push(on.clone());
+            home.add_device(KITCHEN, name, Box::new(Load { watts, on }))
+                .unwrap();
+        }
+
+        let decisions = home.balance_load().await;
+        assert_eq!(decisions.len(), 1);
+        assert_eq!(decisions[0].device, "kettle");
+        assert_eq!(decisions[0].action, LoadAction::Shed);
+        assert!(!switches[0].load(Ordering::SeqCst));
+        assert_eq!(
+            events.try_recv(),
+            Some(HomeEvent::LoadShedding {
+                room: KITCHEN.to_string(),
+                device: "kettle".to_string(),
+                device_type: "PSOC".to_string(),
+                shed: true,
+                watts: 2000.0,
+                load: 2500.0,
+                budget: 3000.0,
+            })
+        );
+        assert!(home.balance_load().await.is_empty());
+
+        // the oven is done, the kettle fits again
+        home.perform_action(KITCHEN, "oven", DeviceAction::TurnOff)
+            .await
+            .unwrap();
+        let decisions = home.balance_load().await;
+        assert_eq!(decisions[0].action, LoadAction::Restore);
+        assert!(switches[0].load(Ordering::SeqCst));
+        assert!(matches!(
+            events.try_recv(),
+            Some(HomeEvent::LoadShedding { shed: false, .. })
+        ));
+    }
+
+    // a thermostat heating away that never gets round to switching off
+    struct Stuck;
+
+    impl Device for Stuck {
+        fn get_status(&self) -> DeviceStatus {
+            DeviceStatus::quick_unknown("heater", "TSTAT")
+                .with_readings(&[("power", 2500.0)], Instant::now())
+        }
+
+        fn perform(&mut self, _action: DeviceAction) -> DeviceActionFuture<'_> {
+            Box::pin(std::future::pending())
+        }
+
+        fn refresh(&mut self) -> DeviceRefreshFuture<'_> {
+            Box::pin(std::future::pending())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_refresh_timeout() {
+        let mut home = new_home();
+        home.add_room(KITCHEN).unwrap();
+        home.add_device(KITCHEN, "heater", Box::new(Stuck)).unwrap();
+        home.add_device(KITCHEN, "thermometer", Box::new(Frozen))
+            .unwrap();
+
+        // a device that never answers is reported, not waited on forever
+        let failed = home.refresh().await;
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].1, "heater");
+        assert_eq!(
+            failed[0].2.to_string(),
+            "err making request: refresh timed out"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_balance_load_timeout() {
+        let mut home = new_home().with_power_budget(
+            PowerBudget::new(3000.0)
+                .with_priority(KITCHEN, "heater", 1)
+                .with_priority(KITCHEN, "kettle", 2),
+        );
+        home.add_room(KITCHEN).unwrap();
+        home.add_device(KITCHEN, "heater", Box::new(Stuck)).unwrap();
+        let on = Arc::new(AtomicBool::new(true));
+        home.add_device(KITCHEN, "kettle", Box::new(Load { watts: 1000.0, on }))
+            .unwrap();
+
+        // the thermostat's sockets count towards the budget like any other
+        assert!(home
+            .socket_loads()
+            .contains(&(KITCHEN.to_string(), "heater".to_string(), 2500.0)));
+        assert!(home.balance_load().await.is_empty());
+        assert!(!home.power_budget().unwrap().is_shed(KITCHEN, "heater"));
+    }
+
+    #[tokio::test]
+    async fn test_perform_bulk() {
+        let mut home = new_home();
+        let mut switches = vec![];
+        for room in [KITCHEN, "garden", "porch"] {
+            home.add_room(room).unwrap();
+            let on = Arc::new(AtomicBool::new(true));
+            switches.push(on.clone());
+            home.add_device(room, "lamp", Box::new(Load { watts: 60.0, on }))
+                .unwrap();
+        }
+        home.add_device("garden", "thrm", Box::new(Thermometer::new("thrm", "")))
+            .unwrap();
+        for (room, device) in [("garden", "lamp"), ("porch", "lamp"), ("garden", "thrm")] {
+            home.tag_device(room, device, "outdoor").unwrap();
+        }
+        assert!(home.tag_device("garden", "missing", "outdoor").is_err());
+
+        let outdoor = home.group("outdoor");
+        assert_eq!(outdoor.len(), 3);
+        assert_eq!(outdoor[0], ("garden".to_string(), "lamp".to_string()));
+        let query = DeviceQuery::new().with_tag("outdoor").of_type("PSOC");
+        assert_eq!(home.find_devices(&query).len(), 2);
+        assert_eq!(
+            home.find_devices(&DeviceQuery::new().with_condition(ConditionState::Ok))
+                .len(),
+            4
+        );
+
+        let results = home
+            .perform_bulk(
+                &DeviceQuery::new().with_tag("outdoor"),
+                DeviceAction::TurnOff,
+            )
+            .await;
+        assert_eq!(results.len(), 3);
+        assert!(results[&("porch".to_string(), "lamp".to_string())].is_ok());
+        // thermometers have no switch
+        assert!(results[&("garden".to_string(), "thrm".to_string())].is_err());
+        let on: Vec<bool> = switches
+            .iter()
+            .map(|on| on.load(Ordering::SeqCst))
+            .collect();
+        assert_eq!(on, [true, false, false]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_perform_bulk_timeout() {
+        let mut home = new_home();
+        home.add_room(KITCHEN).unwrap();
+        home.add_device(KITCHEN, "heater", Box::new(Stuck)).unwrap();
+        let on = Arc::new(AtomicBool::new(true));
+        home.add_device(
+            KITCHEN,
+            "lamp",
+            Box::new(Load {
+                watts: 60.0,
+                on: on.clone(),
+            }),
+        )
+        .unwrap();
+
+        let results = home
+            .perform_bulk(&DeviceQuery::new(), DeviceAction::TurnOff)
+            .await;
+        // the stuck one does not keep the others from being switched
+        assert!(results[&(KITCHEN.to_string(), "lamp".to_string())].is_ok());
+        assert!(!on.load(Ordering::SeqCst));
+        assert_eq!(
+            results[&(KITCHEN.to_string(), "heater".to_string())]
+                .as_ref()
+                .unwrap_err()
+                .to_string(),
+            "device 'heater' in room 'kitchen' timed out"
+        );
+    }
+
+    #[test]
+    fn test_areas() {
+        let mut home = new_home();
+        home.add_area("floor1", AreaKind::Floor).unwrap();
+        home.add_area("floor1/east", AreaKind::Zone).unwrap();
+        assert!(matches!(
+            home.add_area("floor1/east/up", AreaKind::Floor),
+            Err(HomeUpdateError::InvalidArea(_))
+        ));
+        assert!(matches!(
+            home.add_area("floor2/west", AreaKind::Zone),
+            Err(HomeUpdateError::DoesNotContainArea(_))
+        ));
+        assert!(matches!(
+            home.add_room("floor1"),
+            Err(HomeUpdateError::AlreadyContainsArea(_))
+        ));
+        assert!(matches!(
+            home.add_room("floor1//kitchen"),
+            Err(HomeUpdateError::InvalidArea(_))
+        ));
+
+        for room in ["floor1/kitchen", "floor1/east/bedroom", "garage"] {
+            home.add_room(room).unwrap();
+        }
+        for (room, watts) in [("floor1/kitchen", 2000.0), ("floor1/east/bedroom", 60.0)] {
+            let on = Arc::new(AtomicBool::new(true));
+            home.add_device(room, "socket", Box::new(Load { watts, on }))
+                .unwrap();
+        }
+        home.add_device("garage", "socket", Box::new(PowerSocket::new("socket", "")))
+            .unwrap();
+
+        let summary = home.area_summary("").unwrap();
+        assert_eq!((summary.rooms, summary.devices), (3, 3));
+        assert_eq!(summary.power, 2060.0);
+        let floor = &summary.areas[0];
+        assert_eq!(
+            (floor.path.as_str(), floor.kind),
+            ("floor1", AreaKind::Floor)
+        );
+        assert_eq!((floor.rooms, floor.power), (2, 2060.0));
+        assert_eq!(floor.areas[0].power, 60.0);
+        assert_eq!(
+            home.collect_area_summary("floor1/east").unwrap(),
+            "AREA 'floor1/east' (ZONE) SUMMARY:\n\trooms: 1, devices: 1, faulty: 0, power: 60.0 W, energy: 0.000 kWh\n"
+        );
+        assert!(home.area_summary("attic").is_err());
+
+        assert!(matches!(
+            home.remove_area("floor1"),
+            Err(HomeUpdateError::AreaNotEmpty(_))
+        ));
+        home.remove_room("floor1/east/bedroom").unwrap();
+        home.remove_area("floor1/east").unwrap();
+        assert_eq!(home.list_areas(), [("floor1", AreaKind::Floor)]);
+
+        let get = |path: &str| HomeRequest::GetArea {
+            path: path.to_string(),
+        };
+        assert!(matches!(
+            home.handle_request(get("floor1")),
+            Response::Area(_)
+        ));
+        assert!(matches!(
+            home.handle_request(get("floor9")),
+            Response::Err(_)
+        ));
+        assert_eq!(
+            home.handle_request(HomeRequest::AddArea {
+                path: "floor1/west".to_string(),
+                kind: AreaKind::Zone,
+            }),
+            Response::Ok
+        );
+        assert_eq!(
+            home.handle_request(HomeRequest::HomeAction {
+                method: HomeAction::AddRoom,
+                room_name: "floor1/west/office".to_string(),
+            }),
+            Response::Ok
+        );
+        assert_eq!(home.rooms_in("floor1/west")[0].name, "floor1/west/office");
+    }
+
+    #[test]
+    fn test_rename_room() {
+        let mut home = new_home()
+            .with_power_budget(PowerBudget::new(3000.0).with_priority(KITCHEN, "kettle", 1));
+        home.add_area("floor1", AreaKind::Floor).unwrap();
+        for room in [KITCHEN, "garage"] {
+            home.add_room(room).unwrap();
+        }
+        let on = Arc::new(AtomicBool::new(true));
+        home.add_device(KITCHEN, "kettle", Box::new(Load { watts: 2000.0, on }))
+            .unwrap();
+        home.add_device(KITCHEN, "thermometer", Box::new(Frozen))
+            .unwrap();
+        home.tag_device(KITCHEN, "kettle", "appliances").unwrap();
+        home.alarms_mut()
+            .define(
+                KITCHEN,
+                "thermometer",
+                AlarmDefinition::new("temperature").with_min(5.0),
+            )
+            .unwrap();
+        home.evaluate_alarms().unwrap();
+
+        for (to, err) in [
+            ("garage", "home already contains room 'garage'"),
+            ("floor1", "home already contains area 'floor1'"),
+            ("floor2/kitchen", "home does not contain area 'floor2'"),
+        ] {
+            assert_eq!(home.rename_room(KITCHEN, to).unwrap_err().to_string(), err);
+        }
+        assert!(matches!(
+            home.rename_room("attic", "loft"),
+            Err(HomeUpdateError::DoesNotContainRoom(_))
+        ));
+        assert!(home.get_room(KITCHEN).is_ok());
+
+        let mut events = home.subscribe();
+        home.rename_room(KITCHEN, "floor1/kitchen").unwrap();
+        assert!(home.get_room(KITCHEN).is_err());
+        let room = home.get_room("floor1/kitchen").unwrap();
+        assert_eq!(room.name, "floor1/kitchen");
+        assert_eq!(room.list_devices().len(), 2);
+        assert_eq!(room.device_tags("kettle"), ["appliances"]);
+        assert_eq!(home.group("appliances")[0].0, "floor1/kitchen");
+
+        let mut removed = 0;
+        while let Some(event) = events.try_recv() {
+            if let HomeEvent::DeviceAdded { room, .. } = event {
+                assert_eq!(room, "floor1/kitchen");
+            } else if matches!(event, HomeEvent::DeviceRemoved { .. }) {
+                removed += 1;
+            }
+        }
+        assert_eq!(removed, 2);
+
+        let id = "floor1/kitchen/thermometer/temperature/min";
+        assert_eq!(home.alarms().active().next().unwrap().id, id);
+        assert_eq!(home.alarms().definitions()[0].room, "floor1/kitchen");
+        assert!(home.evaluate_alarms().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_move_device() {
+        let mut home = new_home()
+            .with_power_budget(PowerBudget::new(1000.0).with_priority(KITCHEN, "kettle", 1));
+        for room in [KITCHEN, "garage"] {
+            home.add_room(room).unwrap();
+        }
+        let on = Arc::new(AtomicBool::new(true));
+        home.add_device(KITCHEN, "kettle", Box::new(Load { watts: 2000.0, on }))
+            .unwrap();
+        for name in ["socket", "kettle"] {
+            home.add_device("garage", name, Box::new(PowerSocket::new(name, "")))
+                .unwrap();
+        }
+
+        assert!(matches!(
+            home.move_device(KITCHEN, "garage", "toaster"),
+            Err(HomeUpdateError::DoesNotContainDevice { .. })
+        ));
+        assert!(matches!(
+            home.move_device(KITCHEN, "attic", "kettle"),
+            Err(HomeUpdateError::DoesNotContainRoom(_))
+        ));
+        assert_eq!(
+            home.move_device(KITCHEN, "garage", "kettle")
+                .unwrap_err()
+                .to_string(),
+            "room 'garage' already contains device 'kettle'"
+        );
+        assert!(home.get_room(KITCHEN).unwrap().get_device("kettle").is_ok());
+        assert!(matches!(
+            home.rename_device("garage", "kettle", "socket"),
+            Err(HomeUpdateError::DeviceNameTaken { .. })
+        ));
+
+        home.rename_device("garage", "kettle", "heater").unwrap();
+        home.move_device(KITCHEN, "garage", "kettle").unwrap();
+        assert!(home.get_room(KITCHEN).unwrap().list_devices().is_empty());
+        assert_eq!(home.get_room("garage").unwrap().list_devices().len(), 3);
+
+        // the budget still knows it, in its new room
+        let decisions = home.balance_load().await;
+        assert_eq!(
+            (decisions[0].room.as_str(), decisions[0].device.as_str()),
+            ("garage", "kettle")
+        );
+    }
+
+    #[test]
+    fn test_mutable_access() {
+        let mut home = new_home();
+        home.add_room(KITCHEN).unwrap();
+        home.get_room_mut(KITCHEN)
+            .unwrap()
+            .add_device("socket", Box::new(PowerSocket::new("socket", "")))
+            .unwrap();
+        assert!(home.get_room_mut("attic").is_err());
+
+        // removed through the room, the device's alarms go along like they would through `Home`
+        let mut room = home.get_room_mut(KITCHEN).unwrap();
+        room.add_device("thermometer", Box::new(Frozen)).unwrap();
+        assert_eq!(room.list_devices().len(), 2);
+        home.alarms_mut()
+            .define(
+                KITCHEN,
+                "thermometer",
+                AlarmDefinition::new("temperature").with_min(5.0),
+            )
+            .unwrap();
+        let mut room = home.get_room_mut(KITCHEN).unwrap();
+        room.rename_device("thermometer", "thrm").unwrap();
+        room.remove_device("thrm").unwrap();
+        assert!(home.alarms().definitions().is_empty());
+
+        let request = |room_name: &str, new_name: &str| HomeRequest::RenameRoom {
+            room_name: room_name.to_string(),
+            new_name: new_name.to_string(),
+        };
+        assert_eq!(home.handle_request(request(KITCHEN, "hall")), Response::Ok);
+        assert!(matches!(
+            home.handle_request(request(KITCHEN, "hall")),
+            Response::Err(_)
+        ));
+        assert_eq!(
+            home.handle_request(HomeRequest::RenameDevice {
+                room_name: "hall".to_string(),
+                device: "socket".to_string(),
+                new_name: "lamp".to_string(),
+            }),
+            Response::Ok
+        );
+        home.add_room(KITCHEN).unwrap();
+        assert_eq!(
+            home.handle_request(HomeRequest::MoveDevice {
+                from_room: "hall".to_string(),
+                to_room: KITCHEN.to_string(),
+                device: "lamp".to_string(),
+            }),
+            Response::Ok
+        );
+        assert!(home.get_room(KITCHEN).unwrap().get_device("lamp").is_ok());
+    }
 }
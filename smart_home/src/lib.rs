@@ -1,5 +1,20 @@
 #![allow(dead_code)]
 
+pub mod alarms;
+pub mod area;
+pub mod budget;
+pub mod config;
+pub mod dashboard;
 pub mod devices;
-mod home;
-mod room;
+pub mod discovery;
+pub mod events;
+pub mod feed;
+pub mod health;
+pub mod history;
+pub mod home;
+pub mod metrics;
+pub mod mqtt;
+pub mod query;
+pub mod report;
+pub mod room;
+mod store;
@@ -0,0 +1,348 @@
+use crate::events::{EventBus, HomeEvent};
+use crate::home::Home;
+use s_home_proto::metrics::escape_label;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+static CONDITIONS: [&str; 3] = ["OK", "ERROR", "UNKNOWN"];
+
+struct DeviceGauges {
+    device_type: String,
+    readings: BTreeMap<String, f32>,
+    condition: String,
+    updated: Option<Instant>,
+}
+
+// gauges named after well known metrics, anything else goes to the generic one
+fn gauge_name(metric: &str) -> Option<&'static str> {
+    match metric {
+        "power" => Some("smart_home_device_power_watts"),
+        "temperature" => Some("smart_home_device_temperature_celsius"),
+        _ => None,
+    }
+}
+
+// "ERROR: reason" is reported as the ERROR state, the reason would explode label cardinality
+fn condition_state(condition: &str) -> &str {
+    CONDITIONS
+        .iter()
+        .find(|state| condition.starts_with(*state))
+        .copied()
+        .unwrap_or("UNKNOWN")
+}
+
+// device gauges kept up to date from the event bus, so rendering never touches the devices
+pub struct HomeMetrics {
+    home: String,
+    // (room, device) -> gauges
+    devices: Mutex<BTreeMap<(String, String), DeviceGauges>>,
+}
+
+impl HomeMetrics {
+    pub fn new(home: &str) -> Arc<Self> {
+        Arc::new(Self {
+            home: home.to_string(),
+            devices: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    // seeds gauges with devices which were added before the metrics were started
+    pub fn observe_home(&self, home: &Home) {
+        let mut devices = self.devices.lock().unwrap();
+        for room in home.list_rooms() {
+            for (name, device) in room.named_devices() {
+                let status = device.get_status();
+                devices.insert(
+                    (room.name.to_string(), name.to_string()),
+                    DeviceGauges {
+                        device_type: status.device_type().to_string(),
+                        readings: status.readings().iter().cloned().collect(),
+                        condition: status.condition().to_string(),
+                        updated: status.updated(),
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn observe_event(&self, event: &HomeEvent) {
+        let mut devices = self.devices.lock().unwrap();
+        match event {
+            HomeEvent::RoomRemoved { room } => devices.retain(|(r, _), _| r != room),
+            HomeEvent::DeviceAdded {
+                room,
+                device,
+                device_type,
+            } => {
+                devices.insert(
+                    (room.to_string(), device.to_string()),
+                    DeviceGauges {
+                        device_type: device_type.to_string(),
+                        readings: BTreeMap::new(),
+                        condition: "UNKNOWN".to_string(),
+                        updated: None,
+                    },
+                );
+            }
+            HomeEvent::DeviceRemoved { room, device, .. } => {
+                devices.remove(&(room.to_string(), device.to_string()));
+            }
+            HomeEvent::ReadingChanged {
+                room,
+                device,
+                metric,
+                value,
+                ..
+            } => {
+                if let Some(gauges) = devices.get_mut(&(room.to_string(), device.to_string())) {
+                    gauges.readings.insert(metric.to_string(), *value);
+                    gauges.updated = Some(Instant::now());
+                }
+            }
+            // readings are only published when they change, the device may well be up to date
+            HomeEvent::DeviceRefreshed { room, device, .. } => {
+                if let Some(gauges) = devices.get_mut(&(room.to_string(), device.to_string())) {
+                    gauges.updated = Some(Instant::now());
+                }
+            }
+            HomeEvent::ConditionChanged {
+                room,
+                device,
+                condition,
+                ..
+            } => {
+                if let Some(gauges) = devices.get_mut(&(room.to_string(), device.to_string())) {
+                    gauges.condition = condition.to_string();
+                }
+            }
+            HomeEvent::RoomAdded { .. }
+            | HomeEvent::PowerSwitched { .. }
+            | HomeEvent::HealthChanged { .. }
+            | HomeEvent::LoadShedding { .. } => {}
+        }
+    }
+
+    pub fn spawn(self: &Arc<Self>, bus: &EventBus) -> JoinHandle<()> {
+        let metrics = Arc::clone(self);
+        let mut events = bus.subscribe();
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                metrics.observe_event(&event)
+            }
+        })
+    }
+
+    pub fn render(&self) -> String {
+        let devices = self.devices.lock().unwrap();
+        let home = escape_label(&self.home);
+        let labels = |room: &str, device: &str, device_type: &str| {
+            format!(
+                "home=\"{}\",room=\"{}\",device=\"{}\",type=\"{}\"",
+                home,
+                escape_label(room),
+                escape_label(device),
+                escape_label(device_type)
+            )
+        };
+
+        let mut known: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        let mut generic = vec![];
+        let mut ages = vec![];
+        let mut conditions = vec![];
+        for ((room, device), gauges) in devices.iter() {
+            let labels = labels(room, device, &gauges.device_type);
+            for (metric, value) in gauges.readings.iter() {
+                match gauge_name(metric) {
+                    Some(name) => known
+                        .entry(name)
+                        .or_default()
+                        .push(format!("{}{{{}}} {}", name, labels, value)),
+                    None => generic.push(format!(
+                        "smart_home_device_reading{{{},metric=\"{}\"}} {}",
+                        labels,
+                        escape_label(metric),
+                        value
+                    )),
+                }
+            }
+            if let Some(updated) = gauges.updated {
+                ages.push(format!(
+                    "smart_home_device_last_update_age_seconds{{{}}} {:.3}",
+                    labels,
+                    updated.elapsed().as_secs_f64()
+                ));
+            }
+            let state = condition_state(&gauges.condition);
+            for condition in CONDITIONS {
+                conditions.push(format!(
+                    "smart_home_device_condition{{{},condition=\"{}\"}} {}",
+                    labels,
+                    condition,
+                    (condition == state) as u8
+                ));
+            }
+        }
+
+        let mut out = String::new();
+        let mut section = |name: &str, help: &str, lines: &[String]| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            for line in lines {
+                out.push_str(line);
+                out.push('\n');
+            }
+        };
+        for (name, lines) in known.iter() {
+            section(name, "Last known device reading.", lines);
+        }
+        if !generic.is_empty() {
+            section(
+                "smart_home_device_reading",
+                "Last known device reading.",
+                &generic,
+            );
+        }
+        section(
+            "smart_home_device_last_update_age_seconds",
+            "Seconds since the device reading was last updated.",
+            &ages,
+        );
+        section(
+            "smart_home_device_condition",
+            "Device condition, 1 for the current one.",
+            &conditions,
+        );
+        out
+    }
+}
+
+// answers `GET /metrics` with the rendered gauges
+pub async fn serve_metrics(
+    listener: TcpListener,
+    metrics: Arc<HomeMetrics>,
+) -> std::io::Result<()> {
+    info!(address = %listener.local_addr()?, "metrics endpoint listening");
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let metrics = Arc::clone(&metrics);
+        tokio::spawn(async move {
+            let mut request_line = String::new();
+            let mut reader = BufReader::new(&mut stream);
+            if reader.read_line(&mut request_line).await.is_err() {
+                return;
+            }
+            let response = s_home_proto::metrics::http_response(&request_line, || metrics.render());
+            if let Err(err) = stream.write_all(response.as_bytes()).await {
+                warn!(%err, "err writing metrics response")
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::power_socket::PowerSocket;
+    use crate::events::HomeEvent;
+    use crate::home::Home;
+    use crate::metrics::{serve_metrics, HomeMetrics};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn test_observe_home() {
+        let mut home = Home::new("test home");
+        home.add_room("kitchen").unwrap();
+        home.add_device(
+            "kitchen",
+            "socket",
+            Box::new(PowerSocket::new("socket", "")),
+        )
+        .unwrap();
+
+        let metrics = HomeMetrics::new("test home");
+        metrics.observe_home(&home);
+
+        let text = metrics.render();
+        let labels = "home=\"test home\",room=\"kitchen\",device=\"socket\",type=\"PSOC\"";
+        assert!(text.contains(&format!("smart_home_device_power_watts{{{}}} 0\n", labels)));
+        assert!(text.contains(&format!(
+            "smart_home_device_condition{{{},condition=\"OK\"}} 1\n",
+            labels
+        )));
+    }
+
+    #[test]
+    fn test_observe_event() {
+        let metrics = HomeMetrics::new("home");
+        metrics.observe_event(&HomeEvent::DeviceAdded {
+            room: "hall".to_string(),
+            device: "thrm".to_string(),
+            device_type: "THRM".to_string(),
+        });
+        metrics.observe_event(&HomeEvent::ReadingChanged {
+            room: "hall".to_string(),
+            device: "thrm".to_string(),
+            device_type: "THRM".to_string(),
+            metric: "temperature".to_string(),
+            value: 21.5,
+        });
+        metrics.observe_event(&HomeEvent::ConditionChanged {
+            room: "hall".to_string(),
+            device: "thrm".to_string(),
+            device_type: "THRM".to_string(),
+            condition: "ERROR: timeout".to_string(),
+        });
+
+        let text = metrics.render();
+        assert!(text.contains("smart_home_device_temperature_celsius{home=\"home\",room=\"hall\",device=\"thrm\",type=\"THRM\"} 21.5\n"));
+        assert!(text.contains("condition=\"ERROR\"} 1\n"));
+        assert!(text.contains("smart_home_device_last_update_age_seconds{"));
+
+        metrics.observe_event(&HomeEvent::RoomRemoved {
+            room: "hall".to_string(),
+        });
+        assert!(!metrics.render().contains("thrm"));
+    }
+
+    #[test]
+    fn test_refreshed_without_change() {
+        let metrics = HomeMetrics::new("home");
+        metrics.observe_event(&HomeEvent::DeviceAdded {
+            room: "hall".to_string(),
+            device: "thrm".to_string(),
+            device_type: "THRM".to_string(),
+        });
+        let age = "smart_home_device_last_update_age_seconds{";
+        assert!(!metrics.render().contains(age));
+
+        metrics.observe_event(&HomeEvent::DeviceRefreshed {
+            room: "hall".to_string(),
+            device: "thrm".to_string(),
+            device_type: "THRM".to_string(),
+        });
+        assert!(metrics.render().contains(age));
+    }
+
+    #[tokio::test]
+    async fn test_serve_metrics() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_metrics(listener, HomeMetrics::new("home")));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut buf = String::new();
+        stream.read_to_string(&mut buf).await.unwrap();
+        assert!(buf.starts_with("HTTP/1.1 200 OK"));
+        assert!(buf.contains("# TYPE smart_home_device_condition gauge"));
+    }
+}
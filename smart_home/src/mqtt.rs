@@ -0,0 +1,563 @@
+use crate::events::{EventBus, HomeEvent};
+use crate::home::Home;
+use s_home_proto::DeviceAction;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+static COMMAND_LEAF: &str = "set";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MqttMessage {
+    pub topic: String,
+    pub payload: String,
+    pub retain: bool,
+}
+
+impl MqttMessage {
+    fn new(topic: String, payload: String, retain: bool) -> Self {
+        Self {
+            topic,
+            payload,
+            retain,
+        }
+    }
+}
+
+// a command received on a `.../set` topic, resolved back to real room and device names
+#[derive(Debug, PartialEq)]
+pub struct MqttCommand {
+    pub room: String,
+    pub device: String,
+    pub action: DeviceAction,
+}
+
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    pub home: String,
+    pub prefix: String,
+    pub discovery_prefix: String,
+}
+
+impl MqttConfig {
+    pub fn new(home: &str) -> Self {
+        Self {
+            home: home.to_string(),
+            prefix: "smart_home".to_string(),
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+}
+
+// topics can't contain wildcards or separators, so names are reduced to [a-z0-9_]
+fn slug(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// topic bases handed out to devices; slugs of different names can be the same, e.g. of
+// "Socket 1" and "socket_1", so later ones get a numbered suffix
+#[derive(Default)]
+struct Topics {
+    // device topic base -> (room, device)
+    devices: HashMap<String, (String, String)>,
+    // (room, device) -> device topic base
+    bases: HashMap<(String, String), String>,
+}
+
+// translates home events to MQTT messages and MQTT commands to device actions;
+// the broker connection lives behind the `outgoing` channel, see `connect`
+#[derive(Clone)]
+pub struct MqttBridge {
+    config: MqttConfig,
+    outgoing: mpsc::Sender<MqttMessage>,
+    known: Arc<Mutex<Topics>>,
+}
+
+impl MqttBridge {
+    pub fn new(config: MqttConfig, outgoing: mpsc::Sender<MqttMessage>) -> Self {
+        Self {
+            config,
+            outgoing,
+            known: Arc::new(Mutex::new(Topics::default())),
+        }
+    }
+
+    fn device_base(&self, room: &str, device: &str) -> String {
+        let key = (room.to_string(), device.to_string());
+        match self.known.lock().unwrap().bases.get(&key) {
+            Some(base) => base.to_string(),
+            None => self.slug_base(room, device),
+        }
+    }
+
+    // the base the device keeps until it is forgotten
+    fn register(&self, room: &str, device: &str) -> String {
+        let mut known = self.known.lock().unwrap();
+        let key = (room.to_string(), device.to_string());
+        if let Some(base) = known.bases.get(&key) {
+            return base.to_string();
+        }
+        let slugged = self.slug_base(room, device);
+        let mut base = slugged.to_string();
+        for n in 2.. {
+            if !known.devices.contains_key(&base) {
+                break;
+            }
+            base = format!("{}_{}", slugged, n);
+        }
+        known.devices.insert(base.to_string(), key.clone());
+        known.bases.insert(key, base.to_string());
+        base
+    }
+
+    fn slug_base(&self, room: &str, device: &str) -> String {
+        format!(
+            "{}/{}/{}/{}",
+            self.config.prefix,
+            slug(&self.config.home),
+            slug(room),
+            slug(device)
+        )
+    }
+
+    pub fn topic(&self, room: &str, device: &str, leaf: &str) -> String {
+        format!("{}/{}", self.device_base(room, device), leaf)
+    }
+
+    // the filter to subscribe to in order to receive every device command
+    pub fn command_filter(&self) -> String {
+        format!(
+            "{}/{}/+/+/{}",
+            self.config.prefix,
+            slug(&self.config.home),
+            COMMAND_LEAF
+        )
+    }
+
+    // Home Assistant discovery payloads for a device, retained so HA picks them up on restart
+    pub fn discovery(&self, room: &str, device: &str, device_type: &str) -> Vec<MqttMessage> {
+        let base = self.register(room, device);
+
+        let object_id = base.replace('/', "_");
+        let ha_device = json!({
+            "identifiers": [object_id],
+            "name": device,
+            "suggested_area": room,
+        });
+        let availability = format!("{}/condition", base);
+
+        let mut components = vec![];
+        match device_type {
+            "PSOC" => {
+                components.push((
+                    "switch",
+                    json!({
+                        "name": format!("{} switch", device),
+                        "command_topic": format!("{}/{}", base, COMMAND_LEAF),
+                        "state_topic": format!("{}/switch", base),
+                        "payload_on": "ON",
+                        "payload_off": "OFF",
+                    }),
+                ));
+                components.push((
+                    "sensor",
+                    json!({
+                        "name": format!("{} power", device),
+                        "state_topic": format!("{}/power", base),
+                        "unit_of_measurement": "W",
+                        "device_class": "power",
+                    }),
+                ));
+            }
+            "THRM" => components.push((
+                "sensor",
+                json!({
+                    "name": format!("{} temperature", device),
+                    "state_topic": format!("{}/temperature", base),
+                    "unit_of_measurement": "°C",
+                    "device_class": "temperature",
+                }),
+            )),
+            _ => {}
+        }
+
+        components
+            .into_iter()
+            .map(|(component, mut payload)| {
+                payload["unique_id"] = json!(format!("{}_{}", object_id, component));
+                payload["device"] = ha_device.clone();
+                payload["availability_topic"] = json!(availability);
+                payload["availability_template"] =
+                    json!("{{ 'online' if value == 'OK' else 'offline' }}");
+                MqttMessage::new(
+                    format!(
+                        "{}/{}/{}/config",
+                        self.config.discovery_prefix, component, object_id
+                    ),
+                    payload.to_string(),
+                    true,
+                )
+            })
+            .collect()
+    }
+
+    // empty retained payloads remove the entities from Home Assistant
+    fn forget(&self, room: &str, device: &str) -> Vec<MqttMessage> {
+        let base = self.device_base(room, device);
+        let mut known = self.known.lock().unwrap();
+        known.devices.remove(&base);
+        known.bases.remove(&(room.to_string(), device.to_string()));
+        drop(known);
+        let object_id = base.replace('/', "_");
+        ["switch", "sensor"]
+            .iter()
+            .map(|component| {
+                MqttMessage::new(
+                    format!(
+                        "{}/{}/{}/config",
+                        self.config.discovery_prefix, component, object_id
+                    ),
+                    String::new(),
+                    true,
+                )
+            })
+            .collect()
+    }
+
+    pub fn event_messages(&self, event: &HomeEvent) -> Vec<MqttMessage> {
+        match event {
+            HomeEvent::RoomAdded { .. }
+            | HomeEvent::RoomRemoved { .. }
+            | HomeEvent::DeviceRefreshed { .. } => vec![],
+            HomeEvent::DeviceAdded {
+                room,
+                device,
+                device_type,
+            } => self.discovery(room, device, device_type),
+            HomeEvent::DeviceRemoved { room, device, .. } => self.forget(room, device),
+            HomeEvent::ReadingChanged {
+                room,
+                device,
+                metric,
+                value,
+                ..
+            } => vec![MqttMessage::new(
+                self.topic(room, device, metric),
+                value.to_string(),
+                false,
+            )],
+            HomeEvent::ConditionChanged {
+                room,
+                device,
+                condition,
+                ..
+            } => vec![MqttMessage::new(
+                self.topic(room, device, "condition"),
+                condition.to_string(),
+                true,
+            )],
+            HomeEvent::PowerSwitched {
+                room,
+                device,
+                is_on,
+                ..
+            } => vec![MqttMessage::new(
+                self.topic(room, device, "switch"),
+                if *is_on { "ON" } else { "OFF" }.to_string(),
+                true,
+            )],
+            HomeEvent::HealthChanged {
+                room,
+                device,
+                health,
+                ..
+            } => vec![MqttMessage::new(
+                self.topic(room, device, "health"),
+                health.to_string(),
+                true,
+            )],
+            HomeEvent::LoadShedding {
+                room, device, shed, ..
+            } => vec![MqttMessage::new(
+                self.topic(room, device, "shed"),
+                if *shed { "ON" } else { "OFF" }.to_string(),
+                true,
+            )],
+        }
+    }
+
+    pub fn parse_command(&self, msg: &MqttMessage) -> Option<MqttCommand> {
+        let base = msg.topic.strip_suffix(&format!("/{}", COMMAND_LEAF))?;
+        let (room, device) = self.known.lock().unwrap().devices.get(base)?.clone();
+        let action = match msg.payload.trim().to_uppercase().as_str() {
+            "ON" | "TURNON" => DeviceAction::TurnOn,
+            "OFF" | "TURNOFF" => DeviceAction::TurnOff,
+            "RESET" => DeviceAction::Reset,
+            _ => return None,
+        };
+        Some(MqttCommand {
+            room,
+            device,
+            action,
+        })
+    }
+
+    // announces devices that were in the home before the bridge was started
+    pub async fn announce(&self, home: &Home) {
+        let mut messages = vec![];
+        for room in home.list_rooms() {
+            for (name, device) in room.named_devices() {
+                let status = device.get_status();
+                messages.extend(self.discovery(&room.name, name, status.device_type()));
+            }
+        }
+        for msg in messages {
+            self.send(msg).await
+        }
+    }
+
+    async fn send(&self, msg: MqttMessage) {
+        if self.outgoing.send(msg).await.is_err() {
+            warn!("mqtt outgoing channel closed")
+        }
+    }
+
+    // forwards every bus event to the broker until the bus is dropped
+    pub fn spawn_publisher(&self, bus: &EventBus) -> JoinHandle<()> {
+        let bridge = self.clone();
+        let mut events = bus.subscribe();
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                for msg in bridge.event_messages(&event) {
+                    bridge.send(msg).await
+                }
+            }
+        })
+    }
+
+    // turns raw incoming messages into commands, ignoring anything unknown
+    pub fn spawn_commands(
+        &self,
+        mut incoming: mpsc::Receiver<MqttMessage>,
+    ) -> mpsc::Receiver<MqttCommand> {
+        let bridge = self.clone();
+        let (tx, rx) = mpsc::channel(64);
+        tokio::spawn(async move {
+            while let Some(msg) = incoming.recv().await {
+                match bridge.parse_command(&msg) {
+                    Some(cmd) => {
+                        if tx.send(cmd).await.is_err() {
+                            break;
+                        }
+                    }
+                    None => debug!(topic = msg.topic.as_str(), "ignoring mqtt message"),
+                }
+            }
+        });
+        rx
+    }
+}
+
+// connects the bridge to a real broker; commands are returned for the home owner to perform
+#[cfg(feature = "mqtt")]
+pub async fn connect(
+    config: MqttConfig,
+    host: &str,
+    port: u16,
+) -> Result<(MqttBridge, mpsc::Receiver<MqttCommand>), rumqttc::ClientError> {
+    use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+    use std::time::Duration;
+
+    let mut options = MqttOptions::new(format!("smart_home_{}", slug(&config.home)), host, port);
+    options.set_keep_alive(Duration::from_secs(30));
+    let (client, mut event_loop) = AsyncClient::new(options, 64);
+
+    let (out_tx, mut out_rx) = mpsc::channel::<MqttMessage>(256);
+    let (in_tx, in_rx) = mpsc::channel::<MqttMessage>(64);
+    let bridge = MqttBridge::new(config, out_tx);
+    client
+        .subscribe(bridge.command_filter(), QoS::AtLeastOnce)
+        .await?;
+
+    let publisher = client.clone();
+    tokio::spawn(async move {
+        while let Some(msg) = out_rx.recv().await {
+            let result = publisher
+                .publish(msg.topic, QoS::AtLeastOnce, msg.retain, msg.payload)
+                .await;
+            if let Err(err) = result {
+                warn!(%err, "mqtt publish failed")
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        loop {
+            match event_loop.poll().await {
+                Ok(Event::Incoming(Packet::Publish(p))) => {
+                    let payload = String::from_utf8_lossy(&p.payload).to_string();
+                    let msg = MqttMessage::new(p.topic, payload, p.retain);
+                    if in_tx.send(msg).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    warn!(%err, "mqtt connection error");
+                    tokio::time::sleep(Duration::from_secs(1)).await
+                }
+            }
+        }
+    });
+
+    let commands = bridge.spawn_commands(in_rx);
+    Ok((bridge, commands))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::power_socket::PowerSocket;
+    use crate::devices::thermometer::Thermometer;
+    use crate::home::Home;
+    use crate::mqtt::{MqttBridge, MqttCommand, MqttConfig, MqttMessage};
+    use s_home_proto::DeviceAction;
+    use tokio::sync::mpsc;
+
+    // the in-process broker stand-in is just the pair of channels
+    fn new_bridge() -> (MqttBridge, mpsc::Receiver<MqttMessage>) {
+        let (tx, rx) = mpsc::channel(64);
+        (MqttBridge::new(MqttConfig::new("Test Home"), tx), rx)
+    }
+
+    #[test]
+    fn test_topics() {
+        let (bridge, _) = new_bridge();
+        assert_eq!(
+            bridge.topic("living room", "socket #1", "power"),
+            "smart_home/test_home/living_room/socket__1/power"
+        );
+        assert_eq!(bridge.command_filter(), "smart_home/test_home/+/+/set");
+    }
+
+    #[test]
+    fn test_discovery() {
+        let (bridge, _) = new_bridge();
+        let messages = bridge.discovery("kitchen", "socket", "PSOC");
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0].topic,
+            "homeassistant/switch/smart_home_test_home_kitchen_socket/config"
+        );
+        assert!(messages.iter().all(|m| m.retain));
+
+        let payload: serde_json::Value = serde_json::from_str(&messages[0].payload).unwrap();
+        assert_eq!(
+            payload["command_topic"],
+            "smart_home/test_home/kitchen/socket/set"
+        );
+    }
+
+    #[test]
+    fn test_slug_collisions() {
+        let (bridge, _) = new_bridge();
+        bridge.discovery("kitchen", "Socket 1", "PSOC");
+        bridge.discovery("kitchen", "socket_1", "PSOC");
+        assert_eq!(
+            bridge.topic("kitchen", "Socket 1", "power"),
+            "smart_home/test_home/kitchen/socket_1/power"
+        );
+        assert_eq!(
+            bridge.topic("kitchen", "socket_1", "power"),
+            "smart_home/test_home/kitchen/socket_1_2/power"
+        );
+
+        let command = |device: &str| MqttMessage {
+            topic: format!("smart_home/test_home/kitchen/{}/set", device),
+            payload: "ON".to_string(),
+            retain: false,
+        };
+        assert_eq!(
+            bridge.parse_command(&command("socket_1_2")).unwrap().device,
+            "socket_1"
+        );
+        assert_eq!(
+            bridge.parse_command(&command("socket_1")).unwrap().device,
+            "Socket 1"
+        );
+
+        // the first one gone, its base is free again
+        let forgotten = bridge.forget("kitchen", "Socket 1");
+        assert!(forgotten[0].topic.ends_with("_kitchen_socket_1/config"));
+        assert!(bridge.parse_command(&command("socket_1")).is_none());
+        bridge.discovery("kitchen", "socket-1", "PSOC");
+        assert_eq!(
+            bridge.parse_command(&command("socket_1")).unwrap().device,
+            "socket-1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bridge() {
+        let mut home = Home::new("Test Home");
+        let (bridge, mut broker) = new_bridge();
+        bridge.spawn_publisher(&home.bus());
+
+        home.add_room("kitchen").unwrap();
+        home.add_device(
+            "kitchen",
+            "socket",
+            Box::new(PowerSocket::new("socket", "")),
+        )
+        .unwrap();
+        home.add_device("kitchen", "thrm", Box::new(Thermometer::new("thrm", "")))
+            .unwrap();
+
+        let mut published = vec![];
+        for _ in 0..3 {
+            published.push(broker.recv().await.unwrap());
+        }
+        assert!(published[2].topic.starts_with("homeassistant/sensor/"));
+
+        let (incoming_tx, incoming_rx) = mpsc::channel(8);
+        let mut commands = bridge.spawn_commands(incoming_rx);
+        for (topic, payload) in [
+            ("smart_home/test_home/kitchen/unknown/set", "ON"),
+            ("smart_home/test_home/kitchen/socket/set", "ON"),
+        ] {
+            let msg = MqttMessage {
+                topic: topic.to_string(),
+                payload: payload.to_string(),
+                retain: false,
+            };
+            incoming_tx.send(msg).await.unwrap();
+        }
+
+        let cmd = commands.recv().await.unwrap();
+        assert_eq!(
+            cmd,
+            MqttCommand {
+                room: "kitchen".to_string(),
+                device: "socket".to_string(),
+                action: DeviceAction::TurnOn,
+            }
+        );
+        home.perform_action(&cmd.room, &cmd.device, cmd.action)
+            .await
+            .unwrap();
+
+        let switched = broker.recv().await.unwrap();
+        assert_eq!(switched.topic, "smart_home/test_home/kitchen/socket/switch");
+        assert_eq!(switched.payload, "ON");
+    }
+}
@@ -0,0 +1,99 @@
+use crate::devices::DeviceStatus;
+use crate::report::ConditionState;
+use std::collections::BTreeSet;
+
+// which devices of a home to pick, every criterion set has to match; nothing set picks all
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceQuery {
+    pub room: Option<String>,
+    // e.g. "PSOC", any case
+    pub device_type: Option<String>,
+    pub tags: Vec<String>,
+    pub condition: Option<ConditionState>,
+}
+
+impl DeviceQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn in_room(mut self, room: &str) -> Self {
+        self.room = Some(room.to_string());
+        self
+    }
+
+    pub fn of_type(mut self, device_type: &str) -> Self {
+        self.device_type = Some(device_type.to_string());
+        self
+    }
+
+    // a group of devices, however many rooms it spans
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    pub fn with_condition(mut self, condition: ConditionState) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    pub(crate) fn matches(
+        &self,
+        room: &str,
+        status: &DeviceStatus,
+        tags: &BTreeSet<String>,
+    ) -> bool {
+        if self.room.as_deref().is_some_and(|r| r != room) {
+            return false;
+        }
+        if let Some(device_type) = &self.device_type {
+            if !status.device_type().eq_ignore_ascii_case(device_type) {
+                return false;
+            }
+        }
+        if !self.tags.iter().all(|tag| tags.contains(tag)) {
+            return false;
+        }
+        match self.condition {
+            Some(condition) => status.report().condition == condition,
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::DeviceStatus;
+    use crate::query::DeviceQuery;
+    use crate::report::ConditionState;
+    use std::collections::BTreeSet;
+    use std::time::Instant;
+
+    #[test]
+    fn test_matches() {
+        let status = DeviceStatus::quick_unknown("lamp", "PSOC").with_readings(&[], Instant::now());
+        let tags: BTreeSet<String> = ["outdoor".to_string(), "lights".to_string()].into();
+
+        assert!(DeviceQuery::new().matches("garden", &status, &tags));
+        let query = DeviceQuery::new()
+            .in_room("garden")
+            .of_type("psoc")
+            .with_tag("outdoor")
+            .with_tag("lights")
+            .with_condition(ConditionState::Ok);
+        assert!(query.matches("garden", &status, &tags));
+
+        assert!(!query.matches("porch", &status, &tags));
+        assert!(!query
+            .clone()
+            .with_tag("xmas")
+            .matches("garden", &status, &tags));
+        assert!(!DeviceQuery::new()
+            .of_type("THRM")
+            .matches("garden", &status, &tags));
+        assert!(!DeviceQuery::new()
+            .with_condition(ConditionState::Error)
+            .matches("garden", &status, &tags));
+    }
+}
@@ -0,0 +1,567 @@
+use crate::devices::{DeviceCondition, DeviceStatus};
+use s_home_proto::metrics::escape_label;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Display, Formatter, Write as _};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// wall-clock time, serialized as RFC 3339 in UTC, e.g. "2024-05-01T12:30:00.250Z"
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp {
+    unix_millis: i64,
+}
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Self::from(SystemTime::now())
+    }
+
+    // when something that happened at `instant` happened by the wall clock
+    pub fn from_instant(instant: Instant) -> Self {
+        Self::from(SystemTime::now() - instant.elapsed())
+    }
+
+    pub fn from_unix_millis(unix_millis: i64) -> Self {
+        Self { unix_millis }
+    }
+
+    pub fn unix_millis(&self) -> i64 {
+        self.unix_millis
+    }
+
+    // zero when the timestamp is in the future
+    pub fn age(&self) -> Duration {
+        let age = Self::now().unix_millis - self.unix_millis;
+        Duration::from_millis(age.max(0) as u64)
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Self {
+        let unix_millis = match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_millis() as i64,
+            Err(err) => -(err.duration().as_millis() as i64),
+        };
+        Self { unix_millis }
+    }
+}
+
+// proleptic Gregorian calendar, see http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let secs = self.unix_millis.div_euclid(1000);
+        let millis = self.unix_millis.rem_euclid(1000);
+        let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+        let secs_of_day = secs.rem_euclid(86400);
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year,
+            month,
+            day,
+            secs_of_day / 3600,
+            secs_of_day % 3600 / 60,
+            secs_of_day % 60,
+            millis
+        )
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = String;
+
+    // only the UTC form `Display` produces, the fraction being optional
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || format!("bad timestamp '{}'", s);
+        let rest = s.strip_suffix('Z').ok_or_else(bad)?;
+        let (date, time) = rest.split_once('T').ok_or_else(bad)?;
+        let (time, millis) = match time.split_once('.') {
+            // anything past milliseconds is cut off
+            Some((time, fraction)) => {
+                digits(fraction).ok_or_else(bad)?;
+                let fraction = format!("{:0<3}", &fraction[..fraction.len().min(3)]);
+                (time, fraction.parse::<i64>().map_err(|_| bad())?)
+            }
+            None => (time, 0),
+        };
+        let date: Vec<_> = date.split('-').map(digits).collect();
+        let time: Vec<_> = time.split(':').map(digits).collect();
+        let [Some(year), Some(month @ 1..=12), Some(day)] = date[..] else {
+            return Err(bad());
+        };
+        let [Some(hour @ 0..=23), Some(minute @ 0..=59), Some(second @ 0..=59)] = time[..] else {
+            return Err(bad());
+        };
+        let year = i64::from(year);
+        if day == 0 || day > days_in_month(year, month) {
+            return Err(bad());
+        }
+        let secs = days_from_civil(year, month, day) * 86400
+            + i64::from(hour * 3600 + minute * 60 + second);
+        Ok(Self::from_unix_millis(secs * 1000 + millis))
+    }
+}
+
+// a field of a timestamp, nothing but ASCII digits
+fn digits(part: &str) -> Option<u32> {
+    if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    part.parse().ok()
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ConditionState {
+    Ok,
+    Error,
+    Unknown,
+}
+
+impl Display for ConditionState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ok => write!(f, "OK"),
+            Self::Error => write!(f, "ERROR"),
+            Self::Unknown => write!(f, "UNKNOWN"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Reading {
+    pub metric: String,
+    pub value: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DeviceReport {
+    pub name: String,
+    pub device_type: String,
+    pub condition: ConditionState,
+    // why the condition is ERROR
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub status: String,
+    pub readings: Vec<Reading>,
+    pub updated: Option<Timestamp>,
+}
+
+impl From<&DeviceStatus> for DeviceReport {
+    fn from(status: &DeviceStatus) -> Self {
+        let (condition, error) = match status.condition() {
+            DeviceCondition::Ok => (ConditionState::Ok, None),
+            DeviceCondition::Err(err) => (ConditionState::Error, Some(err.to_string())),
+            DeviceCondition::Unknown => (ConditionState::Unknown, None),
+            DeviceCondition::Tripped(fault) => {
+                (ConditionState::Error, Some(format!("tripped: {}", fault)))
+            }
+        };
+        Self {
+            name: status.name().to_string(),
+            device_type: status.device_type().to_string(),
+            condition,
+            error,
+            status: status.status().to_string(),
+            readings: status
+                .readings()
+                .iter()
+                .map(|(metric, value)| Reading {
+                    metric: metric.to_string(),
+                    value: *value,
+                })
+                .collect(),
+            updated: status.updated().map(Timestamp::from_instant),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RoomReport {
+    pub name: String,
+    // by name
+    pub devices: Vec<DeviceReport>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HomeReport {
+    pub name: String,
+    pub generated: Timestamp,
+    // by name
+    pub rooms: Vec<RoomReport>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    #[default]
+    Json,
+    // aligned columns for a terminal
+    Table,
+    Markdown,
+    // the text exposition format, readings as gauges
+    Prometheus,
+}
+
+impl Display for ReportFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Json => write!(f, "json"),
+            Self::Table => write!(f, "table"),
+            Self::Markdown => write!(f, "markdown"),
+            Self::Prometheus => write!(f, "prometheus"),
+        }
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "table" => Ok(Self::Table),
+            "markdown" | "md" => Ok(Self::Markdown),
+            "prometheus" | "prom" => Ok(Self::Prometheus),
+            _ => Err(format!("unknown report format '{}'", s)),
+        }
+    }
+}
+
+// one line of a table, markdown or prometheus report
+pub struct Row<'a> {
+    room: Option<&'a str>,
+    device: &'a DeviceReport,
+}
+
+pub trait Report: Serialize {
+    fn rows(&self) -> Vec<Row<'_>>;
+
+    fn render(&self, format: ReportFormat) -> String {
+        match format {
+            // reports hold nothing that could fail to serialize
+            ReportFormat::Json => serde_json::to_string_pretty(self).unwrap(),
+            ReportFormat::Table => render_table(&self.rows()),
+            ReportFormat::Markdown => render_markdown(&self.rows()),
+            ReportFormat::Prometheus => render_prometheus(&self.rows()),
+        }
+    }
+}
+
+impl Report for DeviceReport {
+    fn rows(&self) -> Vec<Row<'_>> {
+        vec![Row {
+            room: None,
+            device: self,
+        }]
+    }
+}
+
+impl Report for RoomReport {
+    fn rows(&self) -> Vec<Row<'_>> {
+        self.devices
+            .iter()
+            .map(|device| Row {
+                room: Some(&self.name),
+                device,
+            })
+            .collect()
+    }
+}
+
+impl Report for HomeReport {
+    fn rows(&self) -> Vec<Row<'_>> {
+        self.rooms.iter().flat_map(RoomReport::rows).collect()
+    }
+}
+
+static COLUMNS: [&str; 6] = ["ROOM", "DEVICE", "TYPE", "CONDITION", "STATUS", "UPDATED"];
+
+fn cells(row: &Row) -> [String; 6] {
+    let device = row.device;
+    let condition = match &device.error {
+        Some(err) => format!("{}: {}", device.condition, err),
+        None => device.condition.to_string(),
+    };
+    [
+        row.room.unwrap_or("-").to_string(),
+        device.name.to_string(),
+        device.device_type.to_string(),
+        condition,
+        device.status.to_string(),
+        device
+            .updated
+            .map_or("never".to_string(), |updated| updated.to_string()),
+    ]
+}
+
+fn render_table(rows: &[Row]) -> String {
+    let rows: Vec<[String; 6]> = rows.iter().map(cells).collect();
+    let mut widths = COLUMNS.map(|column| column.chars().count());
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    let header = COLUMNS.map(str::to_string);
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+fn render_markdown(rows: &[Row]) -> String {
+    let mut out = format!("| {} |\n", COLUMNS.join(" | "));
+    out.push_str(&format!("|{}\n", "---|".repeat(COLUMNS.len())));
+    for row in rows {
+        let row = cells(row).map(|cell| cell.replace('|', "\\|").replace('\n', " "));
+        out.push_str(&format!("| {} |\n", row.join(" | ")));
+    }
+    out
+}
+
+fn render_prometheus(rows: &[Row]) -> String {
+    let mut out = String::new();
+    out.push_str("# HELP smart_home_report_reading Last known reading of a device.\n");
+    out.push_str("# TYPE smart_home_report_reading gauge\n");
+    for row in rows {
+        for reading in row.device.readings.iter() {
+            let _ = writeln!(
+                out,
+                "smart_home_report_reading{{{},metric=\"{}\"}} {}",
+                labels(row),
+                escape_label(&reading.metric),
+                reading.value
+            );
+        }
+    }
+    out.push_str(
+        "# HELP smart_home_report_condition Condition of a device, 1 for the current one.\n",
+    );
+    out.push_str("# TYPE smart_home_report_condition gauge\n");
+    for row in rows {
+        for state in [
+            ConditionState::Ok,
+            ConditionState::Error,
+            ConditionState::Unknown,
+        ] {
+            let _ = writeln!(
+                out,
+                "smart_home_report_condition{{{},state=\"{}\"}} {}",
+                labels(row),
+                state,
+                u8::from(row.device.condition == state)
+            );
+        }
+    }
+    out
+}
+
+fn labels(row: &Row) -> String {
+    format!(
+        "room=\"{}\",device=\"{}\",type=\"{}\"",
+        escape_label(row.room.unwrap_or("")),
+        escape_label(&row.device.name),
+        escape_label(&row.device.device_type)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::report::{
+        ConditionState, DeviceReport, HomeReport, Reading, Report, ReportFormat, RoomReport,
+        Timestamp,
+    };
+
+    fn socket() -> DeviceReport {
+        DeviceReport {
+            name: "kettle".to_string(),
+            device_type: "PSOC".to_string(),
+            condition: ConditionState::Ok,
+            error: None,
+            status: "power: 2200.0 W".to_string(),
+            readings: vec![Reading {
+                metric: "power".to_string(),
+                value: 2200.0,
+            }],
+            updated: Some(Timestamp::from_unix_millis(1_714_566_600_250)),
+        }
+    }
+
+    fn thermometer() -> DeviceReport {
+        DeviceReport {
+            name: "thermometer".to_string(),
+            device_type: "THRM".to_string(),
+            condition: ConditionState::Error,
+            error: Some("timed out".to_string()),
+            status: "UNKNOWN".to_string(),
+            readings: vec![],
+            updated: None,
+        }
+    }
+
+    fn home() -> HomeReport {
+        HomeReport {
+            name: "home".to_string(),
+            generated: Timestamp::from_unix_millis(1_714_566_601_000),
+            rooms: vec![RoomReport {
+                name: "kitchen".to_string(),
+                devices: vec![socket(), thermometer()],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let timestamp = Timestamp::from_unix_millis(1_714_566_600_250);
+        assert_eq!(timestamp.to_string(), "2024-05-01T12:30:00.250Z");
+        assert_eq!("2024-05-01T12:30:00.250Z".parse(), Ok(timestamp));
+        assert_eq!(
+            "2024-05-01T12:30:00Z".parse::<Timestamp>().unwrap(),
+            Timestamp::from_unix_millis(1_714_566_600_000)
+        );
+        assert_eq!(
+            Timestamp::from_unix_millis(0).to_string(),
+            "1970-01-01T00:00:00.000Z"
+        );
+        assert_eq!(
+            Timestamp::from_unix_millis(951_782_400_000).to_string(),
+            "2000-02-29T00:00:00.000Z"
+        );
+        assert!("2024-13-01T00:00:00Z".parse::<Timestamp>().is_err());
+        assert!("yesterday".parse::<Timestamp>().is_err());
+        assert_eq!(
+            "2024-02-29T23:59:59.5Z".parse::<Timestamp>().unwrap(),
+            Timestamp::from_unix_millis(1_709_251_199_500)
+        );
+        assert_eq!(
+            "2024-05-01T12:30:00.250999Z".parse::<Timestamp>().unwrap(),
+            Timestamp::from_unix_millis(1_714_566_600_250)
+        );
+        for bad in [
+            // multibyte fractions used to panic on a char boundary
+            "2024-05-01T12:30:00.ééZ",
+            "2024-05-01T12:30:00.1éZ",
+            "2024-05-01T12:30:00.Z",
+            "2024-05-01T12:30:00.-1Z",
+            "2024-05-01T25:99:99Z",
+            "2024-05-01T24:00:00Z",
+            "2024-05-01T12:60:00Z",
+            "2024-05-01T12:30:60Z",
+            "2024-05-01T12:30:+1Z",
+            "2024-04-31T00:00:00Z",
+            "2023-02-29T00:00:00Z",
+            "1900-02-29T00:00:00Z",
+            "2024-05-00T00:00:00Z",
+            "2024-05-01T12:30Z",
+        ] {
+            assert!(bad.parse::<Timestamp>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let json = home().render(ReportFormat::Json);
+        assert!(
+            json.contains("\"updated\": \"2024-05-01T12:30:00.250Z\""),
+            "{}",
+            json
+        );
+        assert!(json.contains("\"condition\": \"ERROR\""), "{}", json);
+        assert_eq!(serde_json::from_str::<HomeReport>(&json).unwrap(), home());
+    }
+
+    #[test]
+    fn test_table() {
+        assert_eq!(
+            home().render(ReportFormat::Table),
+            "ROOM     DEVICE       TYPE  CONDITION         STATUS           UPDATED\n\
+             kitchen  kettle       PSOC  OK                power: 2200.0 W  2024-05-01T12:30:00.250Z\n\
+             kitchen  thermometer  THRM  ERROR: timed out  UNKNOWN          never\n"
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        let mut device = socket();
+        device.status = "a | b".to_string();
+        assert_eq!(
+            device.render(ReportFormat::Markdown),
+            "| ROOM | DEVICE | TYPE | CONDITION | STATUS | UPDATED |\n\
+             |---|---|---|---|---|---|\n\
+             | - | kettle | PSOC | OK | a \\| b | 2024-05-01T12:30:00.250Z |\n"
+        );
+    }
+
+    #[test]
+    fn test_prometheus() {
+        let out = home().render(ReportFormat::Prometheus);
+        assert!(out.contains(
+            "smart_home_report_reading{room=\"kitchen\",device=\"kettle\",type=\"PSOC\",metric=\"power\"} 2200\n"
+        ), "{}", out);
+        assert!(out.contains(
+            "smart_home_report_condition{room=\"kitchen\",device=\"thermometer\",type=\"THRM\",state=\"ERROR\"} 1\n"
+        ), "{}", out);
+    }
+
+    #[test]
+    fn test_format_from_str() {
+        assert_eq!("md".parse(), Ok(ReportFormat::Markdown));
+        assert_eq!("TABLE".parse(), Ok(ReportFormat::Table));
+        assert!("csv".parse::<ReportFormat>().is_err());
+    }
+}
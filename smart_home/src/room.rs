@@ -1,7 +1,10 @@
 use thiserror::Error;
 
-use crate::devices::Device;
-use std::collections::HashMap;
+use crate::devices::{Device, DeviceReadError};
+use crate::events::{DeviceEvents, EventSink, HomeEvent};
+use crate::report::{DeviceReport, RoomReport};
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
 
 #[derive(Error, Debug)]
 pub enum RoomReadError {
@@ -11,7 +14,7 @@ pub enum RoomReadError {
 
 #[derive(Error, Debug)]
 pub enum RoomUpdateError {
-    #[error("device '{0}' does not exist")]
+    #[error("device '{0}' already exists")]
     DeviceAlreadyExists(String),
     #[error("room does not contain device '{0}'")]
     DeviceDoesNotExist(String),
@@ -20,34 +23,124 @@ pub enum RoomUpdateError {
 pub struct Room {
     pub(crate) name: String,
     devices: HashMap<String, Box<dyn Device>>,
+    // device name -> the groups it is in, e.g. "outdoor"
+    tags: HashMap<String, BTreeSet<String>>,
+    events: EventSink,
 }
 
 impl Room {
     pub fn new(name: &str) -> Self {
+        Self::with_events(name, EventSink::default())
+    }
+
+    pub(crate) fn with_events(name: &str, events: EventSink) -> Self {
         Self {
             name: name.to_string(),
             devices: HashMap::new(),
+            tags: HashMap::new(),
+            events,
         }
     }
+
     pub fn add_device(
         &mut self,
         name: &str,
-        device: Box<dyn Device>,
+        mut device: Box<dyn Device>,
     ) -> Result<(), RoomUpdateError> {
         if self.devices.contains_key(name) {
             Err(RoomUpdateError::DeviceAlreadyExists(name.to_string()))
         } else {
+            device.attach_events(DeviceEvents::new(self.events.clone(), &self.name, name));
+            self.events.publish(HomeEvent::DeviceAdded {
+                room: self.name.to_string(),
+                device: name.to_string(),
+                device_type: device.get_status().device_type().to_string(),
+            });
             self.devices.insert(name.to_string(), device);
             Ok(())
         }
     }
 
     pub fn remove_device(&mut self, name: &str) -> Result<(), RoomUpdateError> {
+        self.take_device(name).map(|_| ())
+    }
+
+    // gives the device its new name, tags and all; nothing changes on errors
+    pub fn rename_device(&mut self, name: &str, new_name: &str) -> Result<(), RoomUpdateError> {
         if !self.devices.contains_key(name) {
-            Err(RoomUpdateError::DeviceAlreadyExists(name.to_string()))
-        } else {
-            self.devices.remove(name);
-            Ok(())
+            return Err(RoomUpdateError::DeviceDoesNotExist(name.to_string()));
+        }
+        if name == new_name {
+            return Ok(());
+        }
+        if self.devices.contains_key(new_name) {
+            return Err(RoomUpdateError::DeviceAlreadyExists(new_name.to_string()));
+        }
+        let (device, tags) = self.take_device(name)?;
+        self.put_device(new_name, device, tags)
+    }
+
+    // removes the device with its tags, e.g. to put it in another room
+    pub(crate) fn take_device(
+        &mut self,
+        name: &str,
+    ) -> Result<(Box<dyn Device>, BTreeSet<String>), RoomUpdateError> {
+        match self.devices.remove(name) {
+            None => Err(RoomUpdateError::DeviceDoesNotExist(name.to_string())),
+            Some(mut device) => {
+                let tags = self.tags.remove(name).unwrap_or_default();
+                device.attach_events(DeviceEvents::default());
+                self.events.publish(HomeEvent::DeviceRemoved {
+                    room: self.name.to_string(),
+                    device: name.to_string(),
+                    device_type: device.get_status().device_type().to_string(),
+                });
+                Ok((device, tags))
+            }
+        }
+    }
+
+    // adds a device taken from somewhere else along with its tags
+    pub(crate) fn put_device(
+        &mut self,
+        name: &str,
+        device: Box<dyn Device>,
+        tags: BTreeSet<String>,
+    ) -> Result<(), RoomUpdateError> {
+        self.add_device(name, device)?;
+        if !tags.is_empty() {
+            self.tags.insert(name.to_string(), tags);
+        }
+        Ok(())
+    }
+
+    // the home keeps rooms by name, it renames them; devices are announced anew under it
+    pub(crate) fn rename(&mut self, name: &str) {
+        for (device_name, device) in self.devices.iter() {
+            self.events.publish(HomeEvent::DeviceRemoved {
+                room: self.name.to_string(),
+                device: device_name.to_string(),
+                device_type: device.get_status().device_type().to_string(),
+            });
+        }
+        self.events.publish(HomeEvent::RoomRemoved {
+            room: self.name.to_string(),
+        });
+        self.name = name.to_string();
+        self.events.publish(HomeEvent::RoomAdded {
+            room: self.name.to_string(),
+        });
+        for (device_name, device) in self.devices.iter_mut() {
+            device.attach_events(DeviceEvents::new(
+                self.events.clone(),
+                &self.name,
+                device_name,
+            ));
+            self.events.publish(HomeEvent::DeviceAdded {
+                room: self.name.to_string(),
+                device: device_name.to_string(),
+                device_type: device.get_status().device_type().to_string(),
+            });
         }
     }
 
@@ -70,6 +163,112 @@ impl Room {
         }
     }
 
+    pub fn get_device_mut(&mut self, name: &str) -> Result<&mut dyn Device, RoomReadError> {
+        Ok(self.device_mut(name)?.as_mut())
+    }
+
+    pub(crate) fn device_mut(&mut self, name: &str) -> Result<&mut Box<dyn Device>, RoomReadError> {
+        self.devices
+            .get_mut(name)
+            .ok_or_else(|| RoomReadError::DeviceDoesNotExist(name.to_string()))
+    }
+
+    pub fn tag_device(&mut self, name: &str, tag: &str) -> Result<(), RoomUpdateError> {
+        if !self.devices.contains_key(name) {
+            return Err(RoomUpdateError::DeviceDoesNotExist(name.to_string()));
+        }
+        self.tags
+            .entry(name.to_string())
+            .or_default()
+            .insert(tag.to_string());
+        Ok(())
+    }
+
+    pub fn untag_device(&mut self, name: &str, tag: &str) -> Result<(), RoomUpdateError> {
+        if !self.devices.contains_key(name) {
+            return Err(RoomUpdateError::DeviceDoesNotExist(name.to_string()));
+        }
+        if let Some(tags) = self.tags.get_mut(name) {
+            tags.remove(tag);
+        }
+        Ok(())
+    }
+
+    // sorted, none for devices the room does not contain
+    pub fn device_tags(&self, name: &str) -> Vec<&str> {
+        self.tags
+            .get(name)
+            .map(|tags| tags.iter().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn tags(&self, name: &str) -> Option<&BTreeSet<String>> {
+        self.tags.get(name)
+    }
+
+    pub(crate) fn named_devices_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (&str, &mut Box<dyn Device>)> {
+        self.devices.iter_mut().map(|(name, d)| (name.as_str(), d))
+    }
+
+    // (name, device) pairs, names being the keys devices were added with
+    pub(crate) fn named_devices(&self) -> impl Iterator<Item = (&str, &dyn Device)> {
+        self.devices
+            .iter()
+            .map(|(name, d)| (name.as_str(), d.as_ref()))
+    }
+
+    // devices are reported under the names they were added with
+    pub fn report(&self) -> RoomReport {
+        let mut devices: Vec<DeviceReport> = self
+            .named_devices()
+            .map(|(name, device)| DeviceReport {
+                name: name.to_string(),
+                ..device.get_status().report()
+            })
+            .collect();
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        RoomReport {
+            name: self.name.to_string(),
+            devices,
+        }
+    }
+
+    // (device name, error) pairs of the devices `filter` picks which could not be read, each
+    // given up on after `timeout`
+    pub(crate) async fn refresh_where(
+        &mut self,
+        filter: impl Fn(&dyn Device) -> bool,
+        timeout: Option<Duration>,
+    ) -> Vec<(String, DeviceReadError)> {
+        let mut failed = vec![];
+        for (name, device) in self.devices.iter_mut() {
+            if !filter(device.as_ref()) {
+                continue;
+            }
+            let refreshed = match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, device.refresh())
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(DeviceReadError::ErrMakingRequest(
+                            "refresh timed out".to_string(),
+                        ))
+                    }),
+                None => device.refresh().await,
+            };
+            match refreshed {
+                Ok(()) => self.events.publish(HomeEvent::DeviceRefreshed {
+                    room: self.name.to_string(),
+                    device: name.to_string(),
+                    device_type: device.get_status().device_type().to_string(),
+                }),
+                Err(err) => failed.push((name.to_string(), err)),
+            }
+        }
+        failed
+    }
+
     pub fn get_summary(&self) -> String {
         let mut out = String::new();
         out.push_str(format!("ROOM '{}' SUMMARY:\n", &self.name).as_str());
@@ -93,7 +292,7 @@ impl Room {
 mod tests {
     use crate::devices::power_socket::PowerSocket;
     use crate::devices::thermometer::Thermometer;
-    use crate::room::Room;
+    use crate::room::{Room, RoomUpdateError};
 
     static POWER_SOCKET: &str = "poser_socket";
     static THERMOMETER: &str = "thermometer";
@@ -140,7 +339,8 @@ mod tests {
     #[test]
     fn test_remove_device() {
         let mut room = new_room();
-        room.add_device(THERMOMETER, Box::new(new_thermometer())).unwrap();
+        room.add_device(THERMOMETER, Box::new(new_thermometer()))
+            .unwrap();
 
         let remove_device_ok = room.remove_device(THERMOMETER);
         if let Err(err) = remove_device_ok {
@@ -156,8 +356,10 @@ mod tests {
     #[test]
     fn test_list_devices() {
         let mut room = new_room();
-        room.add_device(THERMOMETER, Box::new(new_thermometer())).unwrap();
-        room.add_device(POWER_SOCKET, Box::new(new_power_socket())).unwrap();
+        room.add_device(THERMOMETER, Box::new(new_thermometer()))
+            .unwrap();
+        room.add_device(POWER_SOCKET, Box::new(new_power_socket()))
+            .unwrap();
 
         let devices_list = room.list_devices();
         assert_eq!(devices_list.len(), 2)
@@ -166,7 +368,8 @@ mod tests {
     #[test]
     fn test_get_device() {
         let mut room = new_room();
-        room.add_device(THERMOMETER, Box::new(new_thermometer())).unwrap();
+        room.add_device(THERMOMETER, Box::new(new_thermometer()))
+            .unwrap();
 
         let get_device_ok = room.get_device(THERMOMETER);
         match get_device_ok {
@@ -186,7 +389,61 @@ mod tests {
         let mut room = new_room();
         assert_eq!(room.get_summary(), blank_summary);
 
-        room.add_device(THERMOMETER, Box::new(new_thermometer())).unwrap();
+        room.add_device(THERMOMETER, Box::new(new_thermometer()))
+            .unwrap();
         assert_ne!(room.get_summary(), blank_summary);
     }
+
+    #[test]
+    fn test_tags() {
+        let mut room = new_room();
+        room.add_device(POWER_SOCKET, Box::new(new_power_socket()))
+            .unwrap();
+
+        room.tag_device(POWER_SOCKET, "outdoor").unwrap();
+        room.tag_device(POWER_SOCKET, "lights").unwrap();
+        assert!(room.tag_device("missing", "outdoor").is_err());
+        assert_eq!(room.device_tags(POWER_SOCKET), ["lights", "outdoor"]);
+
+        room.untag_device(POWER_SOCKET, "lights").unwrap();
+        assert_eq!(room.device_tags(POWER_SOCKET), ["outdoor"]);
+
+        // added again, it starts over
+        room.remove_device(POWER_SOCKET).unwrap();
+        room.add_device(POWER_SOCKET, Box::new(new_power_socket()))
+            .unwrap();
+        assert!(room.device_tags(POWER_SOCKET).is_empty());
+    }
+
+    #[test]
+    fn test_rename_device() {
+        let mut room = new_room();
+        room.add_device(POWER_SOCKET, Box::new(new_power_socket()))
+            .unwrap();
+        room.add_device(THERMOMETER, Box::new(new_thermometer()))
+            .unwrap();
+        room.tag_device(POWER_SOCKET, "outdoor").unwrap();
+
+        assert!(matches!(
+            room.rename_device(POWER_SOCKET, THERMOMETER),
+            Err(RoomUpdateError::DeviceAlreadyExists(_))
+        ));
+        assert!(matches!(
+            room.rename_device("missing", "lamp"),
+            Err(RoomUpdateError::DeviceDoesNotExist(_))
+        ));
+        assert!(room.get_device(POWER_SOCKET).is_ok());
+
+        room.rename_device(POWER_SOCKET, "lamp").unwrap();
+        assert!(room.get_device(POWER_SOCKET).is_err());
+        assert_eq!(
+            room.get_device_mut("lamp")
+                .unwrap()
+                .get_status()
+                .device_type(),
+            "PSOC"
+        );
+        assert_eq!(room.device_tags("lamp"), ["outdoor"]);
+        assert!(room.device_tags(POWER_SOCKET).is_empty());
+    }
 }
@@ -0,0 +1,32 @@
+// the JSON files alarms and the power budget are kept in across restarts
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+// what an earlier run saved, a fresh start when it saved nothing yet
+pub(crate) fn read<T, E>(path: &Path) -> Result<T, E>
+where
+    T: DeserializeOwned + Default,
+    E: From<std::io::Error> + From<serde_json::Error>,
+{
+    match std::fs::read_to_string(path) {
+        Ok(s) => Ok(serde_json::from_str(&s)?),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// written next to the file and renamed over it, a crash midway leaves the old one intact
+pub(crate) fn write_atomically<E>(path: &Path, value: &impl Serialize) -> Result<(), E>
+where
+    E: From<std::io::Error> + From<serde_json::Error>,
+{
+    let mut tmp = path.to_path_buf().into_os_string();
+    tmp.push(".tmp");
+    let mut file = std::fs::File::create(&tmp)?;
+    file.write_all(serde_json::to_string_pretty(value)?.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use s_home_proto::auth::{Credentials, Keyring, Role};
+use s_home_proto::DeviceAction;
+use smart_home::devices::power_socket::PowerSocket;
+use smart_home::devices::thermometer::Thermometer;
+use smart_home::home::Home;
+use std::thread;
+use std::time::Duration;
+
+static ROOM: &str = "kitchen";
+
+fn keyring() -> Keyring {
+    Keyring::open()
+        .with_key("viewer", "v1ew", Role::ReadOnly)
+        .with_key("home", "h0me", Role::Control)
+}
+
+fn new_home(credentials: Option<Credentials>) -> Home {
+    let mut home = Home::new("test home");
+    if let Some(credentials) = credentials {
+        home = home.with_credentials(credentials);
+    }
+    home.add_room(ROOM).unwrap();
+    home
+}
+
+#[tokio::test]
+async fn test_auth() {
+    let state = power_socket_server::State::new();
+    let metrics = s_home_proto::metrics::ServerMetrics::shared(power_socket_server::SERVER_NAME);
+    thread::spawn(move || {
+        power_socket_server::serve_with_auth(state, 1248, metrics, keyring()).unwrap()
+    });
+    let metrics = s_home_proto::metrics::ServerMetrics::shared(thermometer_server::SERVER_NAME);
+    thread::spawn(move || {
+        thermometer_server::serve_with_auth("127.0.0.1:1249", metrics, keyring()).unwrap()
+    });
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // the home's credentials are handed to its devices
+    let mut home = new_home(Some(Credentials::new("home", "h0me")));
+    let socket = PowerSocket::new("socket", "127.0.0.1:1248");
+    home.add_verified_device(ROOM, "socket", Box::new(socket))
+        .await
+        .unwrap();
+    let thermometer = Thermometer::new("thermometer", "127.0.0.1:1249");
+    home.add_verified_device(ROOM, "thermometer", Box::new(thermometer))
+        .await
+        .unwrap();
+    home.perform_action(ROOM, "socket", DeviceAction::TurnOn)
+        .await
+        .unwrap();
+
+    // unsigned requests are refused
+    let mut home = new_home(None);
+    let err = home
+        .add_verified_device(
+            ROOM,
+            "thermometer",
+            Box::new(Thermometer::new("thermometer", "127.0.0.1:1249")),
+        )
+        .await
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("authentication required"),
+        "{}",
+        err
+    );
+
+    // a read-only key may look but not switch; the device's own credentials win
+    let viewer = PowerSocket::new("socket", "127.0.0.1:1248")
+        .with_credentials(Credentials::new("viewer", "v1ew"));
+    let mut home = new_home(Some(Credentials::new("home", "h0me")));
+    home.add_verified_device(ROOM, "socket", Box::new(viewer))
+        .await
+        .unwrap();
+    let err = home
+        .perform_action(ROOM, "socket", DeviceAction::TurnOff)
+        .await
+        .unwrap_err();
+    assert!(
+        format!("{:?}", err).contains("needs the control role"),
+        "{:?}",
+        err
+    );
+
+    // a wrong secret is no better than none
+    let mut home = new_home(Some(Credentials::new("home", "guess")));
+    let err = home
+        .add_verified_device(
+            ROOM,
+            "socket",
+            Box::new(PowerSocket::new("socket", "127.0.0.1:1248")),
+        )
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("bad signature"), "{}", err);
+}
@@ -0,0 +1,50 @@
+use smart_home::config::HomeConfig;
+use smart_home::dashboard::render_html;
+use smart_home::history::ReadingHistory;
+use smart_home::report::{ConditionState, Report, ReportFormat};
+use std::thread;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_dashboard_with_mock_server() {
+    thread::spawn(|| thermometer_server::serve("127.0.0.1:1261").unwrap());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let config = r#"
+        name = "home"
+        [[room]]
+        name = "hall"
+        [[room.device]]
+        name = "thermometer"
+        kind = "thermometer"
+        dsn = "127.0.0.1:1261"
+    "#;
+    let mut home = HomeConfig::parse(config).unwrap().build().unwrap();
+    let history = ReadingHistory::new();
+    for _ in 0..3 {
+        assert!(home.refresh().await.is_empty());
+        history.sample_home(&home);
+        // readings are cached for half a second
+        tokio::time::sleep(Duration::from_millis(600)).await;
+    }
+    assert_eq!(
+        history.values("hall", "thermometer", "temperature").len(),
+        3
+    );
+
+    let report = home.report();
+    let thermometer = &report.rooms[0].devices[0];
+    assert_eq!(thermometer.condition, ConditionState::Ok);
+    assert!(thermometer.updated.is_some());
+
+    let html = render_html(&report, &history);
+    assert!(
+        html.contains("<span class=\"condition ok\">OK</span>"),
+        "{}",
+        html
+    );
+    assert!(html.contains("<polyline"), "{}", html);
+
+    let table = report.render(ReportFormat::Table);
+    assert!(table.contains("hall  thermometer  THRM"), "{}", table);
+}
@@ -0,0 +1,65 @@
+use s_home_proto::{Codec, DeviceDescription, DeviceRequest, Marshal, Response};
+use smart_home::devices::power_socket::PowerSocket;
+use smart_home::devices::thermometer::Thermometer;
+use smart_home::home::{Home, HomeUpdateError};
+use std::thread;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+static ROOM: &str = "kitchen";
+
+fn new_home() -> Home {
+    let mut home = Home::new("test home");
+    home.add_room(ROOM).unwrap();
+    home
+}
+
+#[tokio::test]
+async fn test_add_verified_devices() {
+    let state = power_socket_server::State::new();
+    thread::spawn(move || power_socket_server::serve(state, 1244).unwrap());
+    thread::spawn(|| thermometer_server::serve("127.0.0.1:1245").unwrap());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let mut home = new_home();
+    let socket = PowerSocket::new("socket", "127.0.0.1:1244");
+    home.add_verified_device(ROOM, "socket", Box::new(socket))
+        .await
+        .unwrap();
+
+    let thermometer = Thermometer::new("thermometer", "127.0.0.1:1245").with_codec(Codec::Cbor);
+    home.add_verified_device(ROOM, "thermometer", Box::new(thermometer))
+        .await
+        .unwrap();
+    assert_eq!(home.get_room(ROOM).unwrap().list_devices().len(), 2);
+}
+
+#[tokio::test]
+async fn test_add_wrong_device() {
+    // a fake UDP device claiming to be a power socket
+    let fake = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = fake.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 512];
+        let (len, peer) = fake.recv_from(&mut buf).await.unwrap();
+        let req = DeviceRequest::unmarshal(std::str::from_utf8(&buf[..len]).unwrap()).unwrap();
+        assert_eq!(req, DeviceRequest::Describe);
+
+        let description = DeviceDescription::new("PSOC", "fake", &["Describe"]);
+        let resp = Response::Description(description).marshal().unwrap();
+        fake.send_to(resp.as_bytes(), peer).await.unwrap();
+    });
+
+    let mut home = new_home();
+    let thermometer = Thermometer::new("thermometer", &addr.to_string());
+    let result = home
+        .add_verified_device(ROOM, "thermometer", Box::new(thermometer))
+        .await;
+    match result {
+        Err(HomeUpdateError::DeviceVerification(err)) => {
+            assert_eq!(err.to_string(), "expected a 'THRM' device, found 'PSOC'")
+        }
+        _ => panic!("verification must fail"),
+    }
+    assert!(home.get_room(ROOM).unwrap().list_devices().is_empty());
+}
@@ -0,0 +1,48 @@
+use smart_home::discovery::{add_discovered, Scanner};
+use smart_home::home::Home;
+use std::thread;
+use std::time::Duration;
+
+static ROOM: &str = "hall";
+
+#[tokio::test]
+async fn test_discover_and_add() {
+    let state = power_socket_server::State::new();
+    thread::spawn(move || power_socket_server::serve(state, 1246).unwrap());
+    thread::spawn(|| thermometer_server::serve("127.0.0.1:1247").unwrap());
+    power_socket_server::announce("lamp", 1246, "127.0.0.1:41301").unwrap();
+    thermometer_server::announce("hall thermometer", 1247, "127.0.0.1:41302").unwrap();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let found = Scanner::new()
+        .with_targets(&["127.0.0.1:41301", "127.0.0.1:41302", "127.0.0.1:41301"])
+        .with_timeout(Duration::from_millis(500))
+        .scan()
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 2);
+    let lamp = found.iter().find(|d| d.name == "lamp").unwrap();
+    assert_eq!(lamp.device_type, "PSOC");
+    assert_eq!(lamp.dsn, "127.0.0.1:1246");
+
+    let mut home = Home::new("test home");
+    home.add_room(ROOM).unwrap();
+    let results = add_discovered(&mut home, ROOM, &found).await;
+    assert!(results.iter().all(|(_, result)| result.is_ok()));
+    assert_eq!(home.get_room(ROOM).unwrap().list_devices().len(), 2);
+
+    // adding the same devices again is reported per device
+    let results = add_discovered(&mut home, ROOM, &found).await;
+    assert!(results.iter().all(|(_, result)| result.is_err()));
+}
+
+#[tokio::test]
+async fn test_scan_nothing() {
+    let found = Scanner::new()
+        .with_targets(&["127.0.0.1:41303"])
+        .with_timeout(Duration::from_millis(200))
+        .scan()
+        .await
+        .unwrap();
+    assert!(found.is_empty());
+}
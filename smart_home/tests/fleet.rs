@@ -0,0 +1,79 @@
+use s_home_proto::auth::{Credentials, Keyring, Role};
+use s_home_proto::fleet::FleetConfig;
+use smart_home::devices::power_socket::PowerSocket;
+use smart_home::devices::thermometer::Thermometer;
+use std::thread;
+use std::time::Duration;
+
+fn config(listen: u16, own_port: u16) -> FleetConfig {
+    FleetConfig::parse(&format!(
+        r#"
+        host = "127.0.0.1"
+        listen = {}
+        [[device]]
+        id = "kettle"
+        port = {}
+        [[device]]
+        id = "toaster"
+        "#,
+        listen, own_port
+    ))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_power_socket_fleet() {
+    let config = config(1252, 1253);
+    let fleet = power_socket_server::Fleet::from_config(&config, power_socket_server::State::new);
+    let metrics = s_home_proto::metrics::ServerMetrics::shared(power_socket_server::SERVER_NAME);
+    tokio::spawn(async move {
+        power_socket_server::serve_fleet(fleet, &config, metrics, Keyring::open())
+            .await
+            .unwrap()
+    });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // by id through the shared port, or on a port of its own without one
+    let mut toaster = PowerSocket::new("toaster", "127.0.0.1:1252").with_device_id("toaster");
+    toaster.power_on().await.unwrap();
+    toaster.get_power_consumption().await.unwrap();
+    let mut kettle = PowerSocket::new("kettle", "127.0.0.1:1253");
+    kettle.power_on().await.unwrap();
+    let mut kettle = PowerSocket::new("kettle", "127.0.0.1:1252").with_device_id("kettle");
+    kettle.power_off().await.unwrap();
+
+    // the shared port cannot guess, and a device's own port serves nobody else
+    let mut anonymous = PowerSocket::new("anonymous", "127.0.0.1:1252");
+    assert!(anonymous.power_on().await.is_err());
+    let mut unknown = PowerSocket::new("lamp", "127.0.0.1:1252").with_device_id("lamp");
+    assert!(unknown.power_on().await.is_err());
+    let mut misrouted = PowerSocket::new("toaster", "127.0.0.1:1253").with_device_id("toaster");
+    assert!(misrouted.power_on().await.is_err());
+}
+
+#[tokio::test]
+async fn test_thermometer_fleet() {
+    let keyring = Keyring::open().with_key("home", "h0me", Role::Control);
+    thread::spawn(move || {
+        let config = config(1254, 1255);
+        let fleet = thermometer_server::Fleet::from_config(&config, thermometer_server::State::new);
+        let metrics = s_home_proto::metrics::ServerMetrics::shared(thermometer_server::SERVER_NAME);
+        thermometer_server::serve_fleet(fleet, &config, metrics, keyring).unwrap()
+    });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // the device id is covered by the signature
+    let credentials = Credentials::new("home", "h0me");
+    let mut toaster = Thermometer::new("toaster", "127.0.0.1:1254")
+        .with_credentials(credentials.clone())
+        .with_device_id("toaster");
+    assert!(toaster.get_temp().await.unwrap().celsius() > 0.0);
+    let mut kettle =
+        Thermometer::new("kettle", "127.0.0.1:1255").with_credentials(credentials.clone());
+    assert!(kettle.get_temp().await.unwrap().celsius() > 0.0);
+
+    let mut unknown = Thermometer::new("lamp", "127.0.0.1:1254")
+        .with_credentials(credentials)
+        .with_device_id("lamp");
+    assert!(unknown.get_temp().await.is_err());
+}
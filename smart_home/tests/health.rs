@@ -0,0 +1,56 @@
+use smart_home::devices::power_socket::PowerSocket;
+use smart_home::devices::thermometer::Thermometer;
+use smart_home::health::{HealthMonitor, HealthState};
+use smart_home::home::Home;
+use std::thread;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_health_with_mock_servers() {
+    let state = power_socket_server::State::new();
+    thread::spawn(move || power_socket_server::serve(state, 1262).unwrap());
+    thread::spawn(|| thermometer_server::serve("127.0.0.1:1263").unwrap());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let mut home = Home::new("home");
+    home.add_room("kitchen").unwrap();
+    home.add_device(
+        "kitchen",
+        "socket",
+        Box::new(PowerSocket::new("socket", "127.0.0.1:1262")),
+    )
+    .unwrap();
+    home.add_device(
+        "kitchen",
+        "thermometer",
+        Box::new(Thermometer::new("thermometer", "127.0.0.1:1263")),
+    )
+    .unwrap();
+    // nothing listens there
+    home.add_device(
+        "kitchen",
+        "gone",
+        Box::new(PowerSocket::new("gone", "127.0.0.1:1264")),
+    )
+    .unwrap();
+
+    let mut monitor = HealthMonitor::new()
+        .with_offline_after(1)
+        .with_timeout(Duration::from_secs(1));
+    let alerts = monitor.check(&mut home).await;
+
+    let state = |device| monitor.health("kitchen", device).unwrap().state;
+    assert_eq!(state("socket"), HealthState::Online);
+    assert_eq!(state("thermometer"), HealthState::Online);
+    assert_eq!(state("gone"), HealthState::Offline);
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].device, "gone");
+
+    // what the pings found shows in the status, though nothing was read yet
+    let condition = |device| {
+        let room = home.get_room("kitchen").unwrap();
+        room.get_device(device).unwrap().get_status().as_string()
+    };
+    assert!(condition("thermometer").contains("condition: OK"));
+    assert!(condition("gone").contains("condition: ERROR"));
+}
@@ -1,4 +1,7 @@
+use s_home_proto::{Codec, Overload};
 use smart_home::devices::power_socket::PowerSocket;
+use smart_home::devices::{Device, DeviceUpdateError};
+use smart_home::report::ConditionState;
 use std::thread;
 use std::time::Duration;
 
@@ -23,10 +26,52 @@ async fn test_power_socket_with_mock_server() {
     tokio::time::sleep(Duration::from_secs(1)).await;
     let power_consumption = device.get_power_consumption().await.unwrap();
     println!("power_consumption = {}", power_consumption);
-    assert_ne!(power_consumption, 0.0);
+    assert_ne!(power_consumption.watts(), 0.0);
 
     tokio::time::sleep(Duration::from_secs(1)).await;
     let new_power_consumption = device.get_power_consumption().await.unwrap();
     println!("new_power_consumption = {}", new_power_consumption);
     assert_ne!(new_power_consumption, power_consumption);
 }
+
+#[tokio::test]
+async fn test_power_socket_with_cbor_codec() {
+    let mut device = PowerSocket::new("test cbor socket", "127.0.0.1:1242").with_codec(Codec::Cbor);
+
+    let state = power_socket_server::State::new();
+    thread::spawn(move || power_socket_server::serve(state, 1242).unwrap());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    device.power_on().await.unwrap();
+    let power_consumption = device.get_power_consumption().await.unwrap();
+    assert_ne!(power_consumption.watts(), 0.0);
+}
+
+#[tokio::test]
+async fn test_power_socket_trips() {
+    let mut device = PowerSocket::new("kettle", "127.0.0.1:1265");
+
+    // the simulated load starts at 20 W
+    let limits = power_socket_server::PowerLimits::new().with_max_power(1.0);
+    let state = power_socket_server::State::with_limits(limits);
+    thread::spawn(move || power_socket_server::serve(state, 1265).unwrap());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    match device.power_on().await {
+        Err(DeviceUpdateError::Tripped(fault)) => assert_eq!(fault.overload, Overload::Power),
+        other => panic!("tripped expected, got {:?}", other.err()),
+    }
+    assert!(device.is_tripped());
+    let report = device.get_status().report();
+    assert_eq!(report.condition, ConditionState::Error);
+    assert!(report.error.unwrap().starts_with("tripped: overload"));
+
+    // still tripped when read again
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert_eq!(device.get_power_consumption().await.unwrap().watts(), 0.0);
+    assert!(device.is_tripped());
+
+    device.reset().await.unwrap();
+    assert!(!device.is_tripped());
+    assert_eq!(device.get_status().report().condition, ConditionState::Ok);
+}
@@ -0,0 +1,47 @@
+use s_home_proto::{Codec, Metric, Unit};
+use smart_home::devices::power_socket::PowerSocket;
+use smart_home::devices::sensor::Sensor;
+use smart_home::devices::Device;
+use std::thread;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_sensor_with_thermometer_server() {
+    thread::spawn(|| thermometer_server::serve("127.0.0.1:1256").unwrap());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let mut device = Sensor::new("living room climate", "127.0.0.1:1256").with_codec(Codec::Cbor);
+    device.verify().await.unwrap();
+
+    let measurements = device.get_measurements().await.unwrap();
+    assert_eq!(measurements.len(), Metric::ALL.len());
+    let humidity = device.get(Metric::Humidity).await.unwrap();
+    assert_eq!(humidity.unit, Unit::Percent);
+
+    let status = device.get_status();
+    assert!(
+        status.as_string().contains("co2: "),
+        "{}",
+        status.as_string()
+    );
+    let metrics: Vec<&str> = status.readings().iter().map(|(m, _)| m.as_str()).collect();
+    assert_eq!(
+        metrics,
+        vec!["temperature", "humidity", "pressure", "co2", "illuminance"]
+    );
+}
+
+#[tokio::test]
+async fn test_sensor_rejects_non_sensors() {
+    let state = power_socket_server::State::new();
+    thread::spawn(move || power_socket_server::serve(state, 1257).unwrap());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // the power socket speaks TCP only, a describe datagram goes unanswered
+    let mut device = Sensor::new("not a sensor", "127.0.0.1:1257");
+    assert!(device.verify().await.is_err());
+    assert!(PowerSocket::new("socket", "127.0.0.1:1257")
+        .verify()
+        .await
+        .is_ok());
+}
@@ -1,4 +1,6 @@
+use s_home_proto::{Calibration, Codec, TemperatureUnit};
 use smart_home::devices::thermometer::Thermometer;
+use smart_home::devices::Device;
 use std::thread;
 use std::time::Duration;
 
@@ -13,10 +15,43 @@ async fn test_thermometer_with_mock_server() {
 
     let temp = device.get_temp().await.unwrap();
     println!("temp = {}", temp);
-    assert_ne!(temp, 20.0, "temp should not be default");
+    assert_ne!(temp.celsius(), 20.0, "temp should not be default");
 
     thread::sleep(Duration::from_secs(1));
     let new_temp = device.get_temp().await.unwrap();
     println!("new_temp = {}", new_temp);
     assert_ne!(new_temp, temp, "temp should change");
 }
+
+#[tokio::test]
+async fn test_thermometer_with_msgpack_codec() {
+    let mut device =
+        Thermometer::new("test msgpack thermometer", "127.0.0.1:1243").with_codec(Codec::MsgPack);
+
+    thread::spawn(|| thermometer_server::serve("127.0.0.1:1243").unwrap());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let temp = device.get_temp().await.unwrap();
+    assert_ne!(temp.celsius(), 0.0);
+}
+
+#[tokio::test]
+async fn test_thermometer_calibration_and_unit() {
+    thread::spawn(|| thermometer_server::serve("127.0.0.1:1258").unwrap());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // the server starts at 20 °C and only warms up slowly
+    let mut device = Thermometer::new("porch", "127.0.0.1:1258")
+        .with_unit(TemperatureUnit::Fahrenheit)
+        .with_calibration(Calibration::offset(-1.5));
+    let temp = device.get_temp().await.unwrap();
+    assert!((18.5..19.5).contains(&temp.celsius()), "{}", temp);
+
+    let status = device.get_status().as_string();
+    assert!(
+        status.contains(&temp.format(TemperatureUnit::Fahrenheit)),
+        "{}",
+        status
+    );
+    assert!(status.contains("°F"), "{}", status);
+}
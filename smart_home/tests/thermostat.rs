@@ -0,0 +1,62 @@
+use s_home_proto::{DeviceRequest, Marshal, Response, Temperature};
+use smart_home::devices::power_socket::PowerSocket;
+use smart_home::devices::thermometer::Thermometer;
+use smart_home::devices::thermostat::{Thermostat, ThermostatMode};
+use smart_home::devices::Device;
+use std::io::{Read, Write};
+use std::thread;
+use std::time::Duration;
+
+fn socket_is_on(addr: &str) -> bool {
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(DeviceRequest::Status.marshal().unwrap().as_bytes())
+        .unwrap();
+    let mut buf = String::new();
+    stream.read_to_string(&mut buf).unwrap();
+    match Response::unmarshal(buf.as_str()).unwrap() {
+        Response::Status(is_on) => is_on,
+        resp => panic!("unexpected response: {:?}", resp),
+    }
+}
+
+#[tokio::test]
+async fn test_thermostat_switches_heater() {
+    let state = power_socket_server::State::new();
+    thread::spawn(move || power_socket_server::serve(state, 1259).unwrap());
+    thread::spawn(|| thermometer_server::serve("127.0.0.1:1260").unwrap());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // the server starts at 20 °C and only warms up slowly
+    let thermometer = Thermometer::new("thermometer", "127.0.0.1:1260");
+    let heater = PowerSocket::new("heater", "127.0.0.1:1259");
+    let mut thermostat = Thermostat::new("thermostat", thermometer, vec![heater])
+        .with_setpoint(Temperature::from_celsius(25.0))
+        .with_interval(Duration::from_millis(100));
+    thermostat.verify().await.unwrap();
+
+    thermostat.start();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(thermostat.is_running());
+    assert!(socket_is_on("127.0.0.1:1259"));
+    let status = thermostat.get_status().as_string();
+    assert!(
+        status.contains("mode: heat, setpoint: 25.0 °C"),
+        "{}",
+        status
+    );
+    assert!(status.contains("heating"), "{}", status);
+
+    // with the room well above the setpoint the heater goes off
+    thermostat.set_setpoint(Temperature::from_celsius(10.0));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(!socket_is_on("127.0.0.1:1259"));
+
+    thermostat.set_mode(ThermostatMode::Cool);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(socket_is_on("127.0.0.1:1259"));
+    assert!(thermostat.get_status().as_string().contains("cooling"));
+
+    thermostat.stop();
+    assert!(!thermostat.is_running());
+}
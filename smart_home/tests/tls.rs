@@ -0,0 +1,94 @@
+use s_home_proto::auth::Keyring;
+use s_home_proto::metrics::ServerMetrics;
+use s_home_proto::tls::testing::generate_pems;
+use s_home_proto::tls::TlsConfig;
+use s_home_proto::DeviceAction;
+use smart_home::devices::power_socket::PowerSocket;
+use smart_home::home::Home;
+use std::thread;
+use std::time::Duration;
+
+static ROOM: &str = "kitchen";
+
+fn start_server(port: u32, tls: TlsConfig) {
+    let state = power_socket_server::State::new();
+    thread::spawn(move || {
+        power_socket_server::serve_with_tls(
+            state,
+            port,
+            ServerMetrics::shared(power_socket_server::SERVER_NAME),
+            Keyring::open(),
+            &tls,
+        )
+        .unwrap()
+    });
+}
+
+fn new_home() -> Home {
+    let mut home = Home::new("test home");
+    home.add_room(ROOM).unwrap();
+    home
+}
+
+#[tokio::test]
+async fn test_tls() {
+    let pems = generate_pems("server_only");
+    start_server(
+        1250,
+        TlsConfig::new().with_identity(&pems.server.0, &pems.server.1),
+    );
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let mut home = new_home();
+    let socket = PowerSocket::new("socket", "127.0.0.1:1250")
+        .with_tls(&TlsConfig::new().with_ca(&pems.ca))
+        .unwrap();
+    home.add_verified_device(ROOM, "socket", Box::new(socket))
+        .await
+        .unwrap();
+    home.perform_action(ROOM, "socket", DeviceAction::TurnOn)
+        .await
+        .unwrap();
+
+    // plain requests do not get through, and the server keeps serving
+    let plain = PowerSocket::new("plain", "127.0.0.1:1250");
+    assert!(home
+        .add_verified_device(ROOM, "plain", Box::new(plain))
+        .await
+        .is_err());
+    home.perform_action(ROOM, "socket", DeviceAction::TurnOff)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_mutual_tls() {
+    let pems = generate_pems("mutual");
+    start_server(
+        1251,
+        TlsConfig::new()
+            .with_identity(&pems.server.0, &pems.server.1)
+            .with_ca(&pems.ca),
+    );
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let mut home = new_home();
+    let client = TlsConfig::new()
+        .with_ca(&pems.ca)
+        .with_identity(&pems.client.0, &pems.client.1);
+    let socket = PowerSocket::new("socket", "127.0.0.1:1251")
+        .with_tls(&client)
+        .unwrap();
+    home.add_verified_device(ROOM, "socket", Box::new(socket))
+        .await
+        .unwrap();
+
+    // a controller without a certificate is turned away
+    let anonymous = PowerSocket::new("anonymous", "127.0.0.1:1251")
+        .with_tls(&TlsConfig::new().with_ca(&pems.ca))
+        .unwrap();
+    assert!(home
+        .add_verified_device(ROOM, "anonymous", Box::new(anonymous))
+        .await
+        .is_err());
+}
@@ -1,66 +1,189 @@
 use rand::Rng;
-use s_home_proto::{DeviceAction, DeviceRequest, Marshal, Response};
-use std::net::UdpSocket;
+use s_home_proto::auth::Keyring;
+use s_home_proto::discovery::{spawn_responder, DeviceAnnouncement};
+use s_home_proto::fleet::{FleetConfig, Route};
+use s_home_proto::metrics::ServerMetrics;
+use s_home_proto::server::{serve_udp, Endpoint, Shutdown};
+use s_home_proto::{DeviceAction, DeviceDescription, DeviceRequest, Measurement, Metric, Response};
 use std::sync::{Arc, Mutex};
-use std::thread::{sleep, spawn};
+use std::thread::{sleep, spawn, JoinHandle};
 use std::time::Duration;
+use tokio::net::UdpSocket;
+use tracing::{info, trace};
 
-struct State {
+static DEVICE_TYPE: &str = "THRM";
+// how its metrics are labelled
+pub static SERVER_NAME: &str = "thermometer";
+static FIRMWARE: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+static SUPPORTED: [&str; 6] = [
+    "Ping",
+    "Status",
+    "DeviceAction",
+    "GetTemperature",
+    "GetMeasurements",
+    "Describe",
+];
+
+// answers discovery probes on `discovery_addr` on behalf of the device served on `port`
+pub fn announce(name: &str, port: u16, discovery_addr: &str) -> std::io::Result<JoinHandle<()>> {
+    spawn_responder(
+        discovery_addr,
+        DeviceAnnouncement::new(DEVICE_TYPE, name, port),
+    )
+}
+
+// a thermometer measuring the rest of the room's climate as well
+pub struct State {
     is_on: bool,
     temp: f32,
+    humidity: f32,
+    pressure: f32,
+    co2: f32,
+    illuminance: f32,
 }
 
+impl State {
+    pub fn new() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            is_on: true,
+            temp: 20.0,
+            humidity: 45.0,
+            pressure: 1013.25,
+            co2: 600.0,
+            illuminance: 300.0,
+        }))
+    }
+
+    fn drift(&mut self, rng: &mut impl Rng) {
+        self.temp += rng.gen_range(0.005..0.05);
+        self.humidity = (self.humidity + rng.gen_range(-0.5..0.5)).clamp(0.0, 100.0);
+        self.pressure += rng.gen_range(-0.3..0.3);
+        // never below what is outside
+        self.co2 = (self.co2 + rng.gen_range(-10.0..10.0)).max(400.0);
+        self.illuminance = (self.illuminance + rng.gen_range(-20.0..20.0)).max(0.0);
+    }
+
+    fn measurements(&self) -> Vec<Measurement> {
+        vec![
+            Measurement::new(Metric::Temperature, self.temp),
+            Measurement::new(Metric::Humidity, self.humidity),
+            Measurement::new(Metric::Pressure, self.pressure),
+            Measurement::new(Metric::Co2, self.co2),
+            Measurement::new(Metric::Illuminance, self.illuminance),
+        ]
+    }
+}
+
+// thermometers hosted by one process, addressed by device id
+pub type Fleet = s_home_proto::fleet::Fleet<State>;
+
 pub fn serve(addr: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let state = State {
-        is_on: true,
-        temp: 20.0,
-    };
-    let arc = Arc::new(Mutex::new(state));
-    let arc_clone = Arc::clone(&arc);
+    serve_with_metrics(addr, ServerMetrics::shared(SERVER_NAME))
+}
+
+pub fn serve_with_metrics(
+    addr: &str,
+    metrics: Arc<ServerMetrics>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    serve_with_auth(addr, metrics, Keyring::open())
+}
+
+// like `serve_with_metrics`, but every datagram must be signed by a key from the keyring
+pub fn serve_with_auth(
+    addr: &str,
+    metrics: Arc<ServerMetrics>,
+    keyring: Keyring,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let route = Route::single(State::new());
+    spawn_drift(Arc::clone(route.fleet()));
 
+    runtime()?.block_on(async {
+        let socket = UdpSocket::bind(addr).await?;
+        let endpoint = Endpoint::new(route, respond, metrics, Arc::new(keyring));
+        serve_udp(socket, endpoint, Shutdown::new()).await
+    })?;
+    Ok(())
+}
+
+// serves every thermometer of the fleet on the ports the config lists
+pub fn serve_fleet(
+    fleet: Fleet,
+    config: &FleetConfig,
+    metrics: Arc<ServerMetrics>,
+    keyring: Keyring,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let fleet = Arc::new(fleet);
+    let keyring = Arc::new(keyring);
+    runtime()?.block_on(async {
+        // all bound before any is served, so a taken port fails the whole fleet
+        let mut sockets = vec![];
+        for (route, addr) in config.routes(&fleet) {
+            sockets.push((route, UdpSocket::bind(&addr).await?));
+        }
+        info!(
+            devices = fleet.len(),
+            sockets = sockets.len(),
+            auth = !keyring.is_open(),
+            "fleet up"
+        );
+
+        spawn_drift(Arc::clone(&fleet));
+        let shutdown = Shutdown::new();
+        let mut servers = tokio::task::JoinSet::new();
+        for (route, socket) in sockets {
+            let endpoint =
+                Endpoint::new(route, respond, Arc::clone(&metrics), Arc::clone(&keyring));
+            servers.spawn(serve_udp(socket, endpoint, shutdown.clone()));
+        }
+        while let Some(joined) = servers.join_next().await {
+            joined.map_err(std::io::Error::other)??;
+        }
+        Ok::<_, std::io::Error>(())
+    })?;
+    Ok(())
+}
+
+fn runtime() -> std::io::Result<tokio::runtime::Runtime> {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+}
+
+// every reading of every hosted thermometer moves a little every second, the temperature only up
+fn spawn_drift(fleet: Arc<Fleet>) -> JoinHandle<()> {
     spawn(move || {
         let mut rng = rand::thread_rng();
 
         loop {
-            let mut state = arc_clone.lock().unwrap();
-            state.temp += rng.gen_range(0.005..0.05);
-            println!("[SERVER] temperature changed: new value {}", state.temp);
-            drop(state);
+            for state in fleet.states() {
+                let mut state = state.lock().unwrap();
+                state.drift(&mut rng);
+                trace!(temperature = state.temp, "temperature changed");
+            }
             sleep(Duration::from_secs(1))
         }
-    });
-
-    let socket = UdpSocket::bind(addr).unwrap();
-    let mut buf = [0; 1024];
-
-    loop {
-        match socket.recv_from(&mut buf) {
-            Ok((recv, addr)) => {
-                let s = String::from_utf8_lossy(&buf[..recv]).to_string();
-                println!("[SERVER] received {} bytes", recv);
-                println!("[SERVER] message = {}", s);
-
-                let mut state = arc.lock().unwrap();
-
-                let req =
-                    DeviceRequest::unmarshal(s.as_str()).expect("err unmarshalling device request");
-                let resp = match req {
-                    DeviceRequest::Ping => Response::Pong,
-                    DeviceRequest::Status => Response::Status(state.is_on),
-                    DeviceRequest::DeviceAction { method } => {
-                        match method {
-                            DeviceAction::TurnOff => state.is_on = false,
-                            DeviceAction::TurnOn => state.is_on = true,
-                        };
-                        Response::Ok
-                    }
-                    DeviceRequest::GetTemperature => Response::Temperature(state.temp),
-                    _ => Response::Err(format!("bad request: {:?}", req)),
-                };
-                let message = resp.marshal().unwrap();
-                socket.send_to(message.as_bytes(), addr).unwrap();
-            }
-            Err(e) => println!("err receiving a datagram: {}", &e),
+    })
+}
+
+fn respond(req: DeviceRequest, state: &Mutex<State>) -> Response {
+    let mut state = state.lock().unwrap();
+    match req {
+        DeviceRequest::Ping => Response::Pong,
+        DeviceRequest::Status => Response::Status(state.is_on),
+        DeviceRequest::DeviceAction { method } => {
+            match method {
+                DeviceAction::TurnOff => state.is_on = false,
+                DeviceAction::TurnOn => state.is_on = true,
+                // nothing latches on a thermometer
+                DeviceAction::Reset => {}
+            };
+            Response::Ok
+        }
+        DeviceRequest::GetTemperature => Response::Temperature(state.temp),
+        DeviceRequest::GetMeasurements => Response::Measurements(state.measurements()),
+        DeviceRequest::Describe => {
+            Response::Description(DeviceDescription::new(DEVICE_TYPE, FIRMWARE, &SUPPORTED))
         }
+        _ => Response::Err(format!("bad request: {:?}", req)),
     }
 }
@@ -1,5 +1,35 @@
-use thermometer_server::serve;
+use s_home_proto::auth::Keyring;
+use s_home_proto::fleet::FleetConfig;
+use s_home_proto::metrics::{serve_server_metrics, ServerMetrics};
+use thermometer_server::{announce, serve_fleet, serve_with_auth, Fleet, State};
 
 fn main() {
-    serve("127.0.0.1:12345").unwrap();
+    s_home_proto::logging::init();
+
+    // e.g. AUTH_KEYS=home:s3cret:control,ops:t0p:admin; without keys nothing is checked
+    let keyring = Keyring::from_spec(&std::env::var("AUTH_KEYS").unwrap_or_default()).unwrap();
+
+    let metrics = ServerMetrics::shared(thermometer_server::SERVER_NAME);
+    // the /metrics endpoint is off unless an address is given
+    if let Ok(addr) = std::env::var("METRICS_ADDR") {
+        serve_server_metrics(&addr, metrics.clone()).unwrap();
+    }
+
+    // a whole fleet of thermometers in this one process, see `FleetConfig` for the format
+    if let Ok(path) = std::env::var("FLEET_CONFIG") {
+        let config = FleetConfig::load(&path).unwrap();
+        let fleet = Fleet::from_config(&config, State::new);
+        serve_fleet(fleet, &config, metrics, keyring).unwrap();
+        return;
+    }
+
+    // discovery is off unless an address is given, e.g. 0.0.0.0:41234
+    if let Ok(addr) = std::env::var("DISCOVERY_ADDR") {
+        let name = std::env::var("DEVICE_NAME").unwrap_or_else(|_| "thermometer".to_string());
+        if let Err(err) = announce(&name, 12345, &addr) {
+            tracing::warn!(%err, address = addr.as_str(), "discovery responder not started");
+        }
+    }
+
+    serve_with_auth("127.0.0.1:12345", metrics, keyring).unwrap();
 }
@@ -1,8 +1,9 @@
-use s_home_proto::{DeviceRequest, Marshal, Response};
+use s_home_proto::metrics::ServerMetrics;
+use s_home_proto::{DeviceRequest, Marshal, Metric, Response};
 use std::net::UdpSocket;
 use std::thread;
 use std::time::Duration;
-use thermometer_server::serve;
+use thermometer_server::{serve, serve_with_metrics};
 
 fn quick_sleep(secs: u64) {
     thread::sleep(Duration::from_secs(secs));
@@ -56,4 +57,37 @@ fn test_server() {
         }
         _ => panic!("unexpected response: {:?}", second_temp_resp),
     }
+    // the rest of the room's climate, each in its own unit
+    match send_and_get(DeviceRequest::GetMeasurements) {
+        Response::Measurements(measurements) => {
+            let metrics: Vec<Metric> = measurements.iter().map(|m| m.metric).collect();
+            assert_eq!(metrics, Metric::ALL);
+            assert!(measurements.iter().all(|m| m.unit == m.metric.unit()));
+        }
+        resp => panic!("unexpected response: {:?}", resp),
+    }
+}
+
+#[test]
+fn test_metrics() {
+    let addr = "127.0.0.1:12346";
+    let metrics = ServerMetrics::shared(thermometer_server::SERVER_NAME);
+    let server_metrics = metrics.clone();
+    thread::spawn(move || serve_with_metrics(addr, server_metrics).unwrap());
+    quick_sleep(1);
+
+    let cli_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    cli_socket.connect(addr).unwrap();
+    for req in [DeviceRequest::Ping, DeviceRequest::GetPower] {
+        cli_socket.send(req.marshal().unwrap().as_bytes()).unwrap();
+        let mut buf = [0u8; 512];
+        cli_socket.recv(&mut buf).unwrap();
+    }
+
+    assert_eq!(metrics.requests("Ping"), 1);
+    assert_eq!(metrics.requests("GetPower"), 1);
+    assert_eq!(metrics.errors(), 1);
+    assert!(metrics
+        .render()
+        .contains("device_request_errors_total{server=\"thermometer\"} 1"));
 }